@@ -0,0 +1,187 @@
+//! Rules as they were before the grid kept per-digit bitboards and before the set rules searched subsets by
+//! their span, to compare against the current ones.
+
+use sum_sequence_sudoku_solver::engine_v2::{Contradiction, Entry, Mask, Pos, Rule, Sudoku, Value};
+
+/// Hidden singles found by decoding the candidates of every cell of the unit, once per digit.
+pub struct CellHiddenSingles {
+    pub units: Vec<Vec<Pos>>,
+}
+
+impl Rule for CellHiddenSingles {
+    fn name(&self) -> &'static str {
+        "hidden-singles"
+    }
+
+    fn priority(&self) -> u8 {
+        1
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.units.clone()
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        for digit in 1..=sudoku.size() as u8 {
+            let mut places = cells.iter().filter(|&&pos| sudoku.candidates(pos).contains(digit));
+
+            match (places.next(), places.next()) {
+                (None, _) => {
+                    let reason = format!("digit {} has no place", digit);
+                    return Err(Contradiction::new(self.name(), cells.to_vec(), reason));
+                }
+
+                (Some(&pos), None) if !matches!(sudoku.entry(pos), Entry::Digit(_)) => sudoku.set(pos, digit.into()),
+
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Naked pairs, triples and quads found by trying every combination of two to four cells of the unit,
+/// as the set rules did before they searched subsets by their span.
+pub struct NakedSets {
+    pub units: Vec<Vec<Pos>>,
+}
+
+impl Rule for NakedSets {
+    fn name(&self) -> &'static str {
+        "naked sets"
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.units.clone()
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let size = sudoku.size();
+        let mut allowed_digits = Mask::full(size);
+        let mut pencilmarks: Vec<(Pos, Mask)> = Vec::new();
+        let mut empty_cells: Vec<Pos> = Vec::new();
+
+        for &pos in cells {
+            match sudoku.entry(pos) {
+                Entry::Empty => empty_cells.push(pos),
+                Entry::Digit(digit) if !allowed_digits[digit] => {
+                    let first = cells.iter().copied().find(|&other| sudoku[other] == digit.get() as Value).unwrap();
+                    let reason = format!("digit {} appears twice", digit);
+                    return Err(Contradiction::new(self.name(), vec![first, pos], reason));
+                }
+                Entry::Digit(digit) => allowed_digits.set_digit(digit, false),
+                Entry::Pencil(pm) => pencilmarks.push((pos, pm)),
+            }
+        }
+
+        if pencilmarks.is_empty() && empty_cells.is_empty() {
+            return Ok(());
+        }
+
+        for pm in &mut pencilmarks {
+            for i in 0..size {
+                if !allowed_digits[i] {
+                    pm.1.set_bit(i, false);
+                }
+            }
+        }
+
+        for cell in empty_cells.iter() {
+            pencilmarks.push((*cell, allowed_digits));
+        }
+
+        let naked_sets = find_naked_sets(&pencilmarks, size);
+
+        for (i, naked_cells) in naked_sets.into_iter().enumerate() {
+            let Some(naked_cells) = naked_cells else {
+                continue;
+            };
+
+            for (pos, pm) in pencilmarks.iter_mut() {
+                if !naked_cells.contains(pos) && pm[i] {
+                    pm.set_bit(i, false);
+                }
+            }
+        }
+
+        for (pos, pm) in pencilmarks.iter_mut() {
+            let new_value = match pm.count() {
+                0 => return Err(Contradiction::new(self.name(), vec![*pos], "no candidates left")),
+                1 => pm.digits().next().unwrap() as Value,
+                _ => {
+                    let pm_value = Value::from(*pm);
+
+                    if pm_value != sudoku[*pos] {
+                        pm_value
+                    } else {
+                        continue;
+                    }
+                }
+            };
+
+            sudoku.set(*pos, new_value);
+        }
+
+        Ok(())
+    }
+}
+
+/// For each digit, the cells of the last naked pair, triple or quad holding it.
+fn find_naked_sets(marks: &[(Pos, Mask)], size: usize) -> Vec<Option<Vec<Pos>>> {
+    let mut result = vec![None; size];
+
+    fn check_combination(marks: &[(Pos, Mask)], indices: &[usize], result: &mut [Option<Vec<Pos>>]) {
+        let mut digits = vec![false; result.len()];
+        let mut count = 0;
+
+        for &idx in indices {
+            for (i, digit) in digits.iter_mut().enumerate() {
+                if !*digit && marks[idx].1[i] {
+                    *digit = true;
+                    count += 1;
+                }
+            }
+        }
+
+        if count == indices.len() {
+            let positions: Vec<Pos> = indices.iter().map(|&idx| marks[idx].0).collect();
+            for (i, &digit) in digits.iter().enumerate() {
+                if digit {
+                    result[i] = Some(positions.clone());
+                }
+            }
+        }
+    }
+
+    let len = marks.len();
+
+    // Naked quads
+    for i in 0..len {
+        for j in (i + 1)..len {
+            for k in (j + 1)..len {
+                for l in (k + 1)..len {
+                    check_combination(marks, &[i, j, k, l], &mut result);
+                }
+            }
+        }
+    }
+
+    // Naked triplets
+    for i in 0..len {
+        for j in (i + 1)..len {
+            for k in (j + 1)..len {
+                check_combination(marks, &[i, j, k], &mut result);
+            }
+        }
+    }
+
+    // Naked pairs
+    for i in 0..len {
+        for j in (i + 1)..len {
+            check_combination(marks, &[i, j], &mut result);
+        }
+    }
+
+    result
+}
@@ -0,0 +1,114 @@
+mod baseline;
+
+use baseline::{CellHiddenSingles, NakedSets};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use sum_sequence_sudoku_solver::engine_v2::rules::HiddenSinglesRule;
+use sum_sequence_sudoku_solver::engine_v2::{Pos, Registry, Rule, RuleSpec, Ruleset, Solver, Sudoku};
+
+/// The puzzle of `engine_v2::tests::test_engine`.
+fn puzzle() -> Sudoku {
+    Sudoku::from([
+        [0, 6, 0, 8, 0, 0, 0, 0, 0],
+        [4, 0, 0, 0, 0, 5, 0, 8, 0],
+        [0, 3, 7, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 4, 0, 9, 7, 3, 0, 0],
+        [0, 0, 0, 0, 5, 3, 0, 0, 0],
+        [7, 0, 0, 0, 0, 1, 4, 6, 0],
+        [5, 9, 0, 0, 0, 4, 7, 3, 0],
+        [0, 0, 1, 0, 7, 0, 0, 0, 5],
+    ])
+}
+
+fn solver(rules: &str) -> Solver {
+    let specs: Vec<RuleSpec> = rules.split(',').map(|spec| spec.parse().unwrap()).collect();
+    Registry::standard().build(&specs, &puzzle(), &Ruleset::default()).unwrap()
+}
+
+fn solve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solve");
+
+    for rules in ["row,column,box", "row,column,box,hidden-singles", "row,column,box,hidden-singles,fish"] {
+        let solver = solver(rules);
+        group.bench_function(rules, |b| b.iter(|| solver.solve(black_box(puzzle()))));
+    }
+
+    group.finish();
+}
+
+/// The grid as the standard rules leave it before any candidate test, with its boards built.
+fn stuck() -> Sudoku {
+    let (_, result) = solver("row,column,box").explain(puzzle());
+    let mut stuck = result.1;
+    stuck.boards();
+    stuck
+}
+
+/// Updates every unit of the rule once on a copy of the grid.
+fn pass(rule: &dyn Rule, units: &[Vec<Pos>], grid: &Sudoku) -> Sudoku {
+    let mut sudoku = grid.clone();
+    for (unit, cells) in units.iter().enumerate() {
+        let _ = rule.update_unit(unit, cells, &mut sudoku);
+    }
+    sudoku
+}
+
+/// Rule updates alone, on the grid as the standard rules leave it before any candidate test.
+fn rules(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rules");
+    let stuck = stuck();
+
+    for name in ["box", "hidden-singles", "fish"] {
+        let solver = solver(name);
+        let rule = &solver.rules()[0];
+        let units = rule.units(&stuck);
+
+        group.bench_function(name, |b| b.iter(|| pass(rule.as_ref(), &units, &stuck)));
+    }
+
+    group.finish();
+}
+
+/// Hidden singles read from the digit bitboards, against decoding the candidates of every cell as before them.
+fn representation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("representation");
+    let units = HiddenSinglesRule::default().units(&puzzle());
+
+    let boards = solver("row,column,box,hidden-singles");
+    let cells = solver("row,column,box").with_rule(CellHiddenSingles { units: units.clone() });
+    group.bench_function("solve/boards", |b| b.iter(|| boards.solve(black_box(puzzle()))));
+    group.bench_function("solve/cells", |b| b.iter(|| cells.solve(black_box(puzzle()))));
+
+    let stuck = stuck();
+    let (boards, cells) = (HiddenSinglesRule::default(), CellHiddenSingles { units: units.clone() });
+    group.bench_function("hidden-singles/boards", |b| b.iter(|| pass(&boards, &units, &stuck)));
+    group.bench_function("hidden-singles/cells", |b| b.iter(|| pass(&cells, &units, &stuck)));
+
+    group.finish();
+}
+
+/// Subsets searched by their span, against trying every combination of two to four cells as before.
+fn subsets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("subsets");
+    let puzzle = puzzle();
+    let units: Vec<Vec<Pos>> =
+        ["row", "column", "box"].into_iter().flat_map(|name| solver(name).rules()[0].units(&puzzle)).collect();
+
+    let spans = solver("row,column,box");
+    let combinations = Solver::new().with_rule(NakedSets { units: units.clone() });
+    group.bench_function("solve/spans", |b| b.iter(|| spans.solve(black_box(puzzle.clone()))));
+    group.bench_function("solve/combinations", |b| b.iter(|| combinations.solve(black_box(puzzle.clone()))));
+
+    let stuck = stuck();
+    let solver = solver("box");
+    let (spans, boxes) = (&solver.rules()[0], solver.rules()[0].units(&stuck));
+    let combinations = NakedSets { units: boxes.clone() };
+    group.bench_function("box/spans", |b| b.iter(|| pass(spans.as_ref(), &boxes, &stuck)));
+    group.bench_function("box/combinations", |b| b.iter(|| pass(&combinations, &boxes, &stuck)));
+
+    group.finish();
+}
+
+criterion_group!(benches, solve, rules, representation, subsets);
+criterion_main!(benches);
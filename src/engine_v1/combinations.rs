@@ -1,21 +1,16 @@
-use super::Pair;
+use super::{Pair, Params, pair_sums};
 
-pub fn compute_combinations(sequence: &[u16; 12], pair_sums: &[Vec<Pair>]) -> Vec<[Pair; 12]> {
-    let pair_options: [&Vec<Pair>; 12] = sequence
-        .iter()
-        .map(|&sum_value| &pair_sums[sum_value as usize - 4])
-        .collect::<Vec<&Vec<Pair>>>()
-        .try_into()
-        .unwrap();
+pub fn compute_combinations(params: &Params, sequence: &[u16]) -> Vec<Vec<Pair>> {
+    let pair_options: Vec<Vec<Pair>> = sequence.iter().map(|&sum_value| pair_sums(sum_value)).collect();
 
     let mut all_solutions = Vec::new();
     let mut selected_pairs = Vec::new();
-    find_all_valid_combinations(&pair_options, 0, &mut selected_pairs, &mut all_solutions);
+    find_all_valid_combinations(params, &pair_options, 0, &mut selected_pairs, &mut all_solutions);
 
     let mut results = Vec::new();
 
     for pairs in all_solutions {
-        let splits = split_pairs_evenly(pairs);
+        let splits = split_pairs_evenly(params, &pairs);
         results.extend(splits);
     }
 
@@ -23,27 +18,28 @@ pub fn compute_combinations(sequence: &[u16; 12], pair_sums: &[Vec<Pair>]) -> Ve
 }
 
 fn find_all_valid_combinations(
-    pair_options: &[&Vec<Pair>; 12],
+    params: &Params,
+    pair_options: &[Vec<Pair>],
     index: usize,
     selected_pairs: &mut Vec<Pair>,
-    all_solutions: &mut Vec<[Pair; 12]>,
+    all_solutions: &mut Vec<Vec<Pair>>,
 ) {
     if index == pair_options.len() {
-        if check_frequency_constraint(selected_pairs) {
-            let value = selected_pairs.clone().try_into().unwrap();
-            all_solutions.push(value);
+        if check_frequency_constraint(params, selected_pairs) {
+            all_solutions.push(selected_pairs.clone());
         }
         return;
     }
 
-    for &pair in pair_options[index] {
+    for &pair in &pair_options[index] {
         selected_pairs.push(pair);
-        find_all_valid_combinations(pair_options, index + 1, selected_pairs, all_solutions);
+        find_all_valid_combinations(params, pair_options, index + 1, selected_pairs, all_solutions);
         selected_pairs.pop();
     }
 }
 
-fn check_frequency_constraint(pairs: &[Pair]) -> bool {
+/// Every digit appears in the pairs as often as outside the triplet cages, the palindrome digits twice more.
+fn check_frequency_constraint(params: &Params, pairs: &[Pair]) -> bool {
     let mut digit_count = [0; 9];
 
     for &[a, b] in pairs {
@@ -51,32 +47,44 @@ fn check_frequency_constraint(pairs: &[Pair]) -> bool {
         digit_count[b as usize - 1] += 1;
     }
 
+    let base = params.pair_occurrences();
     let mut dup_sum = 0;
-    let mut count_2 = 0;
+    let mut dup_count = 0;
 
-    for (i, count) in digit_count.iter().enumerate() {
+    for (i, &count) in digit_count.iter().enumerate() {
         match count {
-            4 => dup_sum += i + 1,
-            2 => count_2 += 1,
+            count if count == base + 2 => {
+                dup_sum += i as u16 + 1;
+                dup_count += 1;
+            }
+            count if count == base => {}
             _ => return false,
         }
     }
 
-    count_2 == 6 && dup_sum == 15
+    dup_count == params.palindrome_digits() && 2 * dup_sum == params.palindrome_total
 }
 
-pub fn split_pairs_evenly(pairs: [Pair; 12]) -> Vec<[Pair; 12]> {
+/// Splits the pairs into two groups of `params.split` and the rest, each holding every digit in proportion to its size.
+/// When both groups are the same size, the first pair stays in the first one.
+pub fn split_pairs_evenly(params: &Params, pairs: &[Pair]) -> Vec<Vec<Pair>> {
+    // Every pair in the first group is the only split, and trivially even.
+    if params.split == pairs.len() {
+        return vec![pairs.to_vec()];
+    }
+
     let mut results = Vec::new();
+    let symmetric = !pairs.is_empty() && 2 * params.split == pairs.len();
 
-    for mask in 0u32..(1u32 << 12) {
-        if mask.count_ones() == 6 && (mask & 1) == 1 {
-            let group1 = (0..12).filter(|&i| (mask & (1 << i)) != 0).map(|i| pairs[i]);
-            let group2 = (0..12).filter(|&i| (mask & (1 << i)) == 0).map(|i| pairs[i]);
+    for mask in 0u32..(1u32 << pairs.len()) {
+        if mask.count_ones() as usize == params.split && (!symmetric || (mask & 1) == 1) {
+            let group1 = (0..pairs.len()).filter(|&i| (mask & (1 << i)) != 0).map(|i| pairs[i]);
+            let group2 = (0..pairs.len()).filter(|&i| (mask & (1 << i)) == 0).map(|i| pairs[i]);
 
-            let res: [Pair; 12] = group1.chain(group2).collect::<Vec<Pair>>().try_into().unwrap();
+            let res: Vec<Pair> = group1.chain(group2).collect();
 
-            if check_group_constraint(res[0..6].try_into().unwrap())
-                && check_group_constraint(res[6..12].try_into().unwrap())
+            if check_group_constraint(&res[0..params.split], pairs)
+                && check_group_constraint(&res[params.split..], pairs)
             {
                 results.push(res);
             }
@@ -86,26 +94,21 @@ pub fn split_pairs_evenly(pairs: [Pair; 12]) -> Vec<[Pair; 12]> {
     results
 }
 
-fn check_group_constraint(pairs: [Pair; 6]) -> bool {
-    let mut digit_count = [0; 9];
-
-    for [a, b] in pairs {
-        digit_count[a as usize - 1] += 1;
-        digit_count[b as usize - 1] += 1;
-    }
-
-    let mut count_2 = 0;
-    let mut count_1 = 0;
+/// The group holds every digit of the pairs in proportion to its size.
+fn check_group_constraint(group: &[Pair], pairs: &[Pair]) -> bool {
+    let count = |pairs: &[Pair]| {
+        let mut digit_count = [0; 9];
 
-    for &count in &digit_count[0..9] {
-        match count {
-            2 => count_2 += 1,
-            1 => count_1 += 1,
-            _ => return false,
+        for [a, b] in pairs {
+            digit_count[*a as usize - 1] += 1;
+            digit_count[*b as usize - 1] += 1;
         }
-    }
 
-    count_2 == 3 && count_1 == 6
+        digit_count
+    };
+
+    let (group_count, total_count) = (count(group), count(pairs));
+    (0..9).all(|digit| group_count[digit] * pairs.len() == total_count[digit] * group.len())
 }
 
 #[cfg(test)]
@@ -117,7 +120,18 @@ mod tests {
         // 14 15 25 26 37 38 48 58 59 69 79 89
         let pairs = [[1, 4], [1, 5], [2, 5], [2, 6], [3, 7], [3, 8], [4, 8], [5, 8], [5, 9], [6, 9], [7, 9], [8, 9]];
 
-        let splits = split_pairs_evenly(pairs);
+        let splits = split_pairs_evenly(&Params::default(), &pairs);
         assert!(!splits.is_empty());
     }
+
+    #[test]
+    fn test_split_trivially() {
+        let pairs = [[1, 4], [2, 5]];
+
+        let params = Params { split: 2, ..Params::default() };
+        assert_eq!(split_pairs_evenly(&params, &pairs), [pairs.to_vec()]);
+
+        let params = Params { split: 0, ..Params::default() };
+        assert_eq!(split_pairs_evenly(&params, &[]), [Vec::<Pair>::new()]);
+    }
 }
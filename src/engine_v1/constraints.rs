@@ -1,70 +1,141 @@
-use super::Pair;
+use super::{Pair, Params};
+use crate::engine_v2::rules::{PalindromeRule, Rule, TableRule};
+use crate::engine_v2::{Pos, Sudoku, Value};
 
+/// The cell of every position, numbered as in `engine_v1.md`: pair `i` of a solution fills positions `2i` and `2i + 1`.
 #[rustfmt::skip]
-const CONSTRAINT_MASKS: [u32; 24] = [
-    1<<2  | 1<<4  | 1<<6  | 1<<7  | 1<<12 | 1<<13,
-    1<<3  | 1<<5  | 1<<6  | 1<<7  | 1<<12 | 1<<13,
-    1<<0  | 1<<4  | 1<<5  | 1<<8  | 1<<9  | 1<<17,
-    1<<1  | 1<<5  | 1<<8  | 1<<9  | 1<<13 | 1<<17 | 1<<20 | 1<<21,
-    1<<0  | 1<<2  | 1<<10 | 1<<11 | 1<<18 | 1<<19,
-    1<<1  | 1<<2  | 1<<3  | 1<<6  | 1<<7  | 1<<8  | 1<<9  | 1<<10 | 1<<11 | 1<<12 | 1<<13 | 1<<15 | 1<<18 | 1<<19 | 1<<20 | 1<<22 | 1<<23,
-    1<<0  | 1<<1  | 1<<8  | 1<<10 | 1<<16 | 1<<17,
-    1<<0  | 1<<1  | 1<<9  | 1<<11 | 1<<16 | 1<<17,
-    1<<2  | 1<<3  | 1<<6  | 1<<10 | 1<<14 | 1<<15 | 1<<18 | 1<<22,
-    1<<2  | 1<<3  | 1<<7  | 1<<11,
-    1<<4  | 1<<5  | 1<<6  | 1<<8  | 1<<22 | 1<<23,
-    1<<4  | 1<<5  | 1<<7  | 1<<9  | 1<<22 | 1<<23,
-    1<<0  | 1<<1  | 1<<14 | 1<<16 | 1<<18 | 1<<19,
-    1<<0  | 1<<1  | 1<<15 | 1<<17 | 1<<18 | 1<<19,
-    1<<12 | 1<<16 | 1<<20 | 1<<21,
-    1<<1  | 1<<5  | 1<<8  | 1<<9  | 1<<13 | 1<<17 | 1<<20 | 1<<21,
-    1<<6  | 1<<7  | 1<<12 | 1<<14 | 1<<22 | 1<<23,
-    1<<1  | 1<<2  | 1<<3  | 1<<6  | 1<<7  | 1<<8  | 1<<9  | 1<<10 | 1<<11 | 1<<12 | 1<<13 | 1<<15 | 1<<18 | 1<<19 | 1<<20 | 1<<22 | 1<<23,
-    1<<4  | 1<<5  | 1<<12 | 1<<13 | 1<<20 | 1<<22,
-    1<<4  | 1<<5  | 1<<12 | 1<<13 | 1<<21 | 1<<23,
-    1<<2  | 1<<3  | 1<<6  | 1<<10 | 1<<14 | 1<<15 | 1<<18 | 1<<22,
-    1<<14 | 1<<15 | 1<<19 | 1<<23,
-    1<<10 | 1<<11 | 1<<16 | 1<<17 | 1<<18 | 1<<20,
-    1<<10 | 1<<11 | 1<<16 | 1<<17 | 1<<19 | 1<<21,
+pub const CELLS: [(usize, usize); 24] = [
+    (0, 2), (1, 2), (0, 4), (1, 4), (0, 6), (1, 6),
+    (7, 2), (8, 2), (7, 4), (8, 4), (7, 6), (8, 6),
+    (2, 0), (2, 1), (4, 0), (4, 1), (6, 0), (6, 1),
+    (2, 7), (2, 8), (4, 7), (4, 8), (6, 7), (6, 8),
 ];
 
-pub fn fill_constraints(set: &[Pair; 12], dups: [u16; 3], results: &mut Vec<[[u16; 2]; 12]>) {
-    fill_constraints_internal([[0, 0]; 12], 0, set, dups, results);
+/// A layout of pair cages as engine v1 sees it, on a 9x9 grid with 3x3 boxes: the cell of every position,
+/// and the cells holding the same digit, such as the mirrored cells of a palindrome.
+pub struct Layout {
+    cells: Vec<Pos>,
+    /// The positions holding a different digit from each position, as they share a row, column or box with it
+    /// or with a cell holding the same digit.
+    masks: Vec<u32>,
+    /// The other positions holding the same digit as each position, which must be a duplicated digit.
+    twins: Vec<u32>,
+}
+
+impl Layout {
+    /// A layout with the cell of every position, pair `i` filling positions `2i` and `2i + 1`,
+    /// and pairs of cells holding the same digit, which don't need to be cage cells.
+    pub fn new(cells: Vec<Pos>, links: &[(Pos, Pos)]) -> Self {
+        assert!(cells.len() <= 32, "A layout has at most 32 positions");
+
+        // The cells holding the same digit as each cell, by merging the linked ones.
+        let mut groups: Vec<Vec<Pos>> = (0..81).map(|i| vec![(i / 9, i % 9)]).collect();
+        let mut group: Vec<usize> = (0..81).collect();
+
+        for &((r1, c1), (r2, c2)) in links {
+            let (a, b) = (group[r1 * 9 + c1], group[r2 * 9 + c2]);
+
+            if a != b {
+                let merged = std::mem::take(&mut groups[b]);
+                merged.iter().for_each(|&(row, col)| group[row * 9 + col] = a);
+                groups[a].extend(merged);
+            }
+        }
+
+        let cells_of = |(row, col): Pos| &groups[group[row * 9 + col]];
+        let sees = |(r1, c1): Pos, (r2, c2): Pos| r1 == r2 || c1 == c2 || (r1 / 3, c1 / 3) == (r2 / 3, c2 / 3);
+
+        let masks = (0..cells.len())
+            .map(|i| {
+                let others = (0..cells.len()).filter(|&j| !cells_of(cells[i]).contains(&cells[j]));
+                others
+                    .filter(|&j| cells_of(cells[i]).iter().any(|&a| cells_of(cells[j]).iter().any(|&b| sees(a, b))))
+                    .fold(0, |mask, j| mask | 1 << j)
+            })
+            .collect();
+
+        let twins = (0..cells.len())
+            .map(|i| {
+                let twins = (0..cells.len()).filter(|&j| j != i && cells_of(cells[i]).contains(&cells[j]));
+                twins.fold(0, |mask, j| mask | 1 << j)
+            })
+            .collect();
+
+        Layout { cells, masks, twins }
+    }
+
+    pub fn cells(&self) -> &[Pos] {
+        &self.cells
+    }
+}
+
+/// The sum-sequence layout of `engine_v1.md`, linked by the palindromes of `PalindromeRule`.
+impl Default for Layout {
+    fn default() -> Self {
+        let palindromes = PalindromeRule::default().units(&Sudoku::default());
+        Layout::new(CELLS.to_vec(), &palindrome_links(&CELLS, &palindromes))
+    }
+}
+
+/// The mirrored cells of every palindrome, and the two cage cells on palindromes left without a mirror among the
+/// cage cells: the cage cells on palindromes hold each of their digits twice, so those two hold the same one.
+pub fn palindrome_links(cells: &[Pos], palindromes: &[Vec<Pos>]) -> Vec<(Pos, Pos)> {
+    let mut links: Vec<(Pos, Pos)> =
+        palindromes.iter().flat_map(|cells| cells.iter().copied().zip(cells.iter().rev().copied())).collect();
+
+    let on_palindrome = |pos: &Pos| palindromes.iter().any(|palindrome| palindrome.contains(pos));
+    let mirrored = |pos: Pos| links.iter().any(|&(a, b)| a == pos && a != b && cells.contains(&b));
+    let unpaired: Vec<Pos> = cells.iter().copied().filter(on_palindrome).filter(|&pos| !mirrored(pos)).collect();
+
+    if let [a, b] = unpaired[..] {
+        links.push((a, b));
+    }
+
+    links
+}
+
+/// Places the pairs of a split sequence on the layout, the pairs of the first group on its first positions.
+pub fn fill_constraints(params: &Params, layout: &Layout, set: &[Pair], dups: &[u16], results: &mut Vec<Vec<Pair>>) {
+    assert_eq!(layout.cells.len(), 2 * set.len(), "The layout doesn't have a position for every pair cage cell");
+    fill_constraints_internal(params, layout, vec![[0, 0]; set.len()], 0, set, dups, results);
 }
 
 fn fill_constraints_internal(
-    current: [[u16; 2]; 12],
+    params: &Params,
+    layout: &Layout,
+    current: Vec<[u16; 2]>,
     index: usize,
     pairs: &[Pair],
-    dups: [u16; 3],
-    results: &mut Vec<[[u16; 2]; 12]>,
+    dups: &[u16],
+    results: &mut Vec<Vec<Pair>>,
 ) {
-    let constraint_a = get_constraint(&current, dups, index * 2);
-    let constraint_b = get_constraint(&current, dups, index * 2 + 1);
+    let constraint_a = get_constraint(layout, &current, dups, index * 2);
+    let constraint_b = get_constraint(layout, &current, dups, index * 2 + 1);
 
     let [a, b] = current[index];
     let mask_a = extract_forbidden_digit_mask(a);
     let mask_b = extract_forbidden_digit_mask(b);
 
-    let available_pairs = if index < 6 { &pairs[0..pairs.len() - 6] } else { pairs };
+    let second = current.len() - params.split;
+    let available_pairs = if index < params.split { &pairs[0..pairs.len() - second] } else { pairs };
 
     for (i, [x, y]) in available_pairs.iter().enumerate() {
         for [&a, &b] in [[x, y], [y, x]] {
             if is_pair_valid_for_position(a, b, constraint_a, constraint_b, mask_a, mask_b) {
-                let mut new_current = current;
+                let mut new_current = current.clone();
                 new_current[index] = [a, b];
 
-                if index == 11 {
+                if index == current.len() - 1 {
                     results.push(new_current);
                     continue;
                 }
 
-                apply_constraints(&mut new_current, index, a, b);
+                apply_constraints(layout, &mut new_current, index, a, b);
 
                 let mut remaining_pairs = pairs.to_vec();
                 remaining_pairs.remove(i);
 
-                fill_constraints_internal(new_current, index + 1, &remaining_pairs, dups, results);
+                fill_constraints_internal(params, layout, new_current, index + 1, &remaining_pairs, dups, results);
             }
         }
     }
@@ -92,12 +163,12 @@ fn is_digit_forbidden(digit: u16, forbidden_mask: u16) -> bool {
     forbidden_mask != 0 && (forbidden_mask & (1 << digit)) != 0
 }
 
-fn apply_constraints(current: &mut [[u16; 2]; 12], index: usize, digit_a: u16, digit_b: u16) {
-    apply_digit_constraints_to_positions(current, CONSTRAINT_MASKS[index * 2], digit_a);
-    apply_digit_constraints_to_positions(current, CONSTRAINT_MASKS[index * 2 + 1], digit_b);
+fn apply_constraints(layout: &Layout, current: &mut [[u16; 2]], index: usize, digit_a: u16, digit_b: u16) {
+    apply_digit_constraints_to_positions(current, layout.masks[index * 2], digit_a);
+    apply_digit_constraints_to_positions(current, layout.masks[index * 2 + 1], digit_b);
 }
 
-fn apply_digit_constraints_to_positions(current: &mut [[u16; 2]; 12], mut position_mask: u32, forbidden_digit: u16) {
+fn apply_digit_constraints_to_positions(current: &mut [[u16; 2]], mut position_mask: u32, forbidden_digit: u16) {
     while position_mask != 0 {
         let position = position_mask.trailing_zeros() as usize;
         position_mask &= position_mask - 1;
@@ -123,51 +194,67 @@ fn extract_forbidden_digit_mask(constraint_value: u16) -> u16 {
     if constraint_value & FORBIDDEN_FLAG != 0 { constraint_value & DIGIT_MASK } else { 0 }
 }
 
-const INDEXES: [usize; 6] = [3, 5, 8, 15, 17, 20];
+/// A rule allowing only the given solutions on the cells of the layout, bridging engine v1 to engine v2.
+pub fn solution_table(layout: &Layout, solutions: &[Vec<Pair>]) -> TableRule {
+    let cells = layout.cells();
+    let tuples: Vec<Vec<u8>> = solutions
+        .iter()
+        .inspect(|solution| assert_eq!(2 * solution.len(), cells.len(), "The solution doesn't fit the layout"))
+        .map(|solution| solution.iter().flatten().map(|&digit| digit as u8).collect())
+        .collect();
+    TableRule::new(cells.to_vec(), &tuples)
+}
+
+/// Writes the digits of a solution on the cells of the layout.
+/// Fails with the first cell that can't hold its digit, such as one holding a different given.
+pub fn place(layout: &Layout, solution: &[Pair], sudoku: &mut Sudoku) -> Result<(), Pos> {
+    let cells = layout.cells();
+    assert_eq!(2 * solution.len(), cells.len(), "The solution doesn't fit the layout");
 
-fn get_constraint(current: &[[u16; 2]; 12], dups: [u16; 3], index: usize) -> u16 {
-    if INDEXES.contains(&index) {
-        let y = (index + 12) % 24;
-        let (div, rem) = (y / 2, y % 2);
+    for (&pos, &digit) in cells.iter().zip(solution.iter().flatten()) {
+        if !sudoku.candidates(pos).contains(digit as u8) {
+            return Err(pos);
+        }
+
+        sudoku[pos] = Value::from(digit);
+    }
+
+    Ok(())
+}
 
-        let constraint_value = current[div][rem];
+fn get_constraint(layout: &Layout, current: &[[u16; 2]], dups: &[u16], index: usize) -> u16 {
+    if layout.twins[index] != 0 {
+        let placed = positions(layout.twins[index])
+            .map(|twin| current[twin / 2][twin % 2])
+            .find(|&value| value > 0 && value & FORBIDDEN_FLAG == 0);
 
-        if constraint_value > 0 && constraint_value & FORBIDDEN_FLAG == 0 {
-            1 << constraint_value
-        } else {
-            dups.iter().fold(0u16, |mask, &digit| mask | (1 << digit))
+        match placed {
+            Some(digit) => 1 << digit,
+            None => dups.iter().fold(0u16, |mask, &digit| mask | (1 << digit)),
         }
     } else {
         0
     }
 }
 
+/// The positions of a mask, in increasing order.
+fn positions(mask: u32) -> impl Iterator<Item = usize> {
+    (0..32).filter(move |position| mask & (1 << position) != 0)
+}
+
 #[allow(dead_code)]
-fn check_constraints(solution: &[[u16; 2]; 12], dups: [u16; 3]) -> bool {
+fn check_constraints(layout: &Layout, solution: &[[u16; 2]], dups: &[u16]) -> bool {
     let get_digit = |index: usize| -> u16 { solution[index / 2][index % 2] };
 
-    let (a1, b1, c1) = (get_digit(INDEXES[0]), get_digit(INDEXES[1]), get_digit(INDEXES[2]));
-    let (a2, b2, c2) = (get_digit(INDEXES[3]), get_digit(INDEXES[4]), get_digit(INDEXES[5]));
-
-    if a1 != a2 || b1 != b2 || c1 != c2 {
-        return false;
-    }
-
-    if a1 == b1 || b1 == c1 || c1 == a1 {
-        return false;
-    }
-
-    if !dups.contains(&a1) || !dups.contains(&b1) || !dups.contains(&c1) {
-        return false;
+    for (i, &twins) in layout.twins.iter().enumerate().filter(|(_, twins)| **twins != 0) {
+        if !dups.contains(&get_digit(i)) || positions(twins).any(|j| get_digit(j) != get_digit(i)) {
+            return false;
+        }
     }
 
-    for (i, &constraint_mask) in CONSTRAINT_MASKS.iter().enumerate() {
-        let digit_i = get_digit(i);
-
-        for j in 0..24 {
-            if (constraint_mask & (1 << j)) != 0 && get_digit(j) == digit_i {
-                return false;
-            }
+    for (i, &constraint_mask) in layout.masks.iter().enumerate() {
+        if positions(constraint_mask).any(|j| get_digit(j) == get_digit(i)) {
+            return false;
         }
     }
     true
@@ -176,6 +263,8 @@ fn check_constraints(solution: &[[u16; 2]; 12], dups: [u16; 3]) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine_v2::Mask;
+
     #[test]
     fn test_setup() {
         let mut results = vec![];
@@ -183,18 +272,80 @@ mod tests {
         let dups = [1, 6, 8];
         let set = [[1, 3], [1, 6], [2, 7], [4, 8], [5, 8], [6, 9], [1, 4], [1, 5], [2, 6], [3, 8], [6, 8], [7, 9]];
 
-        fill_constraints(&set, dups, &mut results);
+        let layout = Layout::default();
+        fill_constraints(&Params::default(), &layout, &set, &dups, &mut results);
 
         assert!(!results.is_empty());
         for res in results {
-            assert!(check_constraints(&res, dups));
+            assert!(check_constraints(&layout, &res, &dups));
         }
     }
 
+    #[test]
+    fn test_layout() {
+        let layout = Layout::default();
+
+        // r1c3 sees r1c5, r1c7, r8c3, r9c3, r3c1 and r3c2, and shares its cage with r2c3.
+        assert_eq!(layout.masks[0], 1 << 1 | 1 << 2 | 1 << 4 | 1 << 6 | 1 << 7 | 1 << 12 | 1 << 13);
+        // r1c5 also sees r3c6 in its box, mirroring r2c7, and r4c5 in its column, mirroring r7c2.
+        assert!(layout.masks[2] & (1 << 5 | 1 << 17) == 1 << 5 | 1 << 17);
+
+        let twins: Vec<(usize, u32)> = layout.twins.iter().copied().enumerate().filter(|&(_, t)| t != 0).collect();
+        assert_eq!(twins, [(3, 1 << 15), (5, 1 << 17), (8, 1 << 20), (15, 1 << 3), (17, 1 << 5), (20, 1 << 8)]);
+    }
+
+    #[test]
+    fn test_palindrome_links() {
+        let palindromes = PalindromeRule::default().units(&Sudoku::default());
+        let links = palindrome_links(&CELLS, &palindromes);
+
+        // r2c7 on the second palindrome and r7c2 on the third are the cage cells without a mirror among them.
+        assert_eq!(links.last(), Some(&(CELLS[5], CELLS[17])));
+        assert_eq!(links.len(), palindromes.iter().map(Vec::len).sum::<usize>() + 1);
+
+        let mut cells = CELLS;
+        cells[5] = (0, 5);
+        assert!(palindrome_links(&cells, &palindromes).iter().all(|&link| link != (CELLS[5], CELLS[17])));
+    }
+
+    #[test]
+    fn test_solution_table() {
+        let solution = [[6, 1], [4, 8], [9, 6], [5, 8], [1, 3], [2, 7], [7, 9], [6, 8], [2, 6], [4, 1], [1, 5], [3, 8]];
+        let mut swapped = solution;
+        swapped[0] = [1, 6];
+
+        let rule = solution_table(&Layout::default(), &[solution.to_vec(), swapped.to_vec()]);
+        let mut sudoku = Sudoku::default();
+        assert!(rule.update_unit(0, &CELLS, &mut sudoku).is_ok());
+
+        assert_eq!(sudoku.candidates(CELLS[0]), Mask::from_digits([1, 6]));
+        assert_eq!(sudoku[CELLS[2]], Mask::from_digits([4]).into());
+    }
+
+    #[test]
+    fn test_place() {
+        let solution = [[6, 1], [4, 8], [9, 6], [5, 8], [1, 3], [2, 7], [7, 9], [6, 8], [2, 6], [4, 1], [1, 5], [3, 8]];
+        let layout = Layout::default();
+        let mut sudoku = Sudoku::default();
+
+        assert_eq!(place(&layout, &solution, &mut sudoku), Ok(()));
+        assert_eq!(sudoku[(1, 4)], 8);
+        assert_eq!(sudoku[(4, 1)], 8);
+
+        let mut sudoku = Sudoku::default();
+        sudoku[(0, 6)] = 2;
+        assert_eq!(place(&layout, &solution, &mut sudoku), Err((0, 6)));
+
+        let layout = Layout::new(vec![(4, 4), (8, 8)], &[]);
+        let mut sudoku = Sudoku::default();
+        assert_eq!(place(&layout, &[[3, 7]], &mut sudoku), Ok(()));
+        assert_eq!((sudoku[(4, 4)], sudoku[(8, 8)]), (3, 7));
+    }
+
     #[test]
     fn test_constraint() {
         let solution = [[6, 1], [4, 8], [9, 6], [5, 8], [1, 3], [2, 7], [7, 9], [6, 8], [2, 6], [4, 1], [1, 5], [3, 8]];
 
-        assert!(check_constraints(&solution, [1, 6, 8]));
+        assert!(check_constraints(&Layout::default(), &solution, &[1, 6, 8]));
     }
 }
@@ -0,0 +1,132 @@
+use super::{Params, get_combinations, get_pairs_sequence, get_triplet_map, palindrome_digits};
+
+/// A sequence start allowed by the algebra of the readme, with the palindrome total it needs.
+#[derive(Debug, PartialEq)]
+pub struct Feasible {
+    pub start: u16,
+    /// The total of the cage cells inside palindromes, X, twice the total of the palindrome digits.
+    pub palindrome_total: u16,
+    /// The palindrome digit sets adding up to half of X, each with the number of ways engine v1 finds
+    /// to give the pair cages their sums, the triplet cages taking the others.
+    pub digit_sets: Vec<(Vec<u16>, usize)>,
+}
+
+/// Lists the sequence starts and palindrome digit sets of a rule set of pair and triplet cages, every digit appearing
+/// `occurrences` times in the cage cells outside palindromes, and palindrome digits twice in the palindrome cells.
+///
+/// The cage sums add up to both nS + n(n - 1) / 2 for n cages from a start S, and 45 × occurrences + X.
+/// Every start giving an even X that distinct palindrome digits add up to half of is listed, then engine v1
+/// looks for cage digits matching each digit set, as in `out/3_combinations.txt` without the split of the pairs.
+pub fn explore(
+    pairs: usize,
+    triplets: usize,
+    occurrences: usize,
+    palindrome_cells: usize,
+) -> Result<Vec<Feasible>, String> {
+    let cells = 2 * pairs + 3 * triplets;
+
+    if cells != 9 * occurrences + palindrome_cells {
+        return Err(format!(
+            "{} cage cells don't hold every digit {} times besides {} palindrome cells",
+            cells, occurrences, palindrome_cells
+        ));
+    }
+
+    let cages = (pairs + triplets) as u16;
+    let params = |start: u16, palindrome_total: u16| Params {
+        pairs,
+        triplets,
+        occurrences,
+        palindrome_total,
+        sequence: start..=start + cages - 1,
+        split: pairs,
+    };
+
+    params(1, 0).validate()?;
+    let sets = digit_sets(palindrome_cells / 2);
+    let mut feasible = vec![];
+
+    // No cage adds up to more than 24, the largest sum of a triplet.
+    for start in 1..=24 {
+        let total = cages * start + cages * (cages - 1) / 2;
+        let Some(palindrome_total) = total.checked_sub(45 * occurrences as u16) else {
+            continue;
+        };
+
+        let matching: Vec<&Vec<u16>> =
+            sets.iter().filter(|set| 2 * set.iter().sum::<u16>() == palindrome_total).collect();
+
+        if matching.is_empty() {
+            continue;
+        }
+
+        let params = params(start, palindrome_total);
+        let mut triplet_map = get_triplet_map(&params);
+        let mut pairs_sequence = get_pairs_sequence(&params, &triplet_map);
+        let combinations = get_combinations(&params, &mut triplet_map, &mut pairs_sequence);
+        let found: Vec<Vec<u16>> =
+            combinations.values().flatten().map(|sequence| palindrome_digits(&params, sequence)).collect();
+
+        let digit_sets = matching
+            .into_iter()
+            .map(|set| (set.clone(), found.iter().filter(|digits| *digits == set).count()))
+            .collect();
+
+        feasible.push(Feasible { start, palindrome_total, digit_sets });
+    }
+
+    Ok(feasible)
+}
+
+/// Every set of `count` distinct digits, in increasing order.
+fn digit_sets(count: usize) -> Vec<Vec<u16>> {
+    let mut sets = vec![vec![]];
+
+    for _ in 0..count {
+        sets = sets
+            .into_iter()
+            .flat_map(|set: Vec<u16>| {
+                let next = set.last().map_or(1, |last| last + 1);
+                (next..=9).map(move |digit| [set.clone(), vec![digit]].concat())
+            })
+            .collect();
+    }
+
+    sets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum_sequence() {
+        let feasible = explore(12, 3, 3, 6).unwrap();
+        assert_eq!(feasible.len(), 1);
+        assert_eq!((feasible[0].start, feasible[0].palindrome_total), (4, 30));
+
+        // The palindrome digit sets of the readme, adding up to 15.
+        let sets: Vec<Vec<u16>> = feasible[0].digit_sets.iter().map(|(set, _)| set.clone()).collect();
+        let readme = [[1, 5, 9], [1, 6, 8], [2, 4, 9], [2, 5, 8], [2, 6, 7], [3, 4, 8], [3, 5, 7], [4, 5, 6]];
+        assert_eq!(sets, readme.map(|set| set.to_vec()));
+        assert!(feasible[0].digit_sets.iter().all(|&(_, combinations)| combinations > 0));
+
+        assert!(explore(12, 3, 3, 4).is_err());
+    }
+
+    #[test]
+    fn test_triplets_only() {
+        // Three triplets from 14 add up to 45, as {1, 4, 9}, {2, 6, 7} and {3, 5, 8} do.
+        let feasible = explore(0, 3, 1, 0).unwrap();
+        assert_eq!(feasible.len(), 1);
+        assert_eq!((feasible[0].start, feasible[0].palindrome_total), (14, 0));
+
+        let [(set, combinations)] = feasible[0].digit_sets.as_slice() else { panic!("one digit set expected") };
+        assert!(set.is_empty());
+        assert!(*combinations > 0);
+
+        let params = Params { pairs: 0, triplets: 3, occurrences: 1, palindrome_total: 0, sequence: 14..=16, split: 0 };
+        let triplets = &get_triplet_map(&params)[&vec![14, 15, 16]];
+        assert!(triplets.contains(&vec![[1, 4, 9], [2, 6, 7], [3, 5, 8]]));
+    }
+}
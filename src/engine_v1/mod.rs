@@ -1,24 +1,96 @@
 pub mod combinations;
 pub mod constraints;
+pub mod feasibility;
 pub mod sums;
 
 use combinations::compute_combinations;
-use constraints::fill_constraints;
+use constraints::{Layout, fill_constraints, place};
 use sums::*;
 
+use crate::engine_v2::rules::Contradiction;
+use crate::engine_v2::{SolveResult, Solver, State, Sudoku};
 use indexmap::IndexMap;
 use std::fs::{self, File};
 use std::io::{BufWriter, Result, Write};
+use std::ops::RangeInclusive;
 
-pub fn generate() -> Result<()> {
-    let pair_sums = pair_sums();
-    let triplet_sums = triplet_sums();
+/// The rule set searched by engine v1, the sum-sequence Sudoku by default: the cages and how often every digit
+/// appears in them. Palindrome digits appear in two palindrome cells each, on top of their count outside palindromes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    /// The number of pair cages.
+    pub pairs: usize,
+    /// The number of triplet cages, which hold every digit the same number of times.
+    pub triplets: usize,
+    /// How many times every digit appears in the cage cells outside palindromes.
+    pub occurrences: usize,
+    /// The total of the cage cells inside palindromes, X.
+    pub palindrome_total: u16,
+    /// The cage sums, one per cage.
+    pub sequence: RangeInclusive<u16>,
+    /// The number of pair cages in the first group of the split, the top and bottom ones of the layout,
+    /// each group holding every digit in proportion to its size.
+    pub split: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params { pairs: 12, triplets: 3, occurrences: 3, palindrome_total: 30, sequence: 4..=18, split: 6 }
+    }
+}
+
+impl Params {
+    /// How many times every digit appears in the triplet cages.
+    pub fn triplet_occurrences(&self) -> usize {
+        3 * self.triplets / 9
+    }
+
+    /// How many times every digit appears in the pair cage cells outside palindromes.
+    pub fn pair_occurrences(&self) -> usize {
+        self.occurrences - self.triplet_occurrences()
+    }
+
+    /// The number of cage cells inside palindromes, those left once every digit appears `occurrences` times.
+    pub fn palindrome_cells(&self) -> usize {
+        2 * self.pairs + 3 * self.triplets - 9 * self.occurrences
+    }
+
+    /// The number of distinct palindrome digits.
+    pub fn palindrome_digits(&self) -> usize {
+        self.palindrome_cells() / 2
+    }
+
+    /// Checks that the cages, the sequence and the digit counts fit together.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let cages = self.pairs + self.triplets;
+        let cells = 2 * self.pairs + 3 * self.triplets;
+
+        if self.sequence.clone().count() != cages {
+            return Err(format!("The sequence {:?} doesn't have a sum for each of the {} cages", self.sequence, cages));
+        }
+        if self.pairs > 31 || self.split > self.pairs {
+            return Err(format!("Can't split {} pair cages into {} and the rest", self.pairs, self.split));
+        }
+        if !(3 * self.triplets).is_multiple_of(9) {
+            return Err(format!("{} triplet cages can't hold every digit the same number of times", self.triplets));
+        }
+        if self.occurrences < self.triplet_occurrences() || cells < 9 * self.occurrences {
+            return Err(format!("{} cage cells can't hold every digit {} times", cells, self.occurrences));
+        }
+        if !self.palindrome_cells().is_multiple_of(2) || !self.palindrome_total.is_multiple_of(2) {
+            return Err("Palindrome digits appear in two palindrome cells each".into());
+        }
+
+        Ok(())
+    }
+}
 
-    let mut triplet_map = get_triplet_map(&triplet_sums);
-    let mut pairs_sequence = get_pairs_sequence(&triplet_map);
+pub fn generate(params: &Params, layout: &Layout) -> Result<()> {
+    let mut triplet_map = get_triplet_map(params);
+    let mut pairs_sequence = get_pairs_sequence(params, &triplet_map);
 
-    let combinations = get_combinations(&mut triplet_map, &mut pairs_sequence, &pair_sums);
-    let solutions = get_solutions(&combinations);
+    let combinations = get_combinations(params, &mut triplet_map, &mut pairs_sequence);
+    let solutions = get_solutions(params, layout, &combinations);
 
     fs::create_dir_all("out")?;
 
@@ -31,30 +103,105 @@ pub fn generate() -> Result<()> {
     file.flush()?;
 
     let mut file = BufWriter::new(File::create("out/3_combinations.txt")?);
-    print_combinations(&mut file, &combinations)?;
+    print_combinations(params, &mut file, &combinations)?;
     file.flush()?;
 
     let mut file = BufWriter::new(File::create("out/4_solutions.txt")?);
-    print_solutions(&mut file, &solutions)?;
+    print_solutions(params, &mut file, &solutions)?;
     file.flush()?;
 
     Ok(())
 }
 
+/// The cage-pair solutions written to `out/4_solutions.txt`, by the sums of the triplet cages.
+pub fn solutions(params: &Params, layout: &Layout) -> IndexMap<Sums, Vec<Vec<Pair>>> {
+    let mut triplet_map = get_triplet_map(params);
+    let mut pairs_sequence = get_pairs_sequence(params, &triplet_map);
+
+    let combinations = get_combinations(params, &mut triplet_map, &mut pairs_sequence);
+    get_solutions(params, layout, &combinations)
+}
+
+/// What engine v2 made of the solutions placed on a grid.
+#[derive(Default)]
+pub struct Pipeline {
+    /// The solutions completed into a single grid, along with it.
+    pub solved: Vec<(Sums, Vec<Pair>, Sudoku)>,
+    pub unsolvable: usize,
+    /// The solutions engine v2 got stuck on without deciding them, or gave up on at one of its limits.
+    pub open: usize,
+}
+
+/// Places up to `max` solutions on the layout cells of the grid and solves each with engine v2,
+/// writing the outcome of every one to `out/5_pipeline.txt`.
+pub fn pipeline(
+    params: &Params,
+    layout: &Layout,
+    sudoku: &Sudoku,
+    solver: &Solver,
+    solutions: &IndexMap<Sums, Vec<Vec<Pair>>>,
+    max: usize,
+) -> Result<Pipeline> {
+    let mut pipeline = Pipeline::default();
+
+    fs::create_dir_all("out")?;
+    let mut file = BufWriter::new(File::create("out/5_pipeline.txt")?);
+
+    let solutions = solutions.iter().flat_map(|(sums, solution)| solution.iter().map(move |s| (sums, s)));
+
+    for (sums, solution) in solutions.take(max) {
+        let SolveResult(_, grid, state) = solve_solution(layout, sudoku, solver, solution);
+
+        write!(file, "{:?} =", sums)?;
+        write_sequence(params, &mut file, solution)?;
+
+        match state {
+            State::Solved => {
+                writeln!(file, " : solved")?;
+                pipeline.solved.push((sums.clone(), solution.clone(), grid));
+            }
+            State::Unsolvable(contradiction) => {
+                writeln!(file, " : {}", contradiction)?;
+                pipeline.unsolvable += 1;
+            }
+            state => {
+                writeln!(file, " : open, {:?}", state)?;
+                pipeline.open += 1;
+            }
+        }
+    }
+
+    file.flush()?;
+    Ok(pipeline)
+}
+
+/// Places a solution on the grid and solves it, a solution clashing with the grid being unsolvable.
+fn solve_solution(layout: &Layout, sudoku: &Sudoku, solver: &Solver, solution: &[Pair]) -> SolveResult {
+    let mut grid = sudoku.clone();
+
+    match place(layout, solution, &mut grid) {
+        Ok(()) => solver.solve(grid),
+        Err(pos) => {
+            let contradiction = Contradiction::new("engine v1", vec![pos], "the solution clashes with the grid");
+            SolveResult(0, grid, State::Unsolvable(contradiction))
+        }
+    }
+}
+
 fn get_combinations(
-    triplet_map: &mut IndexMap<Triplet, Vec<CornerTriplets>>,
-    pairs_sequence: &mut IndexMap<Triplet, [u16; 12]>,
-    pair_sums: &[Vec<Pair>],
-) -> IndexMap<Triplet, Vec<[Pair; 12]>> {
+    params: &Params,
+    triplet_map: &mut IndexMap<Sums, Vec<CornerTriplets>>,
+    pairs_sequence: &mut IndexMap<Sums, Vec<u16>>,
+) -> IndexMap<Sums, Vec<Vec<Pair>>> {
     let mut results = IndexMap::new();
     let mut to_remove = vec![];
 
     for (triplet, seq) in &*pairs_sequence {
-        let combinations = compute_combinations(seq, pair_sums);
+        let combinations = compute_combinations(params, seq);
         if combinations.is_empty() {
-            to_remove.push(*triplet);
+            to_remove.push(triplet.clone());
         } else {
-            results.insert(*triplet, combinations);
+            results.insert(triplet.clone(), combinations);
         }
     }
 
@@ -66,27 +213,18 @@ fn get_combinations(
     results
 }
 
-fn print_combinations<W: Write>(w: &mut W, combinations: &IndexMap<Triplet, Vec<[Pair; 12]>>) -> Result<()> {
+fn print_combinations<W: Write>(
+    params: &Params,
+    w: &mut W,
+    combinations: &IndexMap<Sums, Vec<Vec<Pair>>>,
+) -> Result<()> {
     for (triplet, combination) in combinations {
         if !combination.is_empty() {
             writeln!(w, "Found {} valid combination(s) for triplet {:?}", combination.len(), triplet)?;
 
             for sequence in combination {
-                let mut digit_count = [0; 9];
-                for &[a, b] in &sequence[0..6] {
-                    digit_count[a as usize - 1] += 1;
-                    digit_count[b as usize - 1] += 1;
-                }
-                let dups: Vec<usize> = (0..9).filter(|&digit| digit_count[digit] == 2).map(|i| i + 1).collect();
-
-                write!(w, "  {:?} =", dups)?;
-                for [a, b] in &sequence[0..6] {
-                    write!(w, " {}{}", a, b)?;
-                }
-                write!(w, " |")?;
-                for [a, b] in &sequence[6..12] {
-                    write!(w, " {}{}", a, b)?;
-                }
+                write!(w, "  {:?} =", palindrome_digits(params, sequence))?;
+                write_sequence(params, w, sequence)?;
                 writeln!(w)?;
             }
         }
@@ -95,55 +233,35 @@ fn print_combinations<W: Write>(w: &mut W, combinations: &IndexMap<Triplet, Vec<
     Ok(())
 }
 
-fn get_solutions(combinations: &IndexMap<Triplet, Vec<[Pair; 12]>>) -> IndexMap<Triplet, Vec<[Pair; 12]>> {
+fn get_solutions(
+    params: &Params,
+    layout: &Layout,
+    combinations: &IndexMap<Sums, Vec<Vec<Pair>>>,
+) -> IndexMap<Sums, Vec<Vec<Pair>>> {
     let mut solutions: IndexMap<_, Vec<_>> = IndexMap::new();
 
     for (triplet, combination) in combinations {
         for sequence in combination {
-            let mut digit_count = [0; 9];
-            for &[a, b] in &sequence[0..6] {
-                digit_count[a as usize - 1] += 1;
-                digit_count[b as usize - 1] += 1;
-            }
-
-            let dups = (0..9)
-                .filter(|&digit| digit_count[digit as usize] == 2)
-                .map(|i| i + 1)
-                .collect::<Vec<u16>>()
-                .try_into()
-                .unwrap();
+            let dups = palindrome_digits(params, sequence);
 
             let mut results = vec![];
-            fill_constraints(sequence, dups, &mut results);
+            fill_constraints(params, layout, sequence, &dups, &mut results);
 
-            solutions.entry(*triplet).or_default().append(&mut results);
+            solutions.entry(triplet.clone()).or_default().append(&mut results);
         }
     }
 
     solutions
 }
 
-fn print_solutions<W: Write>(w: &mut W, solutions: &IndexMap<Triplet, Vec<[Pair; 12]>>) -> Result<()> {
+fn print_solutions<W: Write>(params: &Params, w: &mut W, solutions: &IndexMap<Sums, Vec<Vec<Pair>>>) -> Result<()> {
     for (triplet, solution) in solutions {
         if !solution.is_empty() {
             writeln!(w, "Found {} valid solution(s) for triplet {:?}", solution.len(), triplet)?;
 
             for sequence in solution {
-                let mut digit_count = [0; 9];
-                for &[a, b] in &sequence[0..6] {
-                    digit_count[a as usize - 1] += 1;
-                    digit_count[b as usize - 1] += 1;
-                }
-                let dups: Vec<usize> = (0..9).filter(|&digit| digit_count[digit] == 2).map(|i| i + 1).collect();
-
-                write!(w, "  {:?} =", dups)?;
-                for [a, b] in &sequence[0..6] {
-                    write!(w, " {}{}", a, b)?;
-                }
-                write!(w, " |")?;
-                for [a, b] in &sequence[6..12] {
-                    write!(w, " {}{}", a, b)?;
-                }
+                write!(w, "  {:?} =", palindrome_digits(params, sequence))?;
+                write_sequence(params, w, sequence)?;
                 writeln!(w)?;
             }
         }
@@ -151,3 +269,57 @@ fn print_solutions<W: Write>(w: &mut W, solutions: &IndexMap<Triplet, Vec<[Pair;
 
     Ok(())
 }
+
+/// The digits appearing in the pairs of a sequence more often than outside palindromes, in increasing order.
+fn palindrome_digits(params: &Params, sequence: &[Pair]) -> Vec<u16> {
+    let mut digit_count = [0; 9];
+    for &[a, b] in sequence {
+        digit_count[a as usize - 1] += 1;
+        digit_count[b as usize - 1] += 1;
+    }
+
+    (1..=9).filter(|&digit| digit_count[digit as usize - 1] > params.pair_occurrences()).collect()
+}
+
+/// Writes the pairs of a sequence, the first group of the split apart from the second.
+fn write_sequence<W: Write>(params: &Params, w: &mut W, sequence: &[Pair]) -> Result<()> {
+    for [a, b] in &sequence[0..params.split] {
+        write!(w, " {}{}", a, b)?;
+    }
+    write!(w, " |")?;
+    for [a, b] in &sequence[params.split..] {
+        write!(w, " {}{}", a, b)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params() {
+        assert_eq!(Params::default().validate(), Ok(()));
+        assert!(Params { triplets: 4, ..Default::default() }.validate().is_err());
+        assert!(Params { sequence: 4..=17, ..Default::default() }.validate().is_err());
+
+        // Twelve pair cages and every digit twice outside the palindromes: 12S + 66 = 90 + X.
+        let params =
+            Params { triplets: 0, occurrences: 2, palindrome_total: 24, sequence: 4..=15, ..Default::default() };
+        assert_eq!(params.validate(), Ok(()));
+
+        let mut triplet_map = get_triplet_map(&params);
+        let mut pairs_sequence = get_pairs_sequence(&params, &triplet_map);
+        assert_eq!(pairs_sequence[&vec![]], (4..=15).collect::<Vec<u16>>());
+
+        let combinations = get_combinations(&params, &mut triplet_map, &mut pairs_sequence);
+        let sequences = &combinations[&vec![]];
+        assert!(!sequences.is_empty());
+
+        for sequence in sequences {
+            let digits = palindrome_digits(&params, sequence);
+            assert_eq!((digits.len(), digits.iter().sum::<u16>()), (3, 12));
+        }
+    }
+}
@@ -1,60 +1,35 @@
+use super::Params;
 use indexmap::IndexMap;
 use std::io::{Result, Write};
 
 pub type Pair = [u16; 2];
 pub type Triplet = [u16; 3];
-pub type CornerTriplets = [Triplet; 3];
-
-pub fn pair_sums() -> Vec<Vec<Pair>> {
-    vec![
-        vec![[1, 3]],
-        vec![[1, 4], [2, 3]],
-        vec![[1, 5], [2, 4]],
-        vec![[1, 6], [2, 5], [3, 4]],
-        vec![[1, 7], [2, 6], [3, 5]],
-        vec![[1, 8], [2, 7], [3, 6], [4, 5]],
-        vec![[1, 9], [2, 8], [3, 7], [4, 6]],
-        vec![[2, 9], [3, 8], [4, 7], [5, 6]],
-        vec![[3, 9], [4, 8], [5, 7]],
-        vec![[4, 9], [5, 8], [6, 7]],
-        vec![[5, 9], [6, 8]],
-        vec![[6, 9], [7, 8]],
-        vec![[7, 9]],
-        vec![[8, 9]],
-    ]
+/// The sums of the triplet cages, in increasing order.
+pub type Sums = Vec<u16>;
+/// A triplet of digits for every triplet cage, in the order of their sums.
+pub type CornerTriplets = Vec<Triplet>;
+
+/// The pairs of different digits adding up to `sum`, in increasing order.
+pub fn pair_sums(sum: u16) -> Vec<Pair> {
+    (1..=9).flat_map(|a| (a + 1..=9).map(move |b| [a, b])).filter(|&[a, b]| a + b == sum).collect()
 }
 
-pub fn get_pairs_sequence(triplet_map: &IndexMap<Triplet, Vec<CornerTriplets>>) -> IndexMap<Triplet, [u16; 12]> {
-    let mut pairs_sequence: IndexMap<Triplet, [_; 12]> = IndexMap::new();
+/// The sums of the pair cages for every choice of triplet sums: the sequence without the triplet sums.
+pub fn get_pairs_sequence(
+    params: &Params,
+    triplet_map: &IndexMap<Sums, Vec<CornerTriplets>>,
+) -> IndexMap<Sums, Vec<u16>> {
+    let mut pairs_sequence = IndexMap::new();
 
-    for k @ [a, b, c] in triplet_map.keys().copied() {
-        let mut sequence = [0; 12];
-        let mut i = 11;
-        let mut n = 17;
-
-        loop {
-            if n == c || n == b || n == a {
-                n -= 1;
-                continue;
-            }
-
-            sequence[i] = n;
-
-            if i == 0 {
-                break;
-            }
-
-            i -= 1;
-            n -= 1;
-        }
-
-        pairs_sequence.insert(k, sequence);
+    for sums in triplet_map.keys() {
+        let sequence = params.sequence.clone().filter(|sum| !sums.contains(sum)).collect();
+        pairs_sequence.insert(sums.clone(), sequence);
     }
 
     pairs_sequence
 }
 
-pub fn print_pairs_sequence<W: Write>(w: &mut W, pairs_sequence: &IndexMap<Triplet, [u16; 12]>) -> Result<()> {
+pub fn print_pairs_sequence<W: Write>(w: &mut W, pairs_sequence: &IndexMap<Sums, Vec<u16>>) -> Result<()> {
     for (key, sequence) in pairs_sequence {
         let key = format!("{:?}", key);
         writeln!(w, "{:12} = {:2?}", key, sequence)?;
@@ -62,65 +37,121 @@ pub fn print_pairs_sequence<W: Write>(w: &mut W, pairs_sequence: &IndexMap<Tripl
     Ok(())
 }
 
-pub fn triplet_sums() -> Vec<Vec<Triplet>> {
-    vec![
-        vec![[1, 2, 3]],
-        vec![[1, 2, 4]],
-        vec![[1, 2, 5], [1, 3, 4]],
-        vec![[1, 2, 6], [1, 3, 5], [2, 3, 4]],
-        vec![[1, 2, 7], [1, 3, 6], [1, 4, 5], [2, 3, 5]],
-        vec![[1, 2, 8], [1, 3, 7], [1, 4, 6], [2, 3, 6], [2, 4, 5]],
-        vec![[1, 2, 9], [1, 3, 8], [1, 4, 7], [1, 5, 6], [2, 3, 7], [2, 4, 6], [3, 4, 5]],
-        vec![[1, 3, 9], [1, 4, 8], [1, 5, 7], [2, 3, 8], [2, 4, 7], [2, 5, 6], [3, 4, 6]],
-        vec![[1, 4, 9], [1, 5, 8], [1, 6, 7], [2, 3, 9], [2, 4, 8], [2, 5, 7], [3, 4, 7], [3, 5, 6]],
-        vec![[1, 5, 9], [1, 6, 8], [2, 4, 9], [2, 5, 8], [2, 6, 7], [3, 4, 8], [3, 5, 7], [4, 5, 6]],
-        vec![[1, 6, 9], [1, 7, 8], [2, 5, 9], [2, 6, 8], [3, 4, 9], [3, 5, 8], [3, 6, 7], [4, 5, 7]],
-        vec![[1, 7, 9], [2, 6, 9], [2, 7, 8], [3, 5, 9], [3, 6, 8], [4, 5, 9], [4, 6, 7]],
-        vec![[1, 8, 9], [2, 7, 9], [3, 6, 9], [3, 7, 8], [4, 5, 8], [4, 6, 7]],
-    ]
+/// The triplets of different digits adding up to `sum`, in increasing order.
+pub fn triplet_sums(sum: u16) -> Vec<Triplet> {
+    let triplets = (1..=9).flat_map(|a| (a + 1..=9).flat_map(move |b| (b + 1..=9).map(move |c| [a, b, c])));
+    triplets.filter(|&[a, b, c]| a + b + c == sum).collect()
 }
 
-pub fn get_triplet_map(triplet_sums: &[Vec<Triplet>]) -> IndexMap<Triplet, Vec<CornerTriplets>> {
-    let mut res: IndexMap<_, Vec<CornerTriplets>> = IndexMap::new();
+/// The digits of the triplet cages for every choice of their sums, with every digit as many times.
+/// Sums of the sequence too high for a pair cage are always taken by triplet cages.
+pub fn get_triplet_map(params: &Params) -> IndexMap<Sums, Vec<CornerTriplets>> {
+    let mut res = IndexMap::new();
 
-    for i in 0..triplet_sums.len() - 1 {
-        for j in (i + 1)..triplet_sums.len() - 1 {
-            let k = 12;
-            let triplets = find_unique_triplets(&triplet_sums[i], &triplet_sums[j], &triplet_sums[k]);
-            for [a, b, c] in triplets {
-                let e = [i as u16 + 6, j as u16 + 6, k as u16 + 6];
-                res.entry(e).or_default().push([a, b, c]);
-            }
+    let candidates: Vec<u16> = params.sequence.clone().filter(|&sum| !triplet_sums(sum).is_empty()).collect();
+    let forced: Vec<u16> = params.sequence.clone().filter(|&sum| pair_sums(sum).is_empty()).collect();
+
+    for sums in choose(&candidates, params.triplets) {
+        if !forced.iter().all(|sum| sums.contains(sum)) {
+            continue;
+        }
+
+        let triplets: Vec<Vec<Triplet>> = sums.iter().map(|&sum| triplet_sums(sum)).collect();
+        let mut found = vec![];
+        find_even_triplets(&triplets, params.triplet_occurrences(), &mut vec![], &mut [0; 9], &mut found);
+
+        if !found.is_empty() {
+            res.insert(sums, found);
         }
     }
+
     res
 }
 
-fn find_unique_triplets(s1: &[Triplet], s2: &[Triplet], s3: &[Triplet]) -> Vec<CornerTriplets> {
-    let mut res = vec![];
-    for t1 in s1 {
-        for t2 in s2 {
-            for t3 in s3 {
-                let mut digits = vec![t1[0], t1[1], t1[2], t2[0], t2[1], t2[2], t3[0], t3[1], t3[2]];
-                digits.sort_unstable();
-                digits.dedup();
-                if digits.len() == 9 {
-                    res.push([*t1, *t2, *t3]);
-                }
-            }
+/// Every choice of `count` values, in lexicographic order.
+fn choose(values: &[u16], count: usize) -> Vec<Vec<u16>> {
+    match (count, values) {
+        (0, _) => vec![vec![]],
+        (_, []) => vec![],
+        (_, [first, rest @ ..]) => {
+            let mut with: Vec<Vec<u16>> = choose(rest, count - 1);
+            with.iter_mut().for_each(|choice| choice.insert(0, *first));
+            with.extend(choose(rest, count));
+            with
         }
     }
-    res
 }
 
-pub fn print_triple_map<W: Write>(w: &mut W, triplet_map: &IndexMap<Triplet, Vec<CornerTriplets>>) -> Result<()> {
+/// Picks a triplet of every list, keeping the picks that hold every digit `occurrences` times.
+fn find_even_triplets(
+    triplets: &[Vec<Triplet>],
+    occurrences: usize,
+    current: &mut CornerTriplets,
+    digit_count: &mut [usize; 9],
+    res: &mut Vec<CornerTriplets>,
+) {
+    let Some((options, rest)) = triplets.split_first() else {
+        if digit_count.iter().all(|&count| count == occurrences) {
+            res.push(current.clone());
+        }
+        return;
+    };
+
+    for &triplet in options {
+        if triplet.iter().any(|&digit| digit_count[digit as usize - 1] == occurrences) {
+            continue;
+        }
+
+        triplet.iter().for_each(|&digit| digit_count[digit as usize - 1] += 1);
+        current.push(triplet);
+
+        find_even_triplets(rest, occurrences, current, digit_count, res);
+
+        current.pop();
+        triplet.iter().for_each(|&digit| digit_count[digit as usize - 1] -= 1);
+    }
+}
+
+pub fn print_triple_map<W: Write>(w: &mut W, triplet_map: &IndexMap<Sums, Vec<CornerTriplets>>) -> Result<()> {
     for (sum, triplets) in triplet_map {
         writeln!(w, "{:?}", sum)?;
-        for [[a1, a2, a3], [b1, b2, b3], [c1, c2, c3]] in triplets {
-            writeln!(w, " {}{}{} {}{}{} {}{}{}", a1, a2, a3, b1, b2, b3, c1, c2, c3)?;
+        for corner in triplets {
+            for [a, b, c] in corner {
+                write!(w, " {}{}{}", a, b, c)?;
+            }
+            writeln!(w)?;
         }
         writeln!(w)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum_tables() {
+        assert_eq!(pair_sums(4), [[1, 3]]);
+        assert!(pair_sums(18).is_empty());
+        assert_eq!(triplet_sums(18), [[1, 8, 9], [2, 7, 9], [3, 6, 9], [3, 7, 8], [4, 5, 9], [4, 6, 8], [5, 6, 7]]);
+
+        let triplet_map = get_triplet_map(&Params::default());
+        assert!(triplet_map.keys().all(|sums| sums.len() == 3 && sums.contains(&18)));
+        assert!(triplet_map[&vec![10, 17, 18]].contains(&vec![[1, 4, 5], [2, 6, 9], [3, 7, 8]]));
+    }
+
+    #[test]
+    fn test_triplets_add_up_to_their_sums() {
+        assert!(triplet_sums(17).contains(&[4, 5, 8]) && !triplet_sums(17).contains(&[4, 5, 9]));
+        assert!(!triplet_sums(18).contains(&[4, 6, 7]));
+
+        for (sums, corners) in get_triplet_map(&Params::default()) {
+            for triplets in corners {
+                let totals: Vec<u16> = triplets.iter().map(|triplet| triplet.iter().sum()).collect();
+                assert_eq!(totals, sums);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::ops::BitAnd;
+
+/// A set of cells of a canvas, one bit per cell at index `row * width + col`.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Bitboard(Vec<u64>);
+
+impl Bitboard {
+    /// An empty board for a canvas of `cells` cells.
+    pub fn new(cells: usize) -> Self {
+        Bitboard(vec![0; cells.div_ceil(64)])
+    }
+
+    pub fn from_indices(cells: usize, indices: impl IntoIterator<Item = usize>) -> Self {
+        let mut board = Bitboard::new(cells);
+        indices.into_iter().for_each(|index| board.insert(index));
+        board
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.0[index / 64] &= !(1 << (index % 64));
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        self.0[index / 64] ^= 1 << (index % 64);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&word| word == 0)
+    }
+
+    /// The number of cells in both boards, without building their intersection.
+    pub fn count_common(&self, other: &Bitboard) -> u32 {
+        self.0.iter().zip(&other.0).map(|(a, b)| (a & b).count_ones()).sum()
+    }
+
+    /// The lowest cell in both boards.
+    pub fn first_common(&self, other: &Bitboard) -> Option<usize> {
+        self.0.iter().zip(&other.0).enumerate().find_map(|(i, (a, b))| {
+            let word = a & b;
+            (word != 0).then(|| i * 64 + word.trailing_zeros() as usize)
+        })
+    }
+
+    /// The cell indices, in increasing order.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, &word)| {
+            let mut rest = word;
+            std::iter::from_fn(move || {
+                (rest != 0).then(|| {
+                    let bit = rest.trailing_zeros() as usize;
+                    rest &= rest - 1;
+                    i * 64 + bit
+                })
+            })
+        })
+    }
+}
+
+impl BitAnd for &Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Bitboard(self.0.iter().zip(&rhs.0).map(|(a, b)| a & b).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitboard_operations() {
+        let row = Bitboard::from_indices(81, 9..18);
+        let mut col = Bitboard::from_indices(81, (0..9).map(|row| row * 9 + 2));
+
+        assert_eq!(row.count_common(&col), 1);
+        assert_eq!(row.first_common(&col), Some(11));
+        assert_eq!((&row & &col).indices().collect::<Vec<_>>(), [11]);
+
+        col.toggle(11);
+        col.insert(80);
+        assert!((&row & &col).is_empty());
+        assert_eq!(col.indices().last(), Some(80));
+        assert_eq!(col.count(), 9);
+    }
+}
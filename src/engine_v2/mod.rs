@@ -1,31 +1,72 @@
-mod rules;
-
+mod bitboard;
+mod model;
+mod parallel;
+mod registry;
+pub mod rules;
+mod solver;
+mod stats;
+
+pub use bitboard::Bitboard;
+pub use model::{Domain, Linear, Term, Var};
+pub(crate) use registry::distinct_sets;
+pub use registry::{Builder, Registry, RuleSpec};
+pub use rules::{Clue, ClueKind, Contradiction, Direction, Edge, Elimination, Regions, Rule};
+pub use solver::{CancelToken, DEFAULT_LIMIT, Solutions, SolveResult, Solver, State};
+pub use stats::{RuleStats, Stats};
+
+use indexmap::IndexMap;
 use std::{
     fmt::Display,
     num::NonZeroU8,
-    ops::{Index, IndexMut},
+    ops::{BitAnd, Index, IndexMut},
 };
 
+/// The raw content of a cell, best read through `Entry`.
 ///```txt
 ///   0 for number, 1 for pencilmark
 ///   |
-/// 0b1000 0000 0000 0000
-///           |      |
-///           |      digits 1-9
-///           |
-///           1-9 pencilmarks for digits 1-9
+/// 0x8000_0000
+///          |
+///          digits 1-16 or pencilmarks for digits 1-16
 /// ```
-type Value = u16;
+pub type Value = u32;
+
+const PENCIL_FLAG: Value = 0x8000_0000;
 
-/// A bitmask representing possible pencilmarks for digits 1-9.
-#[derive(PartialEq, Debug, Clone, Copy)]
-struct Mask(u16);
+/// The largest supported grid size, limited by the pencilmark bits of a `Value`.
+const MAX_SIZE: usize = 16;
+
+/// A bitmask representing possible pencilmarks for digits 1-16.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Mask(u32);
 
 impl Mask {
-    const ALL: Mask = Mask(0b111111111);
+    /// The mask with every digit of a grid of the given size.
+    pub fn full(size: usize) -> Mask {
+        Mask((1 << size) - 1)
+    }
+
+    pub fn from_digits(digits: impl IntoIterator<Item = u8>) -> Mask {
+        let mut mask = Mask(0);
+        digits.into_iter().for_each(|digit| mask.set_bit(digit as usize - 1, true));
+        mask
+    }
+
+    pub fn contains(self, digit: u8) -> bool {
+        (1..=MAX_SIZE as u8).contains(&digit) && self[digit as usize - 1]
+    }
 
-    fn set_bit(&mut self, index: usize, value: bool) {
-        if index >= 9 {
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// The digits in the mask, in increasing order.
+    pub fn digits(self) -> impl DoubleEndedIterator<Item = u8> {
+        (1..=MAX_SIZE as u8).filter(move |&digit| self.contains(digit))
+    }
+
+    pub fn set_bit(&mut self, index: usize, value: bool) {
+        if index >= MAX_SIZE {
             panic!("Index out of bounds");
         }
 
@@ -36,7 +77,7 @@ impl Mask {
         }
     }
 
-    fn set_digit(&mut self, digit: NonZeroU8, value: bool) {
+    pub fn set_digit(&mut self, digit: NonZeroU8, value: bool) {
         self.set_bit(digit.get() as usize - 1, value);
     }
 }
@@ -45,7 +86,7 @@ impl Index<usize> for Mask {
     type Output = bool;
 
     fn index(&self, index: usize) -> &Self::Output {
-        if index >= 9 {
+        if index >= MAX_SIZE {
             panic!("Index out of bounds");
         }
 
@@ -61,16 +102,59 @@ impl Index<NonZeroU8> for Mask {
     }
 }
 
+impl BitAnd for Mask {
+    type Output = Mask;
+
+    fn bitand(self, other: Mask) -> Mask {
+        Mask(self.0 & other.0)
+    }
+}
+
 impl From<Mask> for Value {
     fn from(value: Mask) -> Self {
-        value.0 | 0x8000
+        value.0 | PENCIL_FLAG
+    }
+}
+
+/// A restriction on the digits a single cell may hold.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Shade {
+    Odd,
+    Even,
+    /// The upper half of the digits, 6-9 on a 9x9 grid.
+    High,
+    /// The lower half of the digits, 1-4 on a 9x9 grid.
+    Low,
+}
+
+impl Shade {
+    pub(crate) fn mask(self, size: usize) -> Mask {
+        let all = Mask::full(size).0;
+        let half = size / 2;
+
+        match self {
+            Shade::Odd => Mask(all & 0x5555_5555),
+            Shade::Even => Mask(all & 0xAAAA_AAAA),
+            Shade::High => Mask(all & !((1 << (size - half)) - 1)),
+            Shade::Low => Mask((1 << half) - 1),
+        }
+    }
+
+    /// The character marking the cell when it has no digit yet.
+    pub fn symbol(self) -> char {
+        match self {
+            Shade::Odd => 'o',
+            Shade::Even => 'e',
+            Shade::High => 'h',
+            Shade::Low => 'l',
+        }
     }
 }
 
 /// Represents the content of a cell: either empty, a digit, or a pencilmark mask.
 /// Used to simplify working with raw cell values.
 #[derive(PartialEq, Debug, Clone, Copy, Default)]
-enum Entry {
+pub enum Entry {
     #[default]
     Empty,
     Digit(NonZeroU8),
@@ -83,59 +167,405 @@ impl From<Value> for Entry {
             return Entry::Empty;
         }
 
-        if (value & 0x8000) == 0 {
-            Entry::Digit(NonZeroU8::new((value & 0xF) as u8).unwrap())
+        if (value & PENCIL_FLAG) == 0 {
+            Entry::Digit(NonZeroU8::new((value & 0x1F) as u8).unwrap())
         } else {
-            Entry::Pencil(Mask(value & 0x1FF))
+            Entry::Pencil(Mask(value & 0xFFFF))
+        }
+    }
+}
+
+impl From<Entry> for Value {
+    fn from(entry: Entry) -> Self {
+        match entry {
+            Entry::Empty => 0,
+            Entry::Digit(digit) => digit.get() as Value,
+            Entry::Pencil(mask) => mask.into(),
         }
     }
 }
 
-type Pos = (usize, usize);
+/// The character used to draw a digit: 1-9, then A-G for 10-16.
+pub fn digit_symbol(digit: u8) -> char {
+    char::from_digit(digit as u32, 10).unwrap_or((b'A' + digit - 10) as char)
+}
+
+pub type Pos = (usize, usize);
 type CellEntry = (Pos, Entry);
 type CellMask = (Pos, Mask);
 
-#[derive(Debug, PartialEq, Clone, Default)]
-pub struct Sudoku(pub [[Value; 9]; 9]);
+/// The dimensions of a grid: `size` digits per row, column and box, with boxes of
+/// `box_height` rows by `box_width` columns.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Shape {
+    pub size: usize,
+    pub box_height: usize,
+    pub box_width: usize,
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape { size: 9, box_height: 3, box_width: 3 }
+    }
+}
+
+impl Shape {
+    /// Picks the squarest boxes for the size, wider than tall: 2x2 for 4, 2x3 for 6, 3x3 for 9, 4x4 for 16.
+    pub fn new(size: usize) -> Result<Shape, String> {
+        if !(1..=MAX_SIZE).contains(&size) {
+            return Err(format!("Unsupported grid size {}, expected 1-{}", size, MAX_SIZE));
+        }
+
+        let box_height = (1..=size).take_while(|h| h * h <= size).filter(|&h| size.is_multiple_of(h)).last().unwrap();
+
+        Ok(Shape { size, box_height, box_width: size / box_height })
+    }
+}
+
+/// A grid of cells holding one or more overlapping sub-grids of the same shape, as in Samurai puzzles.
+/// Cells outside every sub-grid are void and never hold a value.
+#[derive(Debug, Clone)]
+pub struct Sudoku {
+    shape: Shape,
+    grids: Vec<Pos>,
+    height: usize,
+    width: usize,
+    cells: Vec<Value>,
+    /// The previous value of every cell changed through `set`, most recent last.
+    trail: Vec<(usize, Value)>,
+    /// The cells where each digit is still possible, mirroring `cells`. The values stay the store,
+    /// as the index operator hands out references to them.
+    boards: Vec<Bitboard>,
+    /// Whether `cells` was written through the index operator since the boards were built.
+    stale: bool,
+    /// The auxiliary variables of the rules, such as cage sums, with the values they may still take.
+    vars: IndexMap<Var, Domain>,
+    /// The previous domain of every variable changed through `restrict_var`, by index in `vars`.
+    var_trail: Vec<(usize, Domain)>,
+}
+
+/// A point in the change trails of a `Sudoku` that it can be restored to.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize, usize);
+
+/// Grids are equal when their cells are, whatever their auxiliary variables.
+impl PartialEq for Sudoku {
+    fn eq(&self, other: &Self) -> bool {
+        self.shape == other.shape && self.grids == other.grids && self.cells == other.cells
+    }
+}
+
+impl Default for Sudoku {
+    fn default() -> Self {
+        Sudoku::new(Shape::default())
+    }
+}
 
 impl Sudoku {
+    pub fn new(shape: Shape) -> Self {
+        Sudoku {
+            shape,
+            grids: vec![(0, 0)],
+            height: shape.size,
+            width: shape.size,
+            cells: vec![0; shape.size * shape.size],
+            trail: vec![],
+            boards: vec![],
+            stale: true,
+            vars: IndexMap::new(),
+            var_trail: vec![],
+        }
+    }
+
+    /// Creates an empty canvas with a sub-grid at each origin. Origins must line up with the boxes,
+    /// so that the boxes of overlapping sub-grids coincide.
+    pub fn with_grids(shape: Shape, grids: Vec<Pos>) -> Result<Self, String> {
+        if grids.is_empty() {
+            return Err("Expected at least one grid".into());
+        }
+
+        if let Some((row, col)) = grids.iter().find(|(r, c)| r % shape.box_height != 0 || c % shape.box_width != 0) {
+            return Err(format!("Grid at row {}, col {} doesn't line up with the boxes", row + 1, col + 1));
+        }
+
+        let height = grids.iter().map(|(r, _)| r + shape.size).max().unwrap();
+        let width = grids.iter().map(|(_, c)| c + shape.size).max().unwrap();
+
+        let cells = vec![0; height * width];
+        Ok(Sudoku { shape, grids, height, width, cells, ..Sudoku::new(shape) })
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn size(&self) -> usize {
+        self.shape.size
+    }
+
+    /// The top-left cell of every sub-grid.
+    pub fn grids(&self) -> &[Pos] {
+        &self.grids
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Whether the cell belongs to at least one sub-grid.
+    pub fn is_active(&self, (row, col): Pos) -> bool {
+        let size = self.shape.size;
+        self.grids.iter().any(|&(r, c)| (r..r + size).contains(&row) && (c..c + size).contains(&col))
+    }
+
+    /// Every cell belonging to a sub-grid, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = Pos> + '_ {
+        (0..self.height)
+            .flat_map(move |row| (0..self.width).map(move |col| (row, col)))
+            .filter(|&pos| self.is_active(pos))
+    }
+
+    /// Changes a cell, recording its previous value so that it can be undone by `restore`.
+    /// Writing through the index operator isn't recorded, and is meant for setting up the grid.
+    pub fn set(&mut self, (row, col): Pos, value: Value) {
+        let index = row * self.width + col;
+
+        if self.cells[index] != value {
+            self.trail.push((index, self.cells[index]));
+            self.update_boards(index, self.cells[index], value);
+            self.cells[index] = value;
+        }
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.trail.len(), self.var_trail.len())
+    }
+
+    /// Undoes every change made through `set` and `restrict_var` since the checkpoint was taken.
+    pub fn restore(&mut self, Checkpoint(len, var_len): Checkpoint) {
+        while self.trail.len() > len {
+            let (index, value) = self.trail.pop().unwrap();
+            self.update_boards(index, self.cells[index], value);
+            self.cells[index] = value;
+        }
+
+        while self.var_trail.len() > var_len {
+            let (index, domain) = self.var_trail.pop().unwrap();
+            self.vars[index] = domain;
+        }
+    }
+
+    /// Forgets the changes made so far, which can no longer be restored.
+    fn clear_trail(&mut self) {
+        self.trail.clear();
+        self.var_trail.clear();
+    }
+
+    /// The cells changed since the checkpoint was taken, possibly repeated.
+    fn changes_since(&self, Checkpoint(len, _): Checkpoint) -> impl Iterator<Item = Pos> + '_ {
+        self.trail[len..].iter().map(|&(index, _)| (index / self.width, index % self.width))
+    }
+
+    /// The auxiliary variables changed since the checkpoint was taken, possibly repeated.
+    fn var_changes_since(&self, Checkpoint(_, len): Checkpoint) -> impl Iterator<Item = Var> + '_ {
+        self.var_trail[len..].iter().map(|&(index, _)| *self.vars.get_index(index).unwrap().0)
+    }
+
+    /// Adds an auxiliary variable that may take any value of the domain, unless the grid has it already.
+    /// Solvers declare the variables of their rules before searching.
+    pub fn declare(&mut self, var: Var, domain: Domain) {
+        self.vars.entry(var).or_insert(domain);
+    }
+
+    /// Every auxiliary variable, in the order they were declared.
+    pub fn vars(&self) -> impl Iterator<Item = (Var, Domain)> + '_ {
+        self.vars.iter().map(|(&var, &domain)| (var, domain))
+    }
+
+    /// Removes every value of the variable outside `allowed`, recording its previous domain
+    /// so that it can be undone by `restore`. Returns false if no value is left.
+    pub fn restrict_var(&mut self, var: Var, allowed: Domain) -> bool {
+        let (index, _, &mut domain) = self.vars.get_full_mut(&var).unwrap_or_else(|| panic!("Undeclared {}", var));
+        let restricted = domain & allowed;
+
+        if restricted != domain {
+            self.var_trail.push((index, domain));
+            self.vars[index] = restricted;
+        }
+
+        !restricted.is_empty()
+    }
+
+    pub fn entry(&self, pos: Pos) -> Entry {
+        self[pos].into()
+    }
+
+    /// The digits the cell may still hold: every digit for an empty cell, and only its digit once placed.
+    pub fn candidates(&self, pos: Pos) -> Mask {
+        Sudoku::mask_of(self[pos], self.size())
+    }
+
+    fn mask_of(value: Value, size: usize) -> Mask {
+        match value.into() {
+            Entry::Empty => Mask::full(size),
+            Entry::Digit(digit) => Mask::from_digits([digit.get()]),
+            Entry::Pencil(mask) => mask,
+        }
+    }
+
+    /// Removes every candidate of the cell outside `allowed` through `set`,
+    /// returning false if none are left or its digit isn't allowed.
+    pub fn restrict(&mut self, pos: Pos, allowed: Mask) -> bool {
+        let mask = self.candidates(pos) & allowed;
+
+        match self.entry(pos) {
+            Entry::Digit(_) => mask.count() == 1,
+            _ if mask.count() == 0 => false,
+            Entry::Empty if mask == Mask::full(self.size()) => true,
+            _ => {
+                self.set(pos, mask.into());
+                true
+            }
+        }
+    }
+
+    /// The cells where each digit is still possible, placed or as a candidate, indexed by digit - 1.
+    /// The boards follow `set` and `restore`, and are rebuilt after writes through the index operator.
+    pub fn boards(&mut self) -> &[Bitboard] {
+        if self.stale {
+            let cells = self.cells.len();
+            self.boards = vec![Bitboard::new(cells); self.size()];
+
+            for pos in self.positions().collect::<Vec<_>>() {
+                let index = self.cell_index(pos);
+
+                for digit in self.candidates(pos).digits() {
+                    self.boards[digit as usize - 1].insert(index);
+                }
+            }
+
+            self.stale = false;
+        }
+
+        &self.boards
+    }
+
+    /// The board holding the given cells.
+    pub fn board_of(&self, cells: &[Pos]) -> Bitboard {
+        Bitboard::from_indices(self.cells.len(), cells.iter().map(|&pos| self.cell_index(pos)))
+    }
+
+    /// The index of the cell in the boards.
+    pub fn cell_index(&self, (row, col): Pos) -> usize {
+        row * self.width + col
+    }
+
+    pub fn cell_pos(&self, index: usize) -> Pos {
+        (index / self.width, index % self.width)
+    }
+
+    fn update_boards(&mut self, index: usize, old: Value, new: Value) {
+        if self.stale {
+            return;
+        }
+
+        let size = self.size();
+        let changed = Sudoku::mask_of(old, size).0 ^ Sudoku::mask_of(new, size).0;
+
+        for digit in Mask(changed).digits() {
+            self.boards[digit as usize - 1].toggle(index);
+        }
+    }
+
     fn cell_entry(&self, pos: Pos) -> CellEntry {
         (pos, self[pos].into())
     }
 }
 
+impl From<[[Value; 9]; 9]> for Sudoku {
+    fn from(grid: [[Value; 9]; 9]) -> Self {
+        Sudoku { cells: grid.concat(), ..Sudoku::default() }
+    }
+}
+
 impl Index<Pos> for Sudoku {
     type Output = Value;
 
     fn index(&self, (row, col): Pos) -> &Self::Output {
-        &self.0[row][col]
+        &self.cells[row * self.width + col]
+    }
+}
+
+impl Index<Var> for Sudoku {
+    type Output = Domain;
+
+    fn index(&self, var: Var) -> &Self::Output {
+        self.vars.get(&var).unwrap_or_else(|| panic!("Undeclared {}", var))
     }
 }
 
 impl IndexMut<Pos> for Sudoku {
     fn index_mut(&mut self, (row, col): Pos) -> &mut Self::Output {
-        &mut self.0[row][col]
+        self.stale = true;
+        &mut self.cells[row * self.width + col]
     }
 }
 
 impl Display for Sudoku {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, " _________________")?;
-        for row in 0..9 {
-            write!(f, "|")?;
-            let empty = if (row + 1) % 3 == 0 { '_' } else { ' ' };
-
-            for col in 0..9 {
-                match self[(row, col)].into() {
-                    Entry::Empty => write!(f, "{}", empty)?,
-                    Entry::Digit(d) => write!(f, "{}", d)?,
-                    Entry::Pencil(_) => write!(f, ".")?,
+        self.write_grid(f, |_| None)
+    }
+}
+
+impl Sudoku {
+    /// Draws the grid, showing `mark` on the cells without a digit whenever it returns a character.
+    pub fn write_grid(&self, f: &mut std::fmt::Formatter<'_>, mark: impl Fn(Pos) -> Option<char>) -> std::fmt::Result {
+        let Shape { box_height, box_width, .. } = self.shape;
+        let active = |row: usize, col: usize| row < self.height && col < self.width && self.is_active((row, col));
+
+        // Void cells draw the top border of the sub-grid below them.
+        let floor = |row: usize, col: usize| {
+            if active(row, col) { (row + 1).is_multiple_of(box_height) } else { active(row + 1, col) }
+        };
+
+        write!(f, " ")?;
+        for col in 0..self.width {
+            let top = active(0, col);
+            write!(f, "{}", if top { '_' } else { ' ' })?;
+
+            if col + 1 < self.width {
+                write!(f, "{}", if top && active(0, col + 1) { '_' } else { ' ' })?;
+            }
+        }
+        writeln!(f)?;
+
+        for row in 0..self.height {
+            write!(f, "{}", if active(row, 0) { '|' } else { ' ' })?;
+
+            for col in 0..self.width {
+                let empty = if floor(row, col) { '_' } else { ' ' };
+
+                if !active(row, col) {
+                    write!(f, "{}", empty)?;
+                } else {
+                    match (self[(row, col)].into(), mark((row, col))) {
+                        (Entry::Digit(d), _) => write!(f, "{}", digit_symbol(d.get()))?,
+                        (_, Some(c)) => write!(f, "{}", c)?,
+                        (Entry::Empty, None) => write!(f, "{}", empty)?,
+                        (Entry::Pencil(_), None) => write!(f, ".")?,
+                    }
                 }
 
-                if (col + 1) % 3 == 0 {
+                let (left, right) = (active(row, col), active(row, col + 1));
+
+                if (left && (col + 1) % box_width == 0) || left != right {
                     write!(f, "|")?;
-                } else {
-                    write!(f, "{}", empty)?;
+                } else if col + 1 < self.width {
+                    write!(f, "{}", if floor(row, col) && floor(row, col + 1) { '_' } else { ' ' })?;
                 }
             }
             writeln!(f)?;
@@ -144,93 +574,84 @@ impl Display for Sudoku {
     }
 }
 
-pub enum State {
-    Solved,
-    Unsolvable,
-    Stuck,
-    LimitReached,
+/// The optional constraints applied on top of the standard row, column and box rules.
+#[derive(Debug, Clone, Default)]
+pub struct Ruleset {
+    pub sum_sequence: bool,
+    pub anti_knight: bool,
+    pub anti_king: bool,
+    pub diagonal: bool,
+    /// Irregular regions replacing the boxes.
+    pub regions: Option<Regions>,
+    /// Clues written outside the grid.
+    pub clues: Vec<Clue>,
+    /// Cells restricted to odd, even, high or low digits.
+    pub shades: Vec<(Pos, Shade)>,
+    /// Cages added to the sum-sequence layout, such as its triplet cages, read by the sequence rule and the CNF encoding.
+    pub cages: Vec<Vec<Pos>>,
 }
 
-pub struct SolveResult(pub usize, pub Sudoku, pub State);
-
-fn solve(mut sudoku: Sudoku, rules: &mut [&mut dyn rules::Rule], counter: &mut usize, limit: usize) -> SolveResult {
-    loop {
-        let old_sudoku = sudoku.clone();
-
-        for _ in 0..9 {
-            for rule in &mut *rules {
-                *counter += 1;
-                if rule.update_cells(&mut sudoku).is_err() {
-                    return SolveResult(*counter, sudoku, State::Unsolvable);
-                }
-            }
+impl Ruleset {
+    /// Combines two rulesets, enabling every constraint turned on in either.
+    pub fn merge(&self, other: &Ruleset) -> Ruleset {
+        Ruleset {
+            sum_sequence: self.sum_sequence || other.sum_sequence,
+            anti_knight: self.anti_knight || other.anti_knight,
+            anti_king: self.anti_king || other.anti_king,
+            diagonal: self.diagonal || other.diagonal,
+            regions: other.regions.clone().or_else(|| self.regions.clone()),
+            clues: self.clues.iter().chain(&other.clues).copied().collect(),
+            shades: self.shades.iter().chain(&other.shades).copied().collect(),
+            cages: self.cages.iter().chain(&other.cages).cloned().collect(),
         }
+    }
 
-        if old_sudoku == sudoku {
-            let mut progress = false;
-            for i in 0..9 {
-                for j in 0..9 {
-                    let mut cell_value = sudoku[(i, j)];
-                    if let Entry::Pencil(pm) = cell_value.into() {
-                        for d in 0..9 {
-                            if !pm[d] {
-                                continue;
-                            }
-
-                            let mut test_sudoku = sudoku.clone();
-                            test_sudoku[(i, j)] = d as Value + 1;
-
-                            if let SolveResult(_, _, State::Unsolvable) = solve(test_sudoku, rules, counter, limit) {
-                                progress = true;
-                                cell_value &= !(1 << d);
-                                sudoku[(i, j)] = cell_value;
-                            }
-                        }
-                    }
-                }
-            }
+    /// Checks that every constraint fits the grid.
+    pub fn validate(&self, sudoku: &Sudoku) -> Result<(), String> {
+        let shape = sudoku.shape();
+        let single = sudoku.grids().len() == 1;
 
-            if progress {
-                continue;
-            } else {
-                return SolveResult(*counter, sudoku, State::Stuck);
-            }
+        if self.sum_sequence && (shape != Shape::default() || !single) {
+            return Err("Sum-sequence rules require a single 9x9 grid".into());
         }
 
-        let done = sudoku.0.iter().all(|row| row.iter().all(|&cell| matches!(cell.into(), Entry::Digit(_))));
-
-        if done {
-            return SolveResult(*counter, sudoku, State::Solved);
+        if !single && (self.regions.is_some() || !self.clues.is_empty()) {
+            return Err("Regions and outside clues require a single grid".into());
         }
 
-        if *counter >= limit {
-            return SolveResult(*counter, sudoku, State::LimitReached);
+        if let Some(regions) = &self.regions
+            && regions.size() != shape.size
+        {
+            return Err(format!("Regions are {0}x{0}, but the grid is {1}x{1}", regions.size(), shape.size));
         }
-    }
-}
 
-pub fn solve_sudoku(sudoku: Sudoku, sum_sequence: bool, limit: usize) -> SolveResult {
-    use rules::*;
+        if let Some(clue) = self.clues.iter().find(|clue| clue.index >= shape.size) {
+            return Err(format!("Clue index {} is outside the {1}x{1} grid", clue.index + 1, shape.size));
+        }
 
-    let mut row_rule = RowRule::default();
-    let mut col_rule = ColRule::default();
-    let mut box_rule = BoxRule::default();
-    let mut rules: Vec<&mut dyn Rule> = vec![&mut box_rule, &mut col_rule, &mut row_rule];
+        if let Some(((row, col), _)) = self
+            .shades
+            .iter()
+            .find(|(pos, _)| pos.0 >= sudoku.height() || pos.1 >= sudoku.width() || !sudoku.is_active(*pos))
+        {
+            return Err(format!("Restricted cell at row {}, col {} is outside the grid", row + 1, col + 1));
+        }
 
-    let mut counter = 0;
+        if !self.cages.is_empty() && !self.sum_sequence {
+            return Err("Cages require the sum-sequence rules".into());
+        }
 
-    if sum_sequence {
-        let mut cage_rule = CageRule::default();
-        let mut palindrome_rule = PalindromeRule::default();
-        let mut set_cage_rule = SetCageRule::default();
+        if let Some((row, col)) = self.cages.iter().flatten().find(|&&(row, col)| row >= 9 || col >= 9) {
+            return Err(format!("Cage cell at row {}, col {} is outside the grid", row + 1, col + 1));
+        }
 
-        rules.push(&mut cage_rule);
-        rules.push(&mut palindrome_rule);
-        rules.push(&mut set_cage_rule);
-        return solve(sudoku, &mut rules, &mut counter, limit);
+        Ok(())
     }
+}
 
-    solve(sudoku, &mut rules, &mut counter, limit)
+/// Solves the grid with the standard rules of its layout and every constraint of the ruleset.
+pub fn solve_sudoku(sudoku: Sudoku, ruleset: &Ruleset, limit: usize) -> SolveResult {
+    Solver::from_ruleset(&sudoku, ruleset).with_limit(limit).solve(sudoku)
 }
 
 #[cfg(test)]
@@ -239,7 +660,7 @@ mod tests {
 
     #[test]
     fn test_engine() {
-        let sudoku = Sudoku([
+        let sudoku = Sudoku::from([
             [0, 6, 0, 8, 0, 0, 0, 0, 0],
             [4, 0, 0, 0, 0, 5, 0, 8, 0],
             [0, 3, 7, 0, 0, 0, 0, 0, 0],
@@ -251,7 +672,7 @@ mod tests {
             [0, 0, 1, 0, 7, 0, 0, 0, 5],
         ]);
 
-        let solution = Sudoku([
+        let solution = Sudoku::from([
             [9, 6, 5, 8, 4, 2, 1, 7, 3],
             [4, 1, 2, 7, 3, 5, 9, 8, 6],
             [8, 3, 7, 9, 1, 6, 5, 4, 2],
@@ -265,7 +686,7 @@ mod tests {
 
         let start = std::time::Instant::now();
 
-        let result = solve_sudoku(sudoku, false, 2_000_000);
+        let result = solve_sudoku(sudoku, &Ruleset::default(), 2_000_000);
 
         let duration = start.elapsed();
         println!("Time elapsed in solve_sudoku() is: {:?}", duration);
@@ -278,7 +699,7 @@ mod tests {
                 println!("Resulting Sudoku:\n{}", sudoku);
                 assert_eq!(sudoku, solution);
             }
-            State::Unsolvable => {
+            State::Unsolvable(_) => {
                 println!("Sudoku is unsolvable");
                 panic!("Should have been solvable");
             }
@@ -286,18 +707,166 @@ mod tests {
                 println!("No progress made after {} iterations", counter);
                 panic!("Should have been solvable");
             }
-            State::LimitReached => {
-                println!("Reached iteration limit: {}", counter);
+            state => {
+                println!("Stopped with {:?} after {} iterations", state, counter);
                 println!("Resulting Sudoku:\n{}", sudoku);
                 panic!("Should have been solved before reaching limit");
             }
         };
     }
 
+    #[test]
+    fn test_engine_6x6() {
+        #[rustfmt::skip]
+        let cells = vec![
+            1, 2, 0, 4, 0, 0,
+            0, 5, 0, 0, 2, 3,
+            2, 0, 1, 0, 6, 0,
+            0, 6, 0, 2, 0, 1,
+            3, 0, 2, 0, 4, 0,
+            6, 0, 0, 3, 0, 2,
+        ];
+
+        #[rustfmt::skip]
+        let solution = vec![
+            1, 2, 3, 4, 5, 6,
+            4, 5, 6, 1, 2, 3,
+            2, 3, 1, 5, 6, 4,
+            5, 6, 4, 2, 3, 1,
+            3, 1, 2, 6, 4, 5,
+            6, 4, 5, 3, 1, 2,
+        ];
+
+        let shape = Shape::new(6).unwrap();
+        assert_eq!(shape, Shape { size: 6, box_height: 2, box_width: 3 });
+
+        let SolveResult(_, sudoku, state) =
+            solve_sudoku(Sudoku { cells, ..Sudoku::new(shape) }, &Ruleset::default(), 2_000_000);
+
+        assert!(matches!(state, State::Solved));
+        assert_eq!(sudoku, Sudoku { cells: solution, ..Sudoku::new(shape) });
+    }
+
+    #[test]
+    fn test_twin_grids() {
+        let grid = Sudoku::from([
+            [9, 6, 5, 8, 4, 2, 1, 7, 3],
+            [4, 1, 2, 7, 3, 5, 9, 8, 6],
+            [8, 3, 7, 9, 1, 6, 5, 4, 2],
+            [3, 7, 9, 1, 2, 8, 6, 5, 4],
+            [2, 5, 4, 6, 9, 7, 3, 1, 8],
+            [1, 8, 6, 4, 5, 3, 2, 9, 7],
+            [7, 2, 3, 5, 8, 1, 4, 6, 9],
+            [5, 9, 8, 2, 6, 4, 7, 3, 1],
+            [6, 4, 1, 3, 7, 9, 8, 2, 5],
+        ]);
+
+        // The second grid moves the last band and stack of the first one to the front,
+        // so its top-left box matches the shared bottom-right box.
+        let mut solution = Sudoku::with_grids(Shape::default(), vec![(0, 0), (6, 6)]).unwrap();
+
+        for (row, col) in grid.positions() {
+            solution[(row, col)] = grid[(row, col)];
+            solution[(row + 6, col + 6)] = grid[((row + 6) % 9, (col + 6) % 9)];
+        }
+
+        let mut sudoku = solution.clone();
+
+        for (row, col) in solution.positions().filter(|(row, col)| (row + col) % 3 == 0) {
+            sudoku[(row, col)] = 0;
+        }
+
+        let SolveResult(_, result, state) = solve_sudoku(sudoku, &Ruleset::default(), 2_000_000);
+
+        assert!(matches!(state, State::Solved));
+        assert_eq!(result, solution);
+    }
+
+    #[test]
+    fn test_shaded_cells() {
+        let sudoku = Sudoku::from([
+            [0, 6, 0, 8, 0, 0, 0, 0, 0],
+            [4, 0, 0, 0, 0, 5, 0, 8, 0],
+            [0, 3, 7, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 4, 0, 9, 7, 3, 0, 0],
+            [0, 0, 0, 0, 5, 3, 0, 0, 0],
+            [7, 0, 0, 0, 0, 1, 4, 6, 0],
+            [5, 9, 0, 0, 0, 4, 7, 3, 0],
+            [0, 0, 1, 0, 7, 0, 0, 0, 5],
+        ]);
+
+        let ruleset = Ruleset { shades: vec![((0, 0), Shade::High), ((0, 2), Shade::Odd)], ..Default::default() };
+        let SolveResult(_, result, state) = solve_sudoku(sudoku.clone(), &ruleset, 2_000_000);
+
+        assert!(matches!(state, State::Solved));
+        assert_eq!(result[(0, 0)], 9);
+
+        let ruleset = Ruleset { shades: vec![((0, 1), Shade::Odd)], ..Default::default() };
+        let SolveResult(_, _, state) = solve_sudoku(sudoku, &ruleset, 2_000_000);
+
+        assert!(matches!(state, State::Unsolvable(_)));
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let mut sudoku = Sudoku::default();
+        sudoku[(0, 0)] = 5;
+
+        let checkpoint = sudoku.checkpoint();
+        sudoku.set((0, 1), 3);
+        sudoku.set((0, 1), Mask(0b11).into());
+        sudoku.set((4, 4), 9);
+
+        assert_eq!(sudoku.changes_since(checkpoint).collect::<Vec<_>>(), [(0, 1), (0, 1), (4, 4)]);
+
+        sudoku.restore(checkpoint);
+
+        let mut expected = Sudoku::default();
+        expected[(0, 0)] = 5;
+        assert_eq!(sudoku, expected);
+    }
+
+    #[test]
+    fn test_boards_follow_changes() {
+        let mut sudoku = Sudoku::default();
+        sudoku[(0, 0)] = 5;
+
+        assert!(sudoku.boards()[4].contains(0) && !sudoku.boards()[3].contains(0));
+        assert_eq!(sudoku.boards()[0].count(), 80);
+
+        let checkpoint = sudoku.checkpoint();
+        sudoku.set((0, 1), Mask(0b11).into());
+        sudoku.set((4, 4), 9);
+        assert!(sudoku.boards()[1].contains(1) && !sudoku.boards()[2].contains(1));
+        let mut rebuilt = Sudoku { stale: true, ..sudoku.clone() };
+        assert_eq!(sudoku.boards(), rebuilt.boards());
+
+        sudoku.restore(checkpoint);
+        let mut rebuilt = Sudoku { stale: true, ..sudoku.clone() };
+        assert_eq!(sudoku.boards(), rebuilt.boards());
+    }
+
+    #[test]
+    fn test_restrict_candidates() {
+        let mut sudoku = Sudoku::default();
+        let odd = Mask::from_digits([1, 3, 5, 7, 9]);
+
+        assert!(sudoku.restrict((0, 0), odd));
+        assert_eq!(sudoku.candidates((0, 0)).digits().collect::<Vec<_>>(), [1, 3, 5, 7, 9]);
+
+        assert!(sudoku.restrict((0, 0), Mask::from_digits([2, 3])));
+        assert_eq!(sudoku.entry((0, 0)), Entry::Pencil(Mask::from_digits([3])));
+
+        sudoku[(0, 1)] = 4;
+        assert!(!sudoku.restrict((0, 1), odd));
+        assert!(!sudoku.restrict((0, 0), Mask::from_digits([2])));
+    }
+
     #[test]
     fn test_extract_digit_or_pencilmark_mask() {
-        let digit = 0b0000_0000_0000_0101;
-        let marks = 0b1000_0001_0101_0110;
+        let digit = 0x0000_0005;
+        let marks = 0x8000_0156;
 
         assert_eq!(Entry::from(digit), Entry::Digit(NonZeroU8::new(5).unwrap()));
         assert_eq!(Entry::from(marks), Entry::Pencil(Mask(0b1_0101_0110)));
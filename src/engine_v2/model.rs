@@ -0,0 +1,312 @@
+use super::{Mask, Pos, Sudoku};
+use std::fmt::Display;
+use std::ops::BitAnd;
+
+/// The most values a `Domain` can span, from its lowest to its highest.
+const MAX_SPAN: i32 = 64;
+
+/// An auxiliary variable of the grid, holding an integer rather than a digit, such as the sum of a cage.
+/// Variables are told apart by name and index, so rules naming the same variable share it.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, PartialOrd, Ord)]
+pub struct Var {
+    pub name: &'static str,
+    /// The position of the variable in a family of them, such as the sums of every cage.
+    pub index: Option<usize>,
+}
+
+impl Var {
+    pub const fn new(name: &'static str) -> Self {
+        Var { name, index: None }
+    }
+
+    pub const fn indexed(name: &'static str, index: usize) -> Self {
+        Var { name, index: Some(index) }
+    }
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{} {}", self.name, index + 1),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The values an auxiliary variable may still take, spanning at most 64 consecutive integers.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Domain {
+    /// The value of bit 0, the lowest value unless the domain is empty.
+    base: i32,
+    bits: u64,
+}
+
+impl Domain {
+    /// Every value from `min` to `max`, both included.
+    pub fn range(min: i32, max: i32) -> Domain {
+        if max < min {
+            return Domain::default();
+        }
+
+        assert!(max - min < MAX_SPAN, "A domain spans at most {} values", MAX_SPAN);
+        Domain { base: min, bits: u64::MAX >> (MAX_SPAN - 1 - (max - min)) }
+    }
+
+    pub fn single(value: i32) -> Domain {
+        Domain::range(value, value)
+    }
+
+    pub fn from_values(values: impl IntoIterator<Item = i32>) -> Domain {
+        let values: Vec<i32> = values.into_iter().collect();
+        let Some(&base) = values.iter().min() else {
+            return Domain::default();
+        };
+
+        let mut domain = Domain { base, bits: 0 };
+        for value in values {
+            assert!(value - base < MAX_SPAN, "A domain spans at most {} values", MAX_SPAN);
+            domain.bits |= 1 << (value - base);
+        }
+
+        domain
+    }
+
+    pub fn contains(self, value: i32) -> bool {
+        (0..MAX_SPAN).contains(&(value - self.base)) && self.bits & (1 << (value - self.base)) != 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.bits.count_ones()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+
+    /// The values of the domain, in increasing order.
+    pub fn values(self) -> impl DoubleEndedIterator<Item = i32> {
+        (0..MAX_SPAN).filter(move |i| self.bits & (1 << i) != 0).map(move |i| self.base + i)
+    }
+
+    pub fn min(self) -> Option<i32> {
+        (!self.is_empty()).then(|| self.base + self.bits.trailing_zeros() as i32)
+    }
+
+    pub fn max(self) -> Option<i32> {
+        (!self.is_empty()).then(|| self.base + MAX_SPAN - 1 - self.bits.leading_zeros() as i32)
+    }
+
+    /// The only value left, if there is just one.
+    pub fn value(self) -> Option<i32> {
+        (self.count() == 1).then(|| self.base + self.bits.trailing_zeros() as i32)
+    }
+
+    /// The values for which `keep` returns true.
+    pub fn filter(self, keep: impl Fn(i32) -> bool) -> Domain {
+        let mut bits = self.bits;
+        self.values().filter(|&value| !keep(value)).for_each(|value| bits &= !(1 << (value - self.base)));
+
+        match bits {
+            0 => Domain::default(),
+            _ => Domain { base: self.base + bits.trailing_zeros() as i32, bits: bits >> bits.trailing_zeros() },
+        }
+    }
+}
+
+impl BitAnd for Domain {
+    type Output = Domain;
+
+    fn bitand(self, other: Domain) -> Domain {
+        self.filter(|value| other.contains(value))
+    }
+}
+
+/// Lists the values, writing runs of three or more as `first-last`.
+impl Display for Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut runs: Vec<(i32, i32)> = vec![];
+
+        for value in self.values() {
+            match runs.last_mut() {
+                Some((_, last)) if *last + 1 == value => *last = value,
+                _ => runs.push((value, value)),
+            }
+        }
+
+        let runs: Vec<String> = runs
+            .into_iter()
+            .flat_map(|(first, last)| match last - first {
+                0 => vec![first.to_string()],
+                1 => vec![first.to_string(), last.to_string()],
+                _ => vec![format!("{}-{}", first, last)],
+            })
+            .collect();
+
+        write!(f, "{{{}}}", runs.join(", "))
+    }
+}
+
+/// A cell or an auxiliary variable, as a term of a `Linear` equation.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Term {
+    Cell(Pos),
+    Var(Var),
+}
+
+/// The equation `Σ coefficient × term = total`, linking cells and auxiliary variables.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Linear {
+    pub terms: Vec<(i32, Term)>,
+    pub total: i32,
+}
+
+impl Linear {
+    pub fn new(terms: Vec<(i32, Term)>, total: i32) -> Self {
+        Linear { terms, total }
+    }
+
+    /// The cells of the equation, for the units of a rule.
+    pub fn cells(&self) -> Vec<Pos> {
+        self.terms.iter().filter_map(|&(_, term)| if let Term::Cell(pos) = term { Some(pos) } else { None }).collect()
+    }
+
+    /// The auxiliary variables of the equation, for the links of a rule.
+    pub fn vars(&self) -> Vec<Var> {
+        self.terms.iter().filter_map(|&(_, term)| if let Term::Var(var) = term { Some(var) } else { None }).collect()
+    }
+
+    /// Removes every value of a term that no values of the other terms complete into the total,
+    /// through `Sudoku::restrict` and `Sudoku::restrict_var`. Returns false if the equation can't hold.
+    ///
+    /// The sums reachable by the terms before and after each one are built up term by term,
+    /// so a value is kept when the total minus its weight is the sum of one of each.
+    /// Terms are taken as independent, so a cell appearing twice can be given two different digits.
+    pub fn propagate(&self, sudoku: &mut Sudoku) -> bool {
+        let weights: Vec<Vec<i32>> = self
+            .terms
+            .iter()
+            .map(|&(coefficient, term)| values(sudoku, term).into_iter().map(|value| coefficient * value).collect())
+            .collect();
+
+        // The sums of the terms before each one, then of the terms from each one on.
+        let mut before = vec![Reach::zero()];
+        weights.iter().for_each(|weights| before.push(before.last().unwrap().add(weights)));
+
+        let mut after = vec![Reach::zero()];
+        weights.iter().rev().for_each(|weights| after.push(after.last().unwrap().add(weights)));
+        after.reverse();
+
+        if !before[self.terms.len()].contains(self.total) {
+            return false;
+        }
+
+        for (i, &(coefficient, term)) in self.terms.iter().enumerate() {
+            let supported = |weight: i32| before[i].sums().any(|sum| after[i + 1].contains(self.total - sum - weight));
+            let kept: Vec<i32> =
+                values(sudoku, term).into_iter().filter(|&value| supported(coefficient * value)).collect();
+
+            let restricted = match term {
+                Term::Cell(pos) => sudoku.restrict(pos, Mask::from_digits(kept.iter().map(|&digit| digit as u8))),
+                Term::Var(var) => sudoku.restrict_var(var, Domain::from_values(kept)),
+            };
+
+            if !restricted {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// The values a term may still take.
+fn values(sudoku: &Sudoku, term: Term) -> Vec<i32> {
+    match term {
+        Term::Cell(pos) => sudoku.candidates(pos).digits().map(i32::from).collect(),
+        Term::Var(var) => sudoku[var].values().collect(),
+    }
+}
+
+/// The sums some terms can reach, as flags from the lowest one they could.
+struct Reach {
+    low: i32,
+    flags: Vec<bool>,
+}
+
+impl Reach {
+    /// The empty sum.
+    fn zero() -> Self {
+        Reach { low: 0, flags: vec![true] }
+    }
+
+    /// The sums reached by adding one of the weights to one of these.
+    fn add(&self, weights: &[i32]) -> Reach {
+        let (Some(&min), Some(&max)) = (weights.iter().min(), weights.iter().max()) else {
+            return Reach { low: self.low, flags: vec![] };
+        };
+
+        let mut flags = vec![false; self.flags.len() + (max - min) as usize];
+
+        for (i, _) in self.flags.iter().enumerate().filter(|(_, reached)| **reached) {
+            for &weight in weights {
+                flags[i + (weight - min) as usize] = true;
+            }
+        }
+
+        Reach { low: self.low + min, flags }
+    }
+
+    fn contains(&self, sum: i32) -> bool {
+        usize::try_from(sum - self.low).is_ok_and(|i| self.flags.get(i).copied().unwrap_or(false))
+    }
+
+    fn sums(&self) -> impl Iterator<Item = i32> + '_ {
+        self.flags.iter().enumerate().filter(|(_, reached)| **reached).map(|(i, _)| self.low + i as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain() {
+        let domain = Domain::range(3, 7);
+        assert_eq!(domain.values().collect::<Vec<_>>(), [3, 4, 5, 6, 7]);
+        assert_eq!((domain.min(), domain.max(), domain.value()), (Some(3), Some(7), None));
+
+        let even = domain & Domain::from_values([-2, 4, 6, 8]);
+        assert_eq!(even.to_string(), "{4, 6}");
+        assert_eq!(Domain::from_values([1, 2, 4, 5, 6, 9]).to_string(), "{1, 2, 4-6, 9}");
+        assert_eq!(even.filter(|value| value > 5).value(), Some(6));
+        assert!(Domain::range(2, 1).is_empty() && Domain::default().min().is_none());
+        assert_eq!(Domain::range(0, 63).count(), 64);
+    }
+
+    #[test]
+    fn test_linear_propagation() {
+        let mut sudoku = Sudoku::default();
+        let (sum, start) = (Var::indexed("sum", 0), Var::new("start"));
+        sudoku.declare(sum, Domain::range(2, 18));
+        sudoku.declare(start, Domain::range(0, 40));
+
+        sudoku[(0, 0)] = 9;
+        sudoku[(0, 1)] = Mask::from_digits([1, 2, 7]).into();
+
+        // r1c1 + r1c2 = sum, and 15 × start = 29 + sum.
+        let cage = Linear::new(vec![(1, Term::Cell((0, 0))), (1, Term::Cell((0, 1))), (-1, Term::Var(sum))], 0);
+        let sequence = Linear::new(vec![(15, Term::Var(start)), (-1, Term::Var(sum))], 29);
+
+        assert!(cage.propagate(&mut sudoku));
+        assert_eq!(sudoku[sum], Domain::from_values([10, 11, 16]));
+
+        assert!(sequence.propagate(&mut sudoku));
+        assert_eq!((sudoku[start].value(), sudoku[sum].value()), (Some(3), Some(16)));
+
+        assert!(cage.propagate(&mut sudoku));
+        assert_eq!(sudoku.candidates((0, 1)), Mask::from_digits([7]));
+
+        sudoku.set((0, 1), 1);
+        assert!(!cage.propagate(&mut sudoku));
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::Mutex;
+use std::thread;
+
+/// Maps the items on up to `threads` scoped threads, each taking the next item as soon as it is free.
+/// The results keep the order of the items.
+pub(super) fn parallel_map<T: Send, R: Send>(items: Vec<T>, threads: usize, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    let count = items.len();
+    let pending = Mutex::new(items.into_iter().enumerate().rev().collect::<Vec<_>>());
+    let done = Mutex::new(Vec::with_capacity(count));
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, count.max(1)) {
+            scope.spawn(|| {
+                loop {
+                    // Taken in its own statement so the lock is released before the item is processed.
+                    let next = pending.lock().unwrap().pop();
+                    let Some((index, item)) = next else { break };
+
+                    let result = f(item);
+                    done.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut done = done.into_inner().unwrap();
+    done.sort_unstable_by_key(|&(index, _)| index);
+    done.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_order() {
+        let items: Vec<usize> = (0..100).collect();
+
+        for threads in [1, 3, 8] {
+            assert_eq!(parallel_map(items.clone(), threads, |i| i * 2), (0..200).step_by(2).collect::<Vec<_>>());
+        }
+    }
+}
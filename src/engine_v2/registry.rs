@@ -0,0 +1,245 @@
+use super::rules::SequenceRule;
+use super::rules::{AntiKingRule, AntiKnightRule, CageRule, HiddenSinglesRule, PalindromeRule, SetCageRule, ShadeRule};
+use super::rules::{BoxRule, ColRule, DiagonalRule, FishRule, Regions, RowRule, Rule, clue_rules};
+use super::{Pos, Ruleset, Shape, Solver, Sudoku};
+use indexmap::IndexMap;
+use std::str::FromStr;
+
+/// Builds the instances of a rule for a grid, one per sub-grid for the standard constraints,
+/// or explains why the rule can't be used on it.
+pub type Builder = fn(&Sudoku, &Ruleset) -> Result<Vec<Box<dyn Rule>>, String>;
+
+/// A rule picked by name, optionally moved to another priority tier.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuleSpec {
+    pub name: String,
+    pub priority: Option<u8>,
+}
+
+impl RuleSpec {
+    pub fn new(name: impl Into<String>) -> Self {
+        RuleSpec { name: name.into(), priority: None }
+    }
+}
+
+/// Parses `name` or `name:priority`.
+impl FromStr for RuleSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, priority)) = s.trim().split_once(':') else {
+            return Ok(RuleSpec::new(s.trim()));
+        };
+
+        let priority =
+            priority.trim().parse().map_err(|_| format!("Invalid priority for rule {}: {}", name, priority))?;
+        Ok(RuleSpec { name: name.trim().to_string(), priority: Some(priority) })
+    }
+}
+
+/// The rules a solver can be assembled from, by name.
+#[derive(Default)]
+pub struct Registry {
+    builders: IndexMap<&'static str, Builder>,
+}
+
+impl Registry {
+    /// A registry without any rules.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Every rule of this crate. The clue rules are grouped under `clues`,
+    /// and `cell` restricts the odd, even, high and low cells.
+    pub fn standard() -> Self {
+        let mut registry = Registry::new();
+
+        registry.register("row", |sudoku, _| Ok(per_grid(sudoku, RowRule::new)));
+        registry.register("column", |sudoku, _| Ok(per_grid(sudoku, ColRule::new)));
+        registry.register("box", |sudoku, ruleset| {
+            let regions = regions(sudoku, ruleset);
+            Ok(per_grid(sudoku, |origin| BoxRule::new(&regions, origin)))
+        });
+        registry.register("diagonal", |sudoku, _| Ok(per_grid(sudoku, DiagonalRule::new)));
+        registry.register("hidden-singles", |sudoku, ruleset| {
+            let regions = regions(sudoku, ruleset);
+            Ok(per_grid(sudoku, |origin| HiddenSinglesRule::new(&regions, origin)))
+        });
+        registry.register("fish", |sudoku, _| Ok(per_grid(sudoku, FishRule::new)));
+        registry.register("anti-king", |_, _| Ok(vec![Box::new(AntiKingRule)]));
+        registry.register("anti-knight", |_, _| Ok(vec![Box::new(AntiKnightRule)]));
+        registry.register("cell", |_, ruleset| {
+            let shades = ruleset.shades.clone();
+            Ok(if shades.is_empty() { vec![] } else { vec![Box::new(ShadeRule::new(shades))] })
+        });
+        registry.register("clues", |sudoku, ruleset| Ok(clue_rules(&ruleset.clues, sudoku.size())));
+        registry.register("cage", |sudoku, _| sum_sequence(sudoku, || Box::new(CageRule::default())));
+        registry.register("palindrome", |sudoku, _| sum_sequence(sudoku, || Box::new(PalindromeRule::default())));
+        registry.register("cage-set", |sudoku, _| sum_sequence(sudoku, || Box::new(SetCageRule::default())));
+        registry.register("sequence", |sudoku, ruleset| {
+            sum_sequence(sudoku, || Box::new(SequenceRule::new(&ruleset.cages)))
+        });
+
+        registry
+    }
+
+    /// Adds a rule, replacing any other rule of the same name.
+    pub fn register(&mut self, name: &'static str, builder: Builder) {
+        self.builders.insert(name, builder);
+    }
+
+    /// The names of every rule, in the order they were registered.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.builders.keys().copied()
+    }
+
+    /// Builds the instances of the rule with the given name.
+    pub fn rules(&self, name: &str, sudoku: &Sudoku, ruleset: &Ruleset) -> Result<Vec<Box<dyn Rule>>, String> {
+        let Some(builder) = self.builders.get(name) else {
+            let names: Vec<_> = self.names().collect();
+            return Err(format!("Unknown rule: {}. Available rules: {}", name, names.join(", ")));
+        };
+
+        builder(sudoku, ruleset).map_err(|err| format!("Rule {}: {}", name, err))
+    }
+
+    /// A solver with the selected rules, in the order they are given.
+    pub fn build(&self, specs: &[RuleSpec], sudoku: &Sudoku, ruleset: &Ruleset) -> Result<Solver, String> {
+        let mut solver = Solver::new();
+
+        for spec in specs {
+            for rule in self.rules(&spec.name, sudoku, ruleset)? {
+                solver.add_boxed(rule, spec.priority);
+            }
+        }
+
+        Ok(solver)
+    }
+
+    /// The rules used when none are selected: the standard constraints of every grid,
+    /// then the ones the ruleset turns on, and last the hidden singles and fish in the tiers after them.
+    pub fn default_specs(ruleset: &Ruleset) -> Vec<RuleSpec> {
+        let mut names = vec!["cell", "box", "column", "row"];
+
+        if ruleset.diagonal {
+            names.push("diagonal");
+        }
+
+        if ruleset.anti_king {
+            names.push("anti-king");
+        }
+
+        if ruleset.anti_knight {
+            names.push("anti-knight");
+        }
+
+        names.push("clues");
+
+        if ruleset.sum_sequence {
+            names.extend(["cage", "palindrome", "cage-set", "sequence"]);
+        }
+
+        let mut specs: Vec<RuleSpec> = names.into_iter().map(RuleSpec::new).collect();
+        specs.push(RuleSpec { name: "hidden-singles".into(), priority: Some(1) });
+        specs.push(RuleSpec { name: "fish".into(), priority: Some(2) });
+        specs
+    }
+}
+
+/// The sets of cells holding distinct digits under the ruleset: the rows, columns, boxes or regions, diagonals
+/// and cage sets, then every pair of cells a king's or knight's move apart, for the engines that encode them directly.
+pub(crate) fn distinct_sets(sudoku: &Sudoku, ruleset: &Ruleset) -> Result<Vec<Vec<Pos>>, String> {
+    let registry = Registry::standard();
+    let mut sets = vec![];
+
+    for name in ["row", "column", "box"] {
+        sets.extend(registry.rules(name, sudoku, ruleset)?.iter().flat_map(|rule| rule.units(sudoku)));
+    }
+
+    for (name, enabled) in [("diagonal", ruleset.diagonal), ("cage-set", ruleset.sum_sequence)] {
+        if enabled {
+            sets.extend(registry.rules(name, sudoku, ruleset)?.iter().flat_map(|rule| rule.units(sudoku)));
+        }
+    }
+
+    for (name, enabled) in [("anti-king", ruleset.anti_king), ("anti-knight", ruleset.anti_knight)] {
+        if !enabled {
+            continue;
+        }
+
+        // Each unit is a cell followed by its neighbours.
+        for unit in registry.rules(name, sudoku, ruleset)?.iter().flat_map(|rule| rule.units(sudoku)) {
+            let center = unit[0];
+            sets.extend(unit[1..].iter().filter(|&&other| center < other).map(|&other| vec![center, other]));
+        }
+    }
+
+    Ok(sets)
+}
+
+fn per_grid<R: Rule + 'static>(sudoku: &Sudoku, rule: impl Fn(Pos) -> R) -> Vec<Box<dyn Rule>> {
+    sudoku.grids().iter().map(|&origin| Box::new(rule(origin)) as Box<dyn Rule>).collect()
+}
+
+fn regions(sudoku: &Sudoku, ruleset: &Ruleset) -> Regions {
+    ruleset.regions.clone().unwrap_or_else(|| Regions::boxes(sudoku.shape()))
+}
+
+/// The sum-sequence constraints sit at fixed cells of a single 9x9 grid.
+fn sum_sequence(sudoku: &Sudoku, rule: impl Fn() -> Box<dyn Rule>) -> Result<Vec<Box<dyn Rule>>, String> {
+    if sudoku.shape() != Shape::default() || sudoku.grids().len() != 1 {
+        return Err("requires a single 9x9 grid".into());
+    }
+
+    Ok(vec![rule()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule_spec() {
+        assert_eq!("row".parse(), Ok(RuleSpec::new("row")));
+        assert_eq!(" hidden-singles:3".parse(), Ok(RuleSpec { name: "hidden-singles".into(), priority: Some(3) }));
+        assert!("box:high".parse::<RuleSpec>().is_err());
+    }
+
+    #[test]
+    fn test_build_selected_rules() {
+        let sudoku = Sudoku::default();
+        let ruleset = Ruleset::default();
+        let specs: Vec<RuleSpec> = ["row", "column", "box", "hidden-singles:0"].map(|s| s.parse().unwrap()).to_vec();
+
+        let solver = Registry::standard().build(&specs, &sudoku, &ruleset).unwrap();
+        let names: Vec<_> = solver.rules().iter().map(|rule| rule.name()).collect();
+        assert_eq!(names, ["row", "column", "box", "hidden-singles"]);
+
+        let unknown = Registry::standard().build(&[RuleSpec::new("thermo")], &sudoku, &ruleset);
+        assert!(unknown.is_err_and(|err| err.starts_with("Unknown rule: thermo")));
+
+        let small = Sudoku::new(Shape::new(6).unwrap());
+        let misfit = Registry::standard().build(&[RuleSpec::new("palindrome")], &small, &ruleset);
+        assert_eq!(misfit.err(), Some("Rule palindrome: requires a single 9x9 grid".to_string()));
+    }
+
+    #[test]
+    fn test_names_match_registry() {
+        let sudoku = Sudoku::default();
+        let ruleset = Ruleset { sum_sequence: true, ..Ruleset::default() };
+        let registry = Registry::standard();
+
+        for name in registry.names().filter(|&name| name != "clues" && name != "cell") {
+            for rule in registry.rules(name, &sudoku, &ruleset).unwrap() {
+                assert_eq!(rule.name(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_specs_use_tiers() {
+        let specs = Registry::default_specs(&Ruleset::default());
+        let tiers: Vec<_> = specs.iter().filter_map(|spec| Some((spec.name.as_str(), spec.priority?))).collect();
+        assert_eq!(tiers, [("hidden-singles", 1), ("fish", 2)]);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::engine_v2::rules::{Contradiction, Rule};
+use crate::engine_v2::{Entry, Mask, Pos, Sudoku};
+
+const KING_OFFSETS: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+const KNIGHT_OFFSETS: [(isize, isize); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+
+/// A rule that removes every placed digit from the cells a king's move away.
+#[derive(Default)]
+pub struct AntiKingRule;
+
+/// A rule that removes every placed digit from the cells a knight's move away.
+#[derive(Default)]
+pub struct AntiKnightRule;
+
+impl Rule for AntiKingRule {
+    fn name(&self) -> &'static str {
+        "anti-king"
+    }
+
+    fn units(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        neighborhoods(sudoku, &KING_OFFSETS)
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        exclude_neighbors(self.name(), sudoku, cells)
+    }
+
+    fn explain(&self, _: usize, cells: &[Pos], sudoku: &Sudoku) -> String {
+        describe_center(sudoku, cells[0])
+    }
+}
+
+impl Rule for AntiKnightRule {
+    fn name(&self) -> &'static str {
+        "anti-knight"
+    }
+
+    fn units(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        neighborhoods(sudoku, &KNIGHT_OFFSETS)
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        exclude_neighbors(self.name(), sudoku, cells)
+    }
+
+    fn explain(&self, _: usize, cells: &[Pos], sudoku: &Sudoku) -> String {
+        describe_center(sudoku, cells[0])
+    }
+}
+
+/// Every cell of the grid, followed by the cells at the given offsets from it.
+fn neighborhoods(sudoku: &Sudoku, offsets: &[(isize, isize)]) -> Vec<Vec<Pos>> {
+    let neighbors = |(row, col): Pos| {
+        offsets
+            .iter()
+            .filter_map(move |&(dr, dc)| row.checked_add_signed(dr).zip(col.checked_add_signed(dc)))
+            .filter(|&(r, c)| r < sudoku.height() && c < sudoku.width() && sudoku.is_active((r, c)))
+    };
+
+    sudoku.positions().map(|pos| std::iter::once(pos).chain(neighbors(pos)).collect()).collect()
+}
+
+fn describe_center(sudoku: &Sudoku, (row, col): Pos) -> String {
+    format!("digit {} at r{}c{}", sudoku[(row, col)], row + 1, col + 1)
+}
+
+fn exclude_neighbors(rule: &'static str, sudoku: &mut Sudoku, cells: &[Pos]) -> Result<(), Contradiction> {
+    let Entry::Digit(digit) = sudoku[cells[0]].into() else {
+        return Ok(());
+    };
+
+    for &pos in &cells[1..] {
+        match sudoku[pos].into() {
+            Entry::Empty => {
+                let mut mask = Mask::full(sudoku.size());
+                mask.set_digit(digit, false);
+                sudoku.set(pos, mask.into());
+            }
+
+            Entry::Digit(d) => {
+                if d == digit {
+                    return Err(Contradiction::new(rule, vec![cells[0], pos], format!("digit {} repeats", digit)));
+                }
+            }
+
+            Entry::Pencil(mut mask) => {
+                if mask[digit] {
+                    mask.set_digit(digit, false);
+
+                    if mask.0 == 0 {
+                        return Err(Contradiction::new(rule, vec![pos], "no candidates left"));
+                    }
+
+                    sudoku.set(pos, mask.into());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_king_removes_digit() {
+        let mut sudoku = Sudoku::default();
+
+        sudoku[(0, 4)] = 7;
+        sudoku[(1, 5)] = Mask::full(9).into();
+
+        let rule = AntiKingRule;
+        let result = rule.update_unit(4, &rule.units(&sudoku)[4], &mut sudoku);
+
+        assert!(result.is_ok());
+        assert_eq!(sudoku[(1, 5)], Mask(0b110111111).into());
+        assert_eq!(sudoku[(1, 3)], Mask(0b110111111).into());
+        assert_eq!(sudoku[(2, 4)], 0);
+    }
+
+    #[test]
+    fn test_knight_conflict() {
+        let mut sudoku = Sudoku::default();
+
+        sudoku[(0, 0)] = 3;
+        sudoku[(2, 1)] = 3;
+
+        let rule = AntiKnightRule;
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
+
+        assert_eq!(result, Err(Contradiction::new("anti-knight", vec![(0, 0), (2, 1)], "digit 3 repeats")));
+    }
+}
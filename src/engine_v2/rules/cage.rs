@@ -1,5 +1,5 @@
-use crate::engine_v2::rules::Rule;
-use crate::engine_v2::{CellEntry, Entry, Pos, Sudoku};
+use crate::engine_v2::rules::{Contradiction, Rule};
+use crate::engine_v2::{Entry, Pos, Sudoku};
 
 /// A rule that returns the missing cages in the sum-sequence Sudoku.        
 /// ```txt
@@ -15,14 +15,12 @@ use crate::engine_v2::{CellEntry, Entry, Pos, Sudoku};
 /// |_____|__6__|_____|
 /// ```
 pub struct CageRule {
-    counter: usize,
     cages: [[Pos; 2]; 6],
 }
 
 impl Default for CageRule {
     fn default() -> Self {
         CageRule {
-            counter: 0,
             cages: [
                 [(0, 4), (1, 4)], //1
                 [(0, 6), (1, 6)], //2
@@ -36,11 +34,17 @@ impl Default for CageRule {
 }
 
 impl Rule for CageRule {
-    fn update_cells(&mut self, sudoku: &mut Sudoku) -> Result<(), ()> {
-        let cage = self.next(sudoku);
+    fn name(&self) -> &'static str {
+        "cage"
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.cages.iter().map(|cage| cage.to_vec()).collect()
+    }
 
-        let (pos_a, entry_a) = cage[0];
-        let (pos_b, entry_b) = cage[1];
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let (pos_a, entry_a) = sudoku.cell_entry(cells[0]);
+        let (pos_b, entry_b) = sudoku.cell_entry(cells[1]);
 
         match (entry_a, entry_b) {
             (Entry::Empty, _) | (_, Entry::Empty) => {}
@@ -48,21 +52,21 @@ impl Rule for CageRule {
 
             (Entry::Digit(a), Entry::Digit(b)) => {
                 if a == b {
-                    return Err(());
+                    return Err(Contradiction::new(self.name(), vec![pos_a, pos_b], format!("digit {} repeats", a)));
                 }
             }
 
             (Entry::Digit(digit), Entry::Pencil(mut mask)) => {
                 if mask[digit] {
                     mask.set_digit(digit, false);
-                    sudoku[pos_b] = mask.into();
+                    sudoku.set(pos_b, mask.into());
                 }
             }
 
             (Entry::Pencil(mut mask), Entry::Digit(digit)) => {
                 if mask[digit] {
                     mask.set_digit(digit, false);
-                    sudoku[pos_a] = mask.into();
+                    sudoku.set(pos_a, mask.into());
                 }
             }
         }
@@ -71,16 +75,6 @@ impl Rule for CageRule {
     }
 }
 
-impl CageRule {
-    fn next(&mut self, sudoku: &Sudoku) -> [CellEntry; 2] {
-        let cage = &self.cages[self.counter];
-        let result = cage.iter().map(|&pos| sudoku.cell_entry(pos)).collect::<Vec<_>>().try_into().unwrap();
-
-        self.counter = (self.counter + 1) % self.cages.len();
-        result
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,13 +82,13 @@ mod tests {
 
     #[test]
     fn test_digit_and_pencilmark() {
-        let mut sudoku = Sudoku([[0; 9]; 9]);
+        let mut sudoku = Sudoku::default();
 
         sudoku[(0, 4)] = 7;
-        sudoku[(1, 4)] = Mask::ALL.into();
+        sudoku[(1, 4)] = Mask::full(9).into();
 
-        let mut rule = CageRule::default();
-        let result = rule.update_cells(&mut sudoku);
+        let rule = CageRule::default();
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
 
         assert!(result.is_ok());
         assert_eq!(sudoku[(1, 4)], Mask(0b110111111).into());
@@ -0,0 +1,54 @@
+use super::{ClueCheck, ClueRule};
+
+/// The sum of the digits along a diagonal, where digits may repeat.
+pub struct LittleKiller(pub u8);
+
+pub type LittleKillerRule = ClueRule<LittleKiller>;
+
+impl ClueCheck for LittleKiller {
+    type State = u8;
+
+    const NAME: &'static str = "little killer";
+
+    const DISTINCT: bool = false;
+
+    fn start(&self) -> u8 {
+        0
+    }
+
+    fn step(&self, sum: u8, digit: u8) -> Option<u8> {
+        sum.checked_add(digit).filter(|&sum| sum <= self.0)
+    }
+
+    fn accept(&self, sum: u8) -> bool {
+        sum == self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::Mask;
+    use crate::engine_v2::rules::clue::{Clue, ClueKind, Direction, Edge, prune};
+
+    #[test]
+    fn test_short_diagonal() {
+        let masks = [Mask::full(9); 3];
+
+        let supported = prune(&masks, &LittleKiller(4), 9).unwrap();
+
+        assert_eq!(supported, [Mask(0b000000011); 3]); // 1-2
+    }
+
+    #[test]
+    fn test_sum_past_u8() {
+        // The main diagonal of a 16x16 grid, whose running sums can pass 255 before a cell is ruled out.
+        let clue = Clue::new(ClueKind::LittleKiller(Direction::SouthEast), Edge::Top, 0, 250).unwrap();
+        let masks = vec![Mask::full(16); clue.cells(16).len()];
+
+        let supported = prune(&masks, &LittleKiller(clue.value), 16).unwrap();
+
+        assert_eq!(masks.len(), 16);
+        assert_eq!(supported, [Mask::from_digits(10..=16); 16]); // the other 15 cells add up to at most 240
+    }
+}
@@ -0,0 +1,302 @@
+pub mod little_killer;
+pub mod sandwich;
+pub mod skyscraper;
+pub mod x_sum;
+
+use super::{Contradiction, Rule};
+use crate::engine_v2::{Entry, Mask, Pos, Sudoku};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The side of the grid an outside clue is written on.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// The diagonal direction a little killer clue points to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ClueKind {
+    /// Sum of the digits between the 1 and the highest digit.
+    Sandwich,
+    /// Sum of the first X digits, where X is the first digit.
+    XSum,
+    /// Number of digits visible from the edge, taller digits hiding shorter ones.
+    Skyscraper,
+    /// Sum of the digits along the diagonal, which may repeat.
+    LittleKiller(Direction),
+}
+
+/// A clue written outside the grid, next to row or column `index` on the given edge.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Clue {
+    pub kind: ClueKind,
+    pub edge: Edge,
+    pub index: usize,
+    pub value: u8,
+}
+
+impl Clue {
+    /// Validates that a little killer clue points into the grid.
+    pub fn new(kind: ClueKind, edge: Edge, index: usize, value: u8) -> Result<Clue, String> {
+        let clue = Clue { kind, edge, index, value };
+
+        if let ClueKind::LittleKiller(direction) = kind {
+            let (dr, dc) = clue.step();
+            let inward = match edge {
+                Edge::Top => dr > 0,
+                Edge::Bottom => dr < 0,
+                Edge::Left => dc > 0,
+                Edge::Right => dc < 0,
+            };
+
+            if !inward {
+                return Err(format!("Little killer clue on the {:?} edge can't point {:?}", edge, direction));
+            }
+        }
+
+        Ok(clue)
+    }
+
+    /// The cells the clue refers to on a grid of the given size, ordered starting from the edge.
+    pub fn cells(&self, size: usize) -> Vec<Pos> {
+        let start = match self.edge {
+            Edge::Top => (0, self.index),
+            Edge::Bottom => (size - 1, self.index),
+            Edge::Left => (self.index, 0),
+            Edge::Right => (self.index, size - 1),
+        };
+
+        let (dr, dc) = self.step();
+        let mut result = vec![];
+        let mut pos = Some(start);
+
+        while let Some((row, col)) = pos.filter(|&(r, c)| r < size && c < size) {
+            result.push((row, col));
+            pos = row.checked_add_signed(dr).zip(col.checked_add_signed(dc));
+        }
+
+        result
+    }
+
+    fn step(&self) -> (isize, isize) {
+        match (self.kind, self.edge) {
+            (ClueKind::LittleKiller(direction), _) => match direction {
+                Direction::NorthEast => (-1, 1),
+                Direction::NorthWest => (-1, -1),
+                Direction::SouthEast => (1, 1),
+                Direction::SouthWest => (1, -1),
+            },
+            (_, Edge::Top) => (1, 0),
+            (_, Edge::Bottom) => (-1, 0),
+            (_, Edge::Left) => (0, 1),
+            (_, Edge::Right) => (0, -1),
+        }
+    }
+}
+
+/// The feasibility test of one kind of clue, fed the digits of its cells in order from the edge.
+pub trait ClueCheck: Send + Sync {
+    type State: Copy + Eq + Hash;
+
+    const NAME: &'static str;
+
+    /// Whether the cells must hold distinct digits, as on a full row or column.
+    const DISTINCT: bool;
+
+    fn start(&self) -> Self::State;
+    fn step(&self, state: Self::State, digit: u8) -> Option<Self::State>;
+    fn accept(&self, state: Self::State) -> bool;
+}
+
+/// A rule that keeps only the candidates appearing in some placement that satisfies a clue.
+pub struct ClueRule<C: ClueCheck> {
+    clues: Vec<(Vec<Pos>, C)>,
+}
+
+impl<C: ClueCheck> ClueRule<C> {
+    pub fn new(clues: Vec<(Vec<Pos>, C)>) -> Self {
+        ClueRule { clues }
+    }
+}
+
+impl<C: ClueCheck> Rule for ClueRule<C> {
+    fn name(&self) -> &'static str {
+        C::NAME
+    }
+
+    /// Clues enumerate the fillings of whole lines, so they wait for the cheaper rules.
+    fn priority(&self) -> u8 {
+        2
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.clues.iter().map(|(cells, _)| cells.clone()).collect()
+    }
+
+    fn update_unit(&self, unit: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let size = sudoku.size();
+        let check = &self.clues[unit].1;
+        let masks: Vec<Mask> = cells.iter().map(|&pos| sudoku.candidates(pos)).collect();
+
+        let supported = prune(&masks, check, size)
+            .ok_or_else(|| Contradiction::new(C::NAME, cells.to_vec(), "no placement satisfies the clue"))?;
+
+        for (&pos, &mask) in cells.iter().zip(&supported) {
+            match sudoku[pos].into() {
+                Entry::Digit(_) => {}
+                Entry::Empty if mask == Mask::full(size) => {}
+                Entry::Empty | Entry::Pencil(_) => sudoku.set(pos, mask.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn explain(&self, _: usize, cells: &[Pos], _: &Sudoku) -> String {
+        let (row, col) = cells[0];
+        format!("clue next to r{}c{}", row + 1, col + 1)
+    }
+}
+
+/// Enumerates every placement of digits allowed by `masks` that the check accepts,
+/// returning the digits each cell takes in at least one of them, or `None` if there are none.
+fn prune<C: ClueCheck>(masks: &[Mask], check: &C, size: usize) -> Option<Vec<Mask>> {
+    let mut supported = vec![Mask(0); masks.len()];
+    let mut memo = HashMap::new();
+    let search = Search { masks, check, size };
+
+    if search.run(0, 0, check.start(), &mut supported, &mut memo) { Some(supported) } else { None }
+}
+
+struct Search<'a, C> {
+    masks: &'a [Mask],
+    check: &'a C,
+    size: usize,
+}
+
+impl<C: ClueCheck> Search<'_, C> {
+    fn run(
+        &self,
+        index: usize,
+        used: u16,
+        state: C::State,
+        supported: &mut [Mask],
+        memo: &mut HashMap<(usize, u16, C::State), bool>,
+    ) -> bool {
+        if index == self.masks.len() {
+            return self.check.accept(state);
+        }
+
+        if let Some(&feasible) = memo.get(&(index, used, state)) {
+            return feasible;
+        }
+
+        let mut feasible = false;
+
+        for i in 0..self.size {
+            if !self.masks[index][i] || (C::DISTINCT && used & (1 << i) != 0) {
+                continue;
+            }
+
+            let Some(next) = self.check.step(state, i as u8 + 1) else {
+                continue;
+            };
+
+            // The digits used so far only matter, and only split the memo, when they can't repeat.
+            let used = if C::DISTINCT { used | (1 << i) } else { used };
+
+            if self.run(index + 1, used, next, supported, memo) {
+                supported[index].set_bit(i, true);
+                feasible = true;
+            }
+        }
+
+        memo.insert((index, used, state), feasible);
+        feasible
+    }
+}
+
+/// Builds one rule per kind of clue present, for a grid of the given size.
+pub fn clue_rules(clues: &[Clue], size: usize) -> Vec<Box<dyn Rule>> {
+    use little_killer::{LittleKiller, LittleKillerRule};
+    use sandwich::{Sandwich, SandwichRule};
+    use skyscraper::{Skyscraper, SkyscraperRule};
+    use x_sum::{XSum, XSumRule};
+
+    let mut sandwiches = vec![];
+    let mut x_sums = vec![];
+    let mut skyscrapers = vec![];
+    let mut little_killers = vec![];
+
+    for clue in clues {
+        let cells = clue.cells(size);
+
+        match clue.kind {
+            ClueKind::Sandwich => sandwiches.push((cells, Sandwich { sum: clue.value, size: size as u8 })),
+            ClueKind::XSum => x_sums.push((cells, XSum(clue.value))),
+            ClueKind::Skyscraper => skyscrapers.push((cells, Skyscraper(clue.value))),
+            ClueKind::LittleKiller(_) => little_killers.push((cells, LittleKiller(clue.value))),
+        }
+    }
+
+    let mut rules: Vec<Box<dyn Rule>> = vec![];
+
+    if !sandwiches.is_empty() {
+        rules.push(Box::new(SandwichRule::new(sandwiches)));
+    }
+
+    if !x_sums.is_empty() {
+        rules.push(Box::new(XSumRule::new(x_sums)));
+    }
+
+    if !skyscrapers.is_empty() {
+        rules.push(Box::new(SkyscraperRule::new(skyscrapers)));
+    }
+
+    if !little_killers.is_empty() {
+        rules.push(Box::new(LittleKillerRule::new(little_killers)));
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clue_cells() {
+        let row = Clue::new(ClueKind::Skyscraper, Edge::Right, 2, 3).unwrap();
+        assert_eq!(row.cells(9)[..3], [(2, 8), (2, 7), (2, 6)]);
+
+        let diagonal = Clue::new(ClueKind::LittleKiller(Direction::SouthWest), Edge::Top, 2, 6).unwrap();
+        assert_eq!(diagonal.cells(9), [(0, 2), (1, 1), (2, 0)]);
+
+        assert!(Clue::new(ClueKind::LittleKiller(Direction::NorthEast), Edge::Top, 2, 6).is_err());
+    }
+
+    #[test]
+    fn test_rule_prunes_cells() {
+        let mut sudoku = Sudoku::default();
+        let clue = Clue::new(ClueKind::Skyscraper, Edge::Top, 4, 9).unwrap();
+
+        let rule = skyscraper::SkyscraperRule::new(vec![(clue.cells(9), skyscraper::Skyscraper(9))]);
+        let result = rule.update_unit(0, &clue.cells(9), &mut sudoku);
+
+        assert!(result.is_ok());
+        assert_eq!(sudoku[(0, 4)], Mask(0b000000001).into());
+        assert_eq!(sudoku[(8, 4)], Mask(0b100000000).into());
+    }
+}
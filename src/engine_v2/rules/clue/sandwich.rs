@@ -0,0 +1,63 @@
+use super::{ClueCheck, ClueRule};
+
+/// The sum of the digits between the lowest and the highest digit of a row or column,
+/// the 1 and the 9 on a 9x9 grid.
+pub struct Sandwich {
+    pub sum: u8,
+    pub size: u8,
+}
+
+pub type SandwichRule = ClueRule<Sandwich>;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Bread {
+    Before,
+    Inside(u8),
+    After,
+}
+
+impl ClueCheck for Sandwich {
+    type State = Bread;
+
+    const NAME: &'static str = "sandwich";
+
+    const DISTINCT: bool = true;
+
+    fn start(&self) -> Bread {
+        Bread::Before
+    }
+
+    fn step(&self, state: Bread, digit: u8) -> Option<Bread> {
+        let crust = digit == 1 || digit == self.size;
+
+        match state {
+            Bread::Before if crust => Some(Bread::Inside(0)),
+            Bread::Before => Some(Bread::Before),
+            Bread::Inside(sum) if crust => (sum == self.sum).then_some(Bread::After),
+            Bread::Inside(sum) => sum.checked_add(digit).filter(|&sum| sum <= self.sum).map(Bread::Inside),
+            Bread::After => Some(Bread::After),
+        }
+    }
+
+    fn accept(&self, state: Bread) -> bool {
+        state == Bread::After
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::Mask;
+    use crate::engine_v2::rules::clue::prune;
+
+    #[test]
+    fn test_empty_sandwich() {
+        let mut masks = [Mask::full(9); 9];
+        masks[0] = Mask(0b000000001); // 1
+
+        let supported = prune(&masks, &Sandwich { sum: 0, size: 9 }, 9).unwrap();
+
+        assert_eq!(supported[1], Mask(0b100000000)); // 9 right after the 1
+        assert_eq!(supported[2], Mask(0b011111110)); // 2-8
+    }
+}
@@ -0,0 +1,50 @@
+use super::{ClueCheck, ClueRule};
+
+/// The number of digits visible from the edge, each digit hiding the smaller ones behind it.
+pub struct Skyscraper(pub u8);
+
+pub type SkyscraperRule = ClueRule<Skyscraper>;
+
+/// The tallest digit so far and how many digits are visible.
+type Skyline = (u8, u8);
+
+impl ClueCheck for Skyscraper {
+    type State = Skyline;
+
+    const NAME: &'static str = "skyscraper";
+
+    const DISTINCT: bool = true;
+
+    fn start(&self) -> Skyline {
+        (0, 0)
+    }
+
+    fn step(&self, (tallest, visible): Skyline, digit: u8) -> Option<Skyline> {
+        if digit < tallest {
+            return Some((tallest, visible));
+        }
+
+        Some((digit, visible + 1)).filter(|_| visible < self.0)
+    }
+
+    fn accept(&self, (_, visible): Skyline) -> bool {
+        visible == self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::Mask;
+    use crate::engine_v2::rules::clue::prune;
+
+    #[test]
+    fn test_single_skyscraper() {
+        let masks = [Mask::full(9); 9];
+
+        let supported = prune(&masks, &Skyscraper(1), 9).unwrap();
+
+        assert_eq!(supported[0], Mask(0b100000000)); // 9
+        assert_eq!(supported[1], Mask(0b011111111)); // 1-8
+    }
+}
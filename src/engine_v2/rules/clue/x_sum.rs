@@ -0,0 +1,53 @@
+use super::{ClueCheck, ClueRule};
+
+/// The sum of the first X digits of a row or column, where X is the first digit.
+pub struct XSum(pub u8);
+
+pub type XSumRule = ClueRule<XSum>;
+
+/// The first digit, how many digits were summed so far and their sum.
+type Progress = (u8, u8, u8);
+
+impl ClueCheck for XSum {
+    type State = Progress;
+
+    const NAME: &'static str = "x-sum";
+
+    const DISTINCT: bool = true;
+
+    fn start(&self) -> Progress {
+        (0, 0, 0)
+    }
+
+    fn step(&self, (x, count, sum): Progress, digit: u8) -> Option<Progress> {
+        match count {
+            0 => Some((digit, 1, digit)).filter(|_| digit <= self.0),
+            _ if count == x => Some((x, count, sum)),
+            _ => sum.checked_add(digit).filter(|&sum| sum <= self.0).map(|sum| (x, count + 1, sum)),
+        }
+    }
+
+    fn accept(&self, (x, count, sum): Progress) -> bool {
+        count == x && sum == self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::Mask;
+    use crate::engine_v2::rules::clue::prune;
+
+    #[test]
+    fn test_x_sum_bounds() {
+        let masks = [Mask::full(9); 9];
+
+        let supported = prune(&masks, &XSum(45), 9).unwrap();
+        assert_eq!(supported[0], Mask(0b100000000)); // 9
+
+        let supported = prune(&masks, &XSum(1), 9).unwrap();
+        assert_eq!(supported[0], Mask(0b000000001)); // 1
+
+        assert!(prune(&masks, &XSum(2), 9).is_none());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::engine_v2::rules::{Contradiction, Rule};
+use crate::engine_v2::{Bitboard, Mask, Pos, Sudoku};
+
+/// A rule that finds fish: when the places of a digit in n rows of a grid fall in only n columns,
+/// the digit fills those columns from these rows, so it is removed from the rest of the columns.
+/// The same holds with rows and columns swapped. Covers X-wings, swordfish and jellyfish alike.
+#[derive(Default)]
+pub struct FishRule {
+    origin: Pos,
+}
+
+impl FishRule {
+    pub fn new(origin: Pos) -> Self {
+        FishRule { origin }
+    }
+
+    /// Finds the fish among the lines, given as the places of the digit across them, and eliminates from their cover.
+    fn swim(
+        &self,
+        sudoku: &mut Sudoku,
+        digit: u8,
+        lines: &[u32],
+        pos: impl Fn(usize, usize) -> Pos,
+    ) -> Result<(), Contradiction> {
+        let mut found = vec![];
+
+        for k in 2..=lines.len() as u32 / 2 {
+            fish(lines, k, 0, 0, 0, &mut found);
+        }
+
+        let without = Mask(Mask::full(sudoku.size()).0 & !(1 << (digit - 1)));
+
+        for (base, cover) in found {
+            let (base_count, cover_count) = (base.count_ones(), cover.count_ones());
+            let bits = |mask: u32| (0..lines.len()).filter(move |i| mask & (1 << i) != 0);
+
+            if cover_count < base_count {
+                let pos = &pos;
+                let cells = bits(base).flat_map(|line| bits(lines[line]).map(move |cell| pos(line, cell))).collect();
+                let reason =
+                    format!("digit {} fits {} lines in only {} crossing lines", digit, base_count, cover_count);
+                return Err(Contradiction::new(self.name(), cells, reason));
+            }
+
+            for line in bits(!base) {
+                for cell in bits(cover) {
+                    let pos = pos(line, cell);
+
+                    if sudoku.candidates(pos).contains(digit) && !sudoku.restrict(pos, without) {
+                        let reason = format!("digit {} is outside its fish", digit);
+                        return Err(Contradiction::new(self.name(), vec![pos], reason));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects every choice of `k` lines from `start` on whose places span at most `k` cells,
+/// as the chosen lines and the cells they cover. Lines with a single place are left to the hidden singles.
+fn fish(lines: &[u32], k: u32, start: usize, base: u32, cover: u32, found: &mut Vec<(u32, u32)>) {
+    if cover.count_ones() > k {
+        return;
+    }
+
+    if base.count_ones() == k {
+        found.push((base, cover));
+        return;
+    }
+
+    for line in (start..lines.len()).filter(|&line| lines[line].count_ones() > 1) {
+        fish(lines, k, line + 1, base | 1 << line, cover | lines[line], found);
+    }
+}
+
+/// The places of the digit on each line, as a mask of the cells along it.
+fn places(sudoku: &Sudoku, board: &Bitboard, size: usize, pos: impl Fn(usize, usize) -> Pos) -> Vec<u32> {
+    (0..size)
+        .map(|line| {
+            let cells = (0..size).filter(|&cell| board.contains(sudoku.cell_index(pos(line, cell))));
+            cells.fold(0, |mask, cell| mask | 1 << cell)
+        })
+        .collect()
+}
+
+impl Rule for FishRule {
+    fn name(&self) -> &'static str {
+        "fish"
+    }
+
+    /// Every digit is checked against every combination of lines, which only pays off once the other rules stall.
+    fn priority(&self) -> u8 {
+        2
+    }
+
+    /// One unit per digit, holding the whole grid.
+    fn units(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        let (row, col) = self.origin;
+        let size = sudoku.size();
+        let grid: Vec<Pos> = (0..size).flat_map(|r| (0..size).map(move |c| (row + r, col + c))).collect();
+
+        vec![grid; size]
+    }
+
+    fn update_unit(&self, unit: usize, _: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let (row, col) = self.origin;
+        let size = sudoku.size();
+        let by_row = |line, cell| (row + line, col + cell);
+        let by_col = |line, cell| (row + cell, col + line);
+
+        let board = sudoku.boards()[unit].clone();
+        let rows = places(sudoku, &board, size, by_row);
+        let cols = places(sudoku, &board, size, by_col);
+        let digit = unit as u8 + 1;
+
+        self.swim(sudoku, digit, &rows, by_row)?;
+        self.swim(sudoku, digit, &cols, by_col)
+    }
+
+    fn explain(&self, unit: usize, _: &[Pos], _: &Sudoku) -> String {
+        format!("digit {}", unit + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_x_wing() {
+        let mut sudoku = Sudoku::default();
+        let without_seven = Mask::from_digits([1, 2, 3, 4, 5, 6, 8, 9]);
+
+        // Rows 2 and 6 only allow 7 in columns 3 and 8.
+        for col in [0, 1, 3, 4, 5, 6, 8] {
+            sudoku[(1, col)] = without_seven.into();
+            sudoku[(5, col)] = without_seven.into();
+        }
+
+        let rule = FishRule::default();
+        let result = rule.update_unit(6, &rule.units(&sudoku)[6], &mut sudoku);
+
+        assert!(result.is_ok());
+        assert_eq!(sudoku.candidates((0, 2)), without_seven);
+        assert_eq!(sudoku.candidates((8, 7)), without_seven);
+        assert_eq!(sudoku.candidates((1, 2)), Mask::full(9));
+        assert_eq!(sudoku.candidates((0, 0)), Mask::full(9));
+    }
+
+    #[test]
+    fn test_too_few_places() {
+        let mut sudoku = Sudoku::default();
+        let without_seven = Mask::from_digits([1, 2, 3, 4, 5, 6, 8, 9]);
+
+        // Rows 2, 6 and 9 only allow 7 in columns 3 and 7.
+        for row in [1, 5, 8] {
+            for col in [0, 1, 3, 4, 5, 7, 8] {
+                sudoku[(row, col)] = without_seven.into();
+            }
+        }
+
+        let rule = FishRule::default();
+        let result = rule.update_unit(6, &rule.units(&sudoku)[6], &mut sudoku);
+
+        let cells = vec![(1, 2), (1, 6), (5, 2), (5, 6), (8, 2), (8, 6)];
+        let reason = "digit 7 fits 3 lines in only 2 crossing lines";
+        assert_eq!(result, Err(Contradiction::new("fish", cells, reason)));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::engine_v2::rules::{Contradiction, Regions, Rule};
+use crate::engine_v2::{Entry, Pos, Sudoku};
+
+/// A rule that places a digit in the only cell of a row, column or region that still allows it.
+pub struct HiddenSinglesRule {
+    regions: Vec<Vec<Pos>>,
+    origin: Pos,
+}
+
+impl Default for HiddenSinglesRule {
+    fn default() -> Self {
+        HiddenSinglesRule::new(&Regions::default(), (0, 0))
+    }
+}
+
+impl HiddenSinglesRule {
+    /// Looks at the rows, columns and regions of the sub-grid whose top-left cell is `origin`.
+    pub fn new(regions: &Regions, (row, col): Pos) -> Self {
+        let regions = regions.cells().into_iter().map(|cells| cells.iter().map(|&(r, c)| (row + r, col + c)).collect());
+        HiddenSinglesRule { regions: regions.collect(), origin: (row, col) }
+    }
+}
+
+impl Rule for HiddenSinglesRule {
+    fn name(&self) -> &'static str {
+        "hidden-singles"
+    }
+
+    /// Scanning a unit for every digit costs more than a naked-set pass, so it runs once those stall.
+    fn priority(&self) -> u8 {
+        1
+    }
+
+    /// The rows, then the columns, then the regions.
+    fn units(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        let (row, col) = self.origin;
+        let size = sudoku.size();
+
+        let rows = (0..size).map(|r| (0..size).map(|c| (row + r, col + c)).collect());
+        let cols = (0..size).map(|c| (0..size).map(|r| (row + r, col + c)).collect());
+
+        rows.chain(cols).chain(self.regions.iter().cloned()).collect()
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let unit = sudoku.board_of(cells);
+
+        for digit in 1..=sudoku.size() as u8 {
+            let board = &sudoku.boards()[digit as usize - 1];
+            let first = board.first_common(&unit);
+
+            match board.count_common(&unit) {
+                0 => {
+                    let reason = format!("digit {} has no place", digit);
+                    return Err(Contradiction::new(self.name(), cells.to_vec(), reason));
+                }
+
+                1 => {
+                    let pos = sudoku.cell_pos(first.unwrap());
+
+                    if !matches!(sudoku.entry(pos), Entry::Digit(_)) {
+                        sudoku.set(pos, digit.into());
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn explain(&self, unit: usize, cells: &[Pos], sudoku: &Sudoku) -> String {
+        let size = sudoku.size();
+        let (row, col) = cells[0];
+
+        match unit / size {
+            0 => format!("row {}", row + 1),
+            1 => format!("column {}", col + 1),
+            _ => format!("box {}", unit - 2 * size + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::Mask;
+
+    #[test]
+    fn test_places_hidden_single() {
+        let mut sudoku = Sudoku::default();
+        let without_five = Mask::from_digits([1, 2, 3, 4, 6, 7, 8, 9]);
+
+        (1..9).for_each(|col| sudoku[(0, col)] = without_five.into());
+
+        let rule = HiddenSinglesRule::default();
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
+
+        assert!(result.is_ok());
+        assert_eq!(sudoku[(0, 0)], 5);
+        assert_eq!(rule.explain(9, &rule.units(&sudoku)[9], &sudoku), "column 1");
+    }
+
+    #[test]
+    fn test_digit_without_place() {
+        let mut sudoku = Sudoku::default();
+        let without_five = Mask::from_digits([1, 2, 3, 4, 6, 7, 8, 9]);
+
+        (0..9).for_each(|row| sudoku[(row, 4)] = without_five.into());
+
+        let rule = HiddenSinglesRule::default();
+        let cells = &rule.units(&sudoku)[13];
+        let result = rule.update_unit(13, cells, &mut sudoku);
+
+        assert_eq!(result, Err(Contradiction::new("hidden-singles", cells.clone(), "digit 5 has no place")));
+    }
+}
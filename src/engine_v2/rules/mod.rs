@@ -1,13 +1,135 @@
-use super::Sudoku;
+use super::{Domain, Mask, Pos, Sudoku, Var};
+use std::fmt::Display;
 
+mod anti;
 mod cage;
+mod clue;
+mod fish;
+mod hidden;
 mod palindrome;
+mod sequence;
 mod set;
+mod shade;
+mod table;
 
+pub use anti::{AntiKingRule, AntiKnightRule};
 pub use cage::CageRule;
+pub use clue::{Clue, ClueKind, Direction, Edge, clue_rules};
+pub use fish::FishRule;
+pub use hidden::HiddenSinglesRule;
 pub use palindrome::PalindromeRule;
-pub use set::{r#box::BoxRule, cage::CageRule as SetCageRule, col::ColRule, row::RowRule};
+pub use sequence::SequenceRule;
+pub(crate) use sequence::{FREQUENCY, PAIR_CAGES};
+pub use set::{
+    r#box::{BoxRule, Regions},
+    cage::CageRule as SetCageRule,
+    col::ColRule,
+    diagonal::DiagonalRule,
+    row::RowRule,
+};
+pub use shade::ShadeRule;
+pub use table::TableRule;
 
-pub trait Rule {
-    fn update_cells(&mut self, sudoku: &mut Sudoku) -> Result<(), ()>;
+/// A rule found that the grid can't be completed, pointing at the cells involved.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Contradiction {
+    pub rule: &'static str,
+    pub cells: Vec<Pos>,
+    pub reason: String,
+}
+
+impl Contradiction {
+    pub fn new(rule: &'static str, cells: Vec<Pos>, reason: impl Into<String>) -> Self {
+        Contradiction { rule, cells, reason: reason.into() }
+    }
+}
+
+impl Display for Contradiction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} rule: {}", self.rule, self.reason)?;
+
+        for (i, (row, col)) in self.cells.iter().enumerate() {
+            write!(f, "{}r{}c{}", if i == 0 { " at " } else { ", " }, row + 1, col + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Candidates a rule removed from a cell, along with the rule's explanation of the unit it worked on.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Elimination {
+    pub rule: &'static str,
+    pub reason: String,
+    pub pos: Pos,
+    pub removed: Mask,
+}
+
+impl Display for Elimination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits: Vec<String> = self.removed.digits().map(|digit| digit.to_string()).collect();
+        let (row, col) = self.pos;
+
+        write!(f, "{} rule, {}: removed {} from r{}c{}", self.rule, self.reason, digits.join(", "), row + 1, col + 1)
+    }
+}
+
+/// A constraint that removes candidates from groups of cells, its units.
+/// Implementations outside this crate are added to a solver with `Solver::with_rule`,
+/// and are shared between threads by `Solver::with_threads`.
+pub trait Rule: Send + Sync {
+    /// A short name used when reporting contradictions.
+    fn name(&self) -> &'static str;
+
+    /// The groups of cells the rule reasons about, computed once before solving.
+    /// A unit is scheduled again whenever one of its cells changes.
+    fn units(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>>;
+
+    /// Applies the rule to one of its units, changing none of the cells outside it.
+    fn update_unit(&self, unit: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction>;
+
+    /// The tier the rule runs in unless a solver is told otherwise. Cheap rules stay in tier 0,
+    /// and the units of higher tiers are only updated once every lower tier has nothing left to do.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    /// The auxiliary variables the rule reads, with the values they may take before solving.
+    /// Solvers declare them on the grid, and rules naming the same variable share it.
+    fn vars(&self, _sudoku: &Sudoku) -> Vec<(Var, Domain)> {
+        vec![]
+    }
+
+    /// The auxiliary variables each unit reads, in the order of `units`, missing units reading none.
+    /// A unit is also scheduled again whenever one of its variables changes.
+    fn links(&self, _sudoku: &Sudoku) -> Vec<Vec<Var>> {
+        vec![]
+    }
+
+    /// Every cell the rule covers, in order and without repeats.
+    fn cells(&self, sudoku: &Sudoku) -> Vec<Pos> {
+        let mut cells = self.units(sudoku).concat();
+        cells.sort_unstable();
+        cells.dedup();
+        cells
+    }
+
+    /// Checks the placed digits against the rule, treating cells without a digit as free.
+    /// A finished grid is only accepted as a solution when every rule passes.
+    /// By default, every unit is updated in order on a copy of the grid, with the rule's variables declared.
+    fn check(&self, sudoku: &Sudoku) -> Result<(), Contradiction> {
+        let mut copy = sudoku.clone();
+        self.vars(sudoku).into_iter().for_each(|(var, domain)| copy.declare(var, domain));
+
+        for (unit, cells) in self.units(sudoku).iter().enumerate() {
+            self.update_unit(unit, cells, &mut copy)?;
+        }
+
+        Ok(())
+    }
+
+    /// Describes the unit for the eliminations it makes, given the grid before the update.
+    fn explain(&self, unit: usize, _cells: &[Pos], _sudoku: &Sudoku) -> String {
+        format!("{} {}", self.name(), unit + 1)
+    }
 }
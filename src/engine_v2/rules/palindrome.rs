@@ -1,5 +1,5 @@
-use crate::engine_v2::rules::Rule;
-use crate::engine_v2::{CellEntry, Entry, Mask, Pos, Sudoku};
+use crate::engine_v2::rules::{Contradiction, Rule};
+use crate::engine_v2::{Entry, Mask, Pos, Sudoku};
 
 /// A rule that returns the palindrome in the sum-sequence Sudoku.        
 /// ```txt
@@ -15,7 +15,6 @@ use crate::engine_v2::{CellEntry, Entry, Mask, Pos, Sudoku};
 /// |_____|_____|_____|
 /// ```
 pub struct PalindromeRule {
-    counter: usize,
     sets: [Vec<Pos>; 6],
 }
 
@@ -30,13 +29,21 @@ impl Default for PalindromeRule {
             vec![(4, 7), (5, 6), (6, 5), (7, 4)],
         ];
 
-        PalindromeRule { counter: 0, sets }
+        PalindromeRule { sets }
     }
 }
 
 impl Rule for PalindromeRule {
-    fn update_cells(&mut self, sudoku: &mut Sudoku) -> Result<(), ()> {
-        let palindrome = self.next(sudoku);
+    fn name(&self) -> &'static str {
+        "palindrome"
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.sets.to_vec()
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let palindrome: Vec<_> = cells.iter().map(|&pos| sudoku.cell_entry(pos)).collect();
 
         for i in 0..(palindrome.len() / 2) {
             let (pos_l, entry_l) = palindrome[i];
@@ -47,26 +54,32 @@ impl Rule for PalindromeRule {
 
                 (Entry::Digit(d1), Entry::Digit(d2)) => {
                     if d1 != d2 {
-                        return Err(());
+                        let reason = format!("digits {} and {} don't mirror each other", d1, d2);
+                        return Err(Contradiction::new(self.name(), vec![pos_l, pos_r], reason));
                     }
                 }
 
                 (Entry::Pencil(mask), Entry::Digit(digit)) | (Entry::Digit(digit), Entry::Pencil(mask))
                     if !mask[digit] =>
                 {
-                    return Err(());
+                    let reason = format!("digit {} can't be mirrored", digit);
+                    return Err(Contradiction::new(self.name(), vec![pos_l, pos_r], reason));
                 }
 
-                (Entry::Pencil(_), Entry::Digit(d)) | (Entry::Empty, Entry::Digit(d)) => sudoku[pos_l] = d.get() as _,
-                (Entry::Digit(d), Entry::Pencil(_)) | (Entry::Digit(d), Entry::Empty) => sudoku[pos_r] = d.get() as _,
+                (Entry::Pencil(_), Entry::Digit(d)) | (Entry::Empty, Entry::Digit(d)) => {
+                    sudoku.set(pos_l, d.get() as _)
+                }
+                (Entry::Digit(d), Entry::Pencil(_)) | (Entry::Digit(d), Entry::Empty) => {
+                    sudoku.set(pos_r, d.get() as _)
+                }
 
-                (Entry::Empty, Entry::Pencil(m)) => sudoku[pos_l] = m.into(),
-                (Entry::Pencil(m), Entry::Empty) => sudoku[pos_r] = m.into(),
+                (Entry::Empty, Entry::Pencil(m)) => sudoku.set(pos_l, m.into()),
+                (Entry::Pencil(m), Entry::Empty) => sudoku.set(pos_r, m.into()),
 
                 (Entry::Pencil(mask_l), Entry::Pencil(mask_r)) => {
                     let intersection = Mask(mask_l.0 & mask_r.0).into();
-                    sudoku[pos_l] = intersection;
-                    sudoku[pos_r] = intersection;
+                    sudoku.set(pos_l, intersection);
+                    sudoku.set(pos_r, intersection);
                 }
             }
         }
@@ -75,15 +88,6 @@ impl Rule for PalindromeRule {
     }
 }
 
-impl PalindromeRule {
-    fn next(&mut self, sudoku: &Sudoku) -> Vec<CellEntry> {
-        let result = self.sets[self.counter].iter().map(|&pos| sudoku.cell_entry(pos)).collect();
-
-        self.counter = (self.counter + 1) % self.sets.len();
-        result
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -93,10 +97,10 @@ mod tests {
         let mut sudoku = Sudoku::default();
 
         sudoku[(1, 4)] = 7;
-        sudoku[(4, 1)] = Mask::ALL.into();
+        sudoku[(4, 1)] = Mask::full(9).into();
 
-        let mut rule = PalindromeRule::default();
-        let result = rule.update_cells(&mut sudoku);
+        let rule = PalindromeRule::default();
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
 
         assert!(result.is_ok());
         assert_eq!(sudoku[(4, 1)], 7);
@@ -109,8 +113,8 @@ mod tests {
         sudoku[(1, 4)] = 7;
         sudoku[(4, 1)] = Mask(0b000111111).into(); // Pencilmarks for 1-6
 
-        let mut rule = PalindromeRule::default();
-        let result = rule.update_cells(&mut sudoku);
+        let rule = PalindromeRule::default();
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
 
         assert!(result.is_err());
     }
@@ -125,8 +129,8 @@ mod tests {
         sudoku[(2, 3)] = Mask(0b101010101).into(); // 1,3,5,7,9
         sudoku[(3, 2)] = Mask(0b011111110).into(); // 2-8
 
-        let mut rule = PalindromeRule::default();
-        let result = rule.update_cells(&mut sudoku);
+        let rule = PalindromeRule::default();
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
 
         assert!(result.is_ok());
 
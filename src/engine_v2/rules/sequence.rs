@@ -0,0 +1,363 @@
+use crate::engine_v2::rules::{Contradiction, PalindromeRule, Rule};
+use crate::engine_v2::{Domain, Linear, Pos, Sudoku, Term, Var};
+use std::collections::HashMap;
+
+/// The pair cages of the sum-sequence layout. Its three triplet cages aren't placed yet, and come from `Ruleset::cages`.
+/// ```txt
+///  _________________
+/// |    1|  2  |3    |
+/// |    1|  2  |3    |
+/// |4_4__|_____|__5_5|
+/// |     |     |     |
+/// |6 6  |     |  7 7|
+/// |_____|_____|_____|
+/// |8 8  |     |  9 9|
+/// |    A|  B  |C    |
+/// |____A|__B__|C____|
+/// ```
+pub(crate) const PAIR_CAGES: [[Pos; 2]; 12] = [
+    [(0, 2), (1, 2)],
+    [(0, 4), (1, 4)],
+    [(0, 6), (1, 6)],
+    [(2, 0), (2, 1)],
+    [(2, 7), (2, 8)],
+    [(4, 0), (4, 1)],
+    [(4, 7), (4, 8)],
+    [(6, 0), (6, 1)],
+    [(6, 7), (6, 8)],
+    [(7, 2), (8, 2)],
+    [(7, 4), (8, 4)],
+    [(7, 6), (8, 6)],
+];
+
+/// How many times every digit appears in the cage cells outside the palindromes, once the layout is complete.
+pub(crate) const FREQUENCY: usize = 3;
+
+/// A rule that links the sum of every cage of the sum-sequence Sudoku to an auxiliary variable,
+/// and keeps the sums different.
+///
+/// Once the cages outside the palindromes hold three cells per digit, the sums also form a sequence of
+/// consecutive numbers from a start S, and the algebra of the readme runs on the variables: the total of the
+/// sums is both 15S + 105 and 135 + X, X being the total of the cage cells inside palindromes, and X is
+/// twice the total of the palindrome digits, each of which fills two of those cells.
+///
+/// Units, in order: every cage with its sum, the sums together, and once the layout is complete,
+/// the palindrome total X, the total of the sums, its two expressions, and X as twice the palindrome digits.
+pub struct SequenceRule {
+    cages: Vec<Vec<Pos>>,
+    /// The cage cells inside palindromes, known once the layout is complete.
+    palindromes: Option<Vec<Pos>>,
+    size: usize,
+}
+
+impl SequenceRule {
+    /// The first sum of the sequence, S.
+    pub const START: Var = Var::new("sequence start");
+    /// The total of the cage cells inside palindromes, X.
+    pub const PALINDROME_TOTAL: Var = Var::new("palindrome total");
+    /// The total of the distinct digits of the palindrome cells, half of X.
+    pub const PALINDROME_DIGITS: Var = Var::new("palindrome digits");
+    /// The total of every cage sum.
+    pub const TOTAL: Var = Var::new("cage total");
+
+    /// The sum-sequence cages, the pair cages followed by the given ones.
+    pub fn new(extra: &[Vec<Pos>]) -> Self {
+        let cages: Vec<Vec<Pos>> = PAIR_CAGES.iter().map(|cage| cage.to_vec()).chain(extra.iter().cloned()).collect();
+        let sudoku = Sudoku::default();
+        let palindromes = PalindromeRule::default().cells(&sudoku);
+
+        let mut cells: Vec<Pos> = cages.concat();
+        cells.sort_unstable();
+        cells.dedup();
+
+        let (inside, outside): (Vec<Pos>, Vec<Pos>) = cells.into_iter().partition(|pos| palindromes.contains(pos));
+        let complete = outside.len() == FREQUENCY * sudoku.size();
+
+        SequenceRule { cages, palindromes: complete.then_some(inside), size: sudoku.size() }
+    }
+
+    /// The sum of the cage at the given index.
+    pub fn sum(cage: usize) -> Var {
+        Var::indexed("cage sum", cage)
+    }
+
+    fn sums(&self) -> Vec<Var> {
+        (0..self.cages.len()).map(SequenceRule::sum).collect()
+    }
+
+    /// The equations linking the variables of the complete layout, for the units after the sums.
+    fn equations(&self, palindromes: &[Pos]) -> [Linear; 5] {
+        let n = self.cages.len() as i32;
+        let digits = (self.size * (self.size + 1) / 2) as i32;
+        let sums = self.sums().into_iter().map(|sum| (1, Term::Var(sum)));
+        let cells = palindromes.iter().map(|&pos| (1, Term::Cell(pos)));
+
+        [
+            Linear::new(cells.chain([(-1, Term::Var(SequenceRule::PALINDROME_TOTAL))]).collect(), 0),
+            Linear::new(sums.chain([(-1, Term::Var(SequenceRule::TOTAL))]).collect(), 0),
+            // The sums S, S + 1, ..., S + n - 1.
+            Linear::new(
+                vec![(1, Term::Var(SequenceRule::TOTAL)), (-n, Term::Var(SequenceRule::START))],
+                n * (n - 1) / 2,
+            ),
+            // Every digit three times outside the palindromes.
+            Linear::new(
+                vec![(1, Term::Var(SequenceRule::TOTAL)), (-1, Term::Var(SequenceRule::PALINDROME_TOTAL))],
+                FREQUENCY as i32 * digits,
+            ),
+            // Every palindrome digit twice inside them.
+            Linear::new(
+                vec![(1, Term::Var(SequenceRule::PALINDROME_TOTAL)), (-2, Term::Var(SequenceRule::PALINDROME_DIGITS))],
+                0,
+            ),
+        ]
+    }
+
+    /// What the unit stands for, in contradictions and explanations.
+    fn describe(&self, unit: usize) -> String {
+        let n = self.cages.len();
+
+        match unit {
+            _ if unit < n => format!("sum of cage {}", unit + 1),
+            _ if unit == n => "cage sums".into(),
+            _ => ["palindrome total", "cage total", "sequence total", "digit count total", "palindrome digit total"]
+                [unit - n - 1]
+                .into(),
+        }
+    }
+
+    /// The sum of a cage's distinct digits, from the lowest ones to the highest ones.
+    fn bounds(&self, len: usize) -> (i32, i32) {
+        let lowest = (1..=len).sum::<usize>();
+        let highest = (self.size + 1 - len..=self.size).sum::<usize>();
+        (lowest as i32, highest as i32)
+    }
+
+    /// Keeps the sums different, and once the layout is complete, within `n` consecutive values from the start.
+    fn update_sums(&self, sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let sums = self.sums();
+
+        // A known sum is taken from every other cage.
+        let mut known = vec![false; sums.len()];
+        while let Some(i) = (0..sums.len()).find(|&i| !known[i] && sudoku[sums[i]].value().is_some()) {
+            known[i] = true;
+            let value = sudoku[sums[i]].value().unwrap();
+
+            for (j, &other) in sums.iter().enumerate().filter(|&(j, _)| j != i) {
+                if sudoku[other].contains(value) && !sudoku.restrict_var(other, sudoku[other].filter(|v| v != value)) {
+                    let cells = [&self.cages[i][..], &self.cages[j]].concat();
+                    return Err(Contradiction::new(self.name(), cells, format!("two cages add up to {}", value)));
+                }
+            }
+        }
+
+        let domains: Vec<Domain> = sums.iter().map(|&sum| sudoku[sum]).collect();
+
+        if self.palindromes.is_none() {
+            return match distinct(&domains, None) {
+                true => Ok(()),
+                false => Err(Contradiction::new(self.name(), vec![], "the cage sums can't all be different")),
+            };
+        }
+
+        let n = sums.len() as i32;
+        let window = |start: i32| Domain::range(start, start + n - 1);
+        let starts = sudoku[SequenceRule::START].filter(|start| distinct(&domains, Some(window(start))));
+
+        if !sudoku.restrict_var(SequenceRule::START, starts) {
+            let reason = format!("no {} consecutive values fit the cage sums", n);
+            return Err(Contradiction::new(self.name(), vec![], reason));
+        }
+
+        for &sum in &sums {
+            sudoku.restrict_var(
+                sum,
+                sudoku[sum].filter(|value| starts.values().any(|start| window(start).contains(value))),
+            );
+        }
+
+        // With the start known, every value of the sequence is the sum of some cage.
+        if let Some(start) = starts.value() {
+            for value in window(start).values() {
+                let holders: Vec<Var> = sums.iter().copied().filter(|&sum| sudoku[sum].contains(value)).collect();
+
+                match holders[..] {
+                    [] => {
+                        let reason = format!("no cage can add up to {}", value);
+                        return Err(Contradiction::new(self.name(), vec![], reason));
+                    }
+                    [sum] => {
+                        sudoku.restrict_var(sum, Domain::single(value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether every domain can take a different value, within `allowed` if given, found by augmenting paths.
+fn distinct(domains: &[Domain], allowed: Option<Domain>) -> bool {
+    let mut owners: HashMap<i32, usize> = HashMap::new();
+
+    (0..domains.len()).all(|i| {
+        let mut seen = vec![];
+        augment(i, domains, allowed, &mut owners, &mut seen)
+    })
+}
+
+fn augment(
+    i: usize,
+    domains: &[Domain],
+    allowed: Option<Domain>,
+    owners: &mut HashMap<i32, usize>,
+    seen: &mut Vec<i32>,
+) -> bool {
+    let domain = allowed.map_or(domains[i], |allowed| domains[i] & allowed);
+
+    for value in domain.values() {
+        if seen.contains(&value) {
+            continue;
+        }
+
+        seen.push(value);
+
+        let free = match owners.get(&value) {
+            Some(&owner) => augment(owner, domains, allowed, owners, seen),
+            None => true,
+        };
+
+        if free {
+            owners.insert(value, i);
+            return true;
+        }
+    }
+
+    false
+}
+
+impl Rule for SequenceRule {
+    fn name(&self) -> &'static str {
+        "sequence"
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        let mut units = self.cages.clone();
+        units.push(vec![]);
+
+        if let Some(palindromes) = &self.palindromes {
+            units.extend(self.equations(palindromes).iter().map(Linear::cells));
+        }
+
+        units
+    }
+
+    fn vars(&self, _: &Sudoku) -> Vec<(Var, Domain)> {
+        let mut vars: Vec<(Var, Domain)> = self
+            .cages
+            .iter()
+            .enumerate()
+            .map(|(i, cage)| {
+                let (lowest, highest) = self.bounds(cage.len());
+                (SequenceRule::sum(i), Domain::range(lowest, highest))
+            })
+            .collect();
+
+        if let Some(palindromes) = &self.palindromes {
+            let lowest = vars.iter().filter_map(|(_, domain)| domain.min()).min().unwrap();
+            let highest = vars.iter().filter_map(|(_, domain)| domain.max()).max().unwrap();
+            let (k, size) = (palindromes.len() as i32, self.size as i32);
+            let counted = FREQUENCY as i32 * size * (size + 1) / 2;
+
+            vars.push((SequenceRule::START, Domain::range(lowest, highest + 1 - self.cages.len() as i32)));
+            vars.push((SequenceRule::PALINDROME_TOTAL, Domain::range(k, k * size)));
+            let (lowest, highest) = self.bounds(palindromes.len() / 2);
+            vars.push((SequenceRule::PALINDROME_DIGITS, Domain::range(lowest, highest)));
+            vars.push((SequenceRule::TOTAL, Domain::range(counted + k, counted + k * size)));
+        }
+
+        vars
+    }
+
+    fn links(&self, _: &Sudoku) -> Vec<Vec<Var>> {
+        let mut links: Vec<Vec<Var>> = self.sums().into_iter().map(|sum| vec![sum]).collect();
+        let mut sums = self.sums();
+
+        if let Some(palindromes) = &self.palindromes {
+            sums.push(SequenceRule::START);
+            links.push(sums);
+            links.extend(self.equations(palindromes).iter().map(Linear::vars));
+        } else {
+            links.push(sums);
+        }
+
+        links
+    }
+
+    fn update_unit(&self, unit: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let n = self.cages.len();
+
+        let equation = if unit < n {
+            let terms = cells.iter().map(|&pos| (1, Term::Cell(pos)));
+            Linear::new(terms.chain([(-1, Term::Var(SequenceRule::sum(unit)))]).collect(), 0)
+        } else if unit == n {
+            return self.update_sums(sudoku);
+        } else {
+            let palindromes = self.palindromes.as_deref().expect("equations of an incomplete layout");
+            self.equations(palindromes)[unit - n - 1].clone()
+        };
+
+        match equation.propagate(sudoku) {
+            true => Ok(()),
+            false => {
+                Err(Contradiction::new(self.name(), cells.to_vec(), format!("{} can't hold", self.describe(unit))))
+            }
+        }
+    }
+
+    fn explain(&self, unit: usize, _: &[Pos], _: &Sudoku) -> String {
+        self.describe(unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::{Ruleset, SolveResult, Solver, State};
+
+    /// The pair cages with triplets in three corner boxes, which makes the layout complete.
+    fn triplets() -> Vec<Vec<Pos>> {
+        vec![vec![(0, 0), (0, 1), (1, 0)], vec![(0, 7), (0, 8), (1, 8)], vec![(8, 0), (8, 1), (7, 0)]]
+    }
+
+    #[test]
+    fn test_sequence_algebra() {
+        let ruleset = Ruleset { sum_sequence: true, cages: triplets(), ..Default::default() };
+        let solver = Solver::from_ruleset(&Sudoku::default(), &ruleset);
+        let (_, SolveResult(_, sudoku, state)) = solver.explain(Sudoku::default());
+
+        // 15S + 105 = 135 + X, with X the total of six cells, twice that of three palindrome digits.
+        assert!(matches!(state, State::Stuck));
+        assert_eq!(sudoku[SequenceRule::PALINDROME_TOTAL].value(), Some(30));
+        assert_eq!(sudoku[SequenceRule::PALINDROME_DIGITS].value(), Some(15));
+        assert_eq!(sudoku[SequenceRule::START].value(), Some(4));
+        assert_eq!(sudoku[SequenceRule::TOTAL].value(), Some(165));
+        assert!((0..15).all(|cage| sudoku[SequenceRule::sum(cage)].values().all(|sum| (4..=18).contains(&sum))));
+    }
+
+    #[test]
+    fn test_different_sums() {
+        let rule = SequenceRule::new(&[]);
+        let mut sudoku = Sudoku::default();
+        assert!(rule.vars(&sudoku).iter().all(|&(var, _)| var.name == "cage sum"));
+
+        [((0, 2), 1), ((1, 2), 5), ((0, 4), 2), ((1, 4), 3)].into_iter().for_each(|(pos, digit)| sudoku[pos] = digit);
+        assert!(rule.check(&sudoku).is_ok());
+
+        sudoku[(1, 4)] = 4;
+        let contradiction = rule.check(&sudoku).unwrap_err();
+        assert_eq!(contradiction.reason, "two cages add up to 6");
+    }
+}
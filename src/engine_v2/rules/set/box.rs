@@ -1,25 +1,161 @@
 use super::SetRule;
-use crate::engine_v2::{CellEntry, Sudoku};
+use crate::engine_v2::{Pos, Shape, Sudoku};
 
-#[derive(Default)]
-pub struct BoxRule {
-    counter: usize,
+/// The region index of every cell, replacing the standard boxes in jigsaw puzzles.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Regions {
+    size: usize,
+    cells: Vec<u8>,
 }
 
-impl SetRule for BoxRule {
-    fn next_set(&mut self, sudoku: &Sudoku) -> [CellEntry; 9] {
-        let box_row = (self.counter / 3) * 3;
-        let box_col = (self.counter % 3) * 3;
+impl Default for Regions {
+    fn default() -> Self {
+        Regions::boxes(Shape::default())
+    }
+}
+
+impl Regions {
+    /// The standard boxes of a grid.
+    pub fn boxes(shape: Shape) -> Self {
+        let Shape { size, box_height, box_width } = shape;
+        let boxes_per_row = size / box_width;
+
+        let cells = (0..size * size)
+            .map(|i| {
+                let (row, col) = (i / size, i % size);
+                ((row / box_height) * boxes_per_row + col / box_width) as u8
+            })
+            .collect();
+
+        Regions { size, cells }
+    }
+
+    /// Validates that the regions are connected shapes of `size` cells each, covering the whole grid.
+    pub fn new(regions: Vec<Vec<u8>>) -> Result<Regions, String> {
+        let size = regions.len();
+        let mut cells: Vec<Vec<Pos>> = vec![vec![]; size];
+
+        for (row, line) in regions.iter().enumerate() {
+            if line.len() != size {
+                return Err(format!("Region row {} has {} cells, expected {}", row + 1, line.len(), size));
+            }
+
+            for (col, &region) in line.iter().enumerate() {
+                let Some(region_cells) = cells.get_mut(region as usize) else {
+                    return Err(format!("Invalid region {} at row {}, col {}", region, row + 1, col + 1));
+                };
+
+                region_cells.push((row, col));
+            }
+        }
 
-        let mut result = [CellEntry::default(); 9];
+        for (region, region_cells) in cells.iter().enumerate() {
+            if region_cells.len() != size {
+                return Err(format!("Region {} has {} cells, expected {}", region + 1, region_cells.len(), size));
+            }
 
-        for i in 0..3 {
-            for j in 0..3 {
-                result[i * 3 + j] = sudoku.cell_entry((box_row + i, box_col + j));
+            if !is_connected(region_cells) {
+                return Err(format!("Region {} is not connected", region + 1));
             }
         }
 
-        self.counter = (self.counter + 1) % 9;
+        Ok(Regions { size, cells: regions.concat() })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn cells(&self) -> Vec<Vec<Pos>> {
+        let mut result = vec![vec![]; self.size];
+
+        for (i, &region) in self.cells.iter().enumerate() {
+            result[region as usize].push((i / self.size, i % self.size));
+        }
+
         result
     }
 }
+
+fn is_connected(cells: &[Pos]) -> bool {
+    let mut visited = vec![false; cells.len()];
+    let mut stack = vec![0];
+    visited[0] = true;
+
+    while let Some(i) = stack.pop() {
+        let (row, col) = cells[i];
+
+        for (j, &(r, c)) in cells.iter().enumerate() {
+            if !visited[j] && row.abs_diff(r) + col.abs_diff(c) == 1 {
+                visited[j] = true;
+                stack.push(j);
+            }
+        }
+    }
+
+    visited.iter().all(|&v| v)
+}
+
+pub struct BoxRule {
+    regions: Vec<Vec<Pos>>,
+}
+
+impl Default for BoxRule {
+    fn default() -> Self {
+        BoxRule::new(&Regions::default(), (0, 0))
+    }
+}
+
+impl BoxRule {
+    /// Applies the regions to the sub-grid whose top-left cell is `origin`.
+    pub fn new(regions: &Regions, (row, col): Pos) -> Self {
+        let regions = regions.cells().into_iter().map(|cells| cells.iter().map(|&(r, c)| (row + r, col + c)).collect());
+        BoxRule { regions: regions.collect() }
+    }
+}
+
+impl SetRule for BoxRule {
+    fn name(&self) -> &'static str {
+        "box"
+    }
+
+    fn sets(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.regions.clone()
+    }
+
+    fn describe(&self, set: usize, _: &[Pos]) -> String {
+        format!("box {}", set + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_regions_are_boxes() {
+        let rule = BoxRule::default();
+
+        assert_eq!(rule.regions[4], [(3, 3), (3, 4), (3, 5), (4, 3), (4, 4), (4, 5), (5, 3), (5, 4), (5, 5)]);
+        assert_eq!(Regions::new(labels(&Regions::default())), Ok(Regions::default()));
+
+        let rule = BoxRule::new(&Regions::boxes(Shape::new(6).unwrap()), (6, 0));
+        assert_eq!(rule.regions[1], [(6, 3), (6, 4), (6, 5), (7, 3), (7, 4), (7, 5)]);
+    }
+
+    fn labels(regions: &Regions) -> Vec<Vec<u8>> {
+        regions.cells.chunks(regions.size).map(<[u8]>::to_vec).collect()
+    }
+
+    #[test]
+    fn test_invalid_regions() {
+        let mut uneven = labels(&Regions::default());
+        uneven[0][3] = 0;
+        assert!(Regions::new(uneven).is_err());
+
+        let mut disconnected = labels(&Regions::default());
+        disconnected[0][0] = 1;
+        disconnected[0][3] = 0;
+        assert!(Regions::new(disconnected).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use super::SetRule;
-use crate::engine_v2::{CellEntry, Pos, Sudoku};
+use crate::engine_v2::{Pos, Sudoku};
 
 /// A rule that returns the sets constructed from the caged pairs in the sum-sequence Sudoku.        
 /// ```txt
@@ -15,7 +15,6 @@ use crate::engine_v2::{CellEntry, Pos, Sudoku};
 /// |____0|__0__|0____|
 /// ```
 pub struct CageRule {
-    counter: usize,
     sets: [[Pos; 9]; 2],
 }
 
@@ -34,20 +33,20 @@ impl Default for CageRule {
             sets[1][i] = (y[i], x[i]);
         }
 
-        CageRule { counter: 0, sets }
+        CageRule { sets }
     }
 }
 
 impl SetRule for CageRule {
-    fn next_set(&mut self, sudoku: &Sudoku) -> [CellEntry; 9] {
-        let mut result = [CellEntry::default(); 9];
+    fn name(&self) -> &'static str {
+        "cage-set"
+    }
 
-        for (i, res) in result.iter_mut().enumerate() {
-            let pos = self.sets[self.counter][i];
-            *res = sudoku.cell_entry(pos);
-        }
+    fn sets(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.sets.iter().map(|set| set.to_vec()).collect()
+    }
 
-        self.counter = (self.counter + 1) % self.sets.len();
-        result
+    fn describe(&self, set: usize, _: &[Pos]) -> String {
+        format!("cage set {}", set + 1)
     }
 }
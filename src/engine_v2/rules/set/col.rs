@@ -1,20 +1,30 @@
 use super::SetRule;
-use crate::engine_v2::{CellEntry, Sudoku};
+use crate::engine_v2::{Pos, Sudoku};
 
 #[derive(Default)]
 pub struct ColRule {
-    counter: usize,
+    origin: Pos,
+}
+
+impl ColRule {
+    pub fn new(origin: Pos) -> Self {
+        ColRule { origin }
+    }
 }
 
 impl SetRule for ColRule {
-    fn next_set(&mut self, sudoku: &Sudoku) -> [CellEntry; 9] {
-        let mut result = [CellEntry::default(); 9];
+    fn name(&self) -> &'static str {
+        "column"
+    }
+
+    fn sets(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        let (row, col) = self.origin;
+        let size = sudoku.size();
 
-        for (i, res) in result.iter_mut().enumerate() {
-            *res = sudoku.cell_entry((i, self.counter));
-        }
+        (0..size).map(|c| (0..size).map(|r| (row + r, col + c)).collect()).collect()
+    }
 
-        self.counter = (self.counter + 1) % 9;
-        result
+    fn describe(&self, _: usize, cells: &[Pos]) -> String {
+        format!("column {}", cells[0].1 + 1)
     }
 }
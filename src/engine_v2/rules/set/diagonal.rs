@@ -0,0 +1,34 @@
+use super::SetRule;
+use crate::engine_v2::{Pos, Sudoku};
+
+/// A rule that returns the two main diagonals of a Sudoku-X.
+#[derive(Default)]
+pub struct DiagonalRule {
+    origin: Pos,
+}
+
+impl DiagonalRule {
+    pub fn new(origin: Pos) -> Self {
+        DiagonalRule { origin }
+    }
+}
+
+impl SetRule for DiagonalRule {
+    fn name(&self) -> &'static str {
+        "diagonal"
+    }
+
+    fn sets(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        let size = sudoku.size();
+        let (row, col) = self.origin;
+
+        vec![
+            (0..size).map(|i| (row + i, col + i)).collect(),
+            (0..size).map(|i| (row + i, col + size - 1 - i)).collect(),
+        ]
+    }
+
+    fn describe(&self, set: usize, _: &[Pos]) -> String {
+        if set == 0 { "main diagonal" } else { "anti-diagonal" }.to_string()
+    }
+}
@@ -1,26 +1,48 @@
 pub mod r#box;
 pub mod cage;
 pub mod col;
+pub mod diagonal;
 pub mod row;
 
-use super::Rule;
-use crate::engine_v2::{CellEntry, CellMask, Entry, Mask, Pos, Sudoku, Value};
+use super::{Contradiction, Rule};
+use crate::engine_v2::{CellMask, Entry, Mask, Pos, Sudoku, Value};
 
-trait SetRule {
-    fn next_set(&mut self, sudoku: &Sudoku) -> [CellEntry; 9];
+/// A rule made of sets of cells that must hold distinct digits.
+trait SetRule: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn sets(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>>;
+
+    /// Names the set, such as "row 3".
+    fn describe(&self, set: usize, cells: &[Pos]) -> String;
 }
 
 impl<T: SetRule> Rule for T {
-    fn update_cells(&mut self, sudoku: &mut Sudoku) -> Result<(), ()> {
-        let mut allowed_digits = Mask::ALL;
+    fn name(&self) -> &'static str {
+        SetRule::name(self)
+    }
+
+    fn units(&self, sudoku: &Sudoku) -> Vec<Vec<Pos>> {
+        self.sets(sudoku)
+    }
+
+    fn explain(&self, unit: usize, cells: &[Pos], _: &Sudoku) -> String {
+        self.describe(unit, cells)
+    }
+
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let size = sudoku.size();
+        let mut allowed_digits = Mask::full(size);
         let mut pencilmarks: Vec<CellMask> = Vec::new();
         let mut empty_cells: Vec<Pos> = Vec::new();
 
-        let set = self.next_set(sudoku);
-
-        for &(pos, entry) in set.iter() {
+        for (pos, entry) in cells.iter().map(|&pos| sudoku.cell_entry(pos)) {
             match entry {
                 Entry::Empty => empty_cells.push(pos),
+                Entry::Digit(digit) if !allowed_digits[digit] => {
+                    let first = cells.iter().copied().find(|&other| sudoku[other] == digit.get() as Value).unwrap();
+                    let reason = format!("digit {} appears twice", digit);
+                    return Err(Contradiction::new(self.name(), vec![first, pos], reason));
+                }
                 Entry::Digit(digit) => allowed_digits.set_digit(digit, false),
                 Entry::Pencil(pm) => pencilmarks.push((pos, pm)),
             }
@@ -31,7 +53,7 @@ impl<T: SetRule> Rule for T {
         }
 
         for pm in &mut pencilmarks {
-            for i in 0..9 {
+            for i in 0..size {
                 if !allowed_digits[i] {
                     pm.1.set_bit(i, false);
                 }
@@ -42,20 +64,24 @@ impl<T: SetRule> Rule for T {
             pencilmarks.push((*cell, allowed_digits));
         }
 
-        let naked_sets = find_naked_sets(&pencilmarks);
+        // Only a unit holding every digit has a place for each one, which hidden subsets rely on.
+        let complete = cells.len() == size;
 
-        for (i, naked_cells) in naked_sets.into_iter().enumerate() {
-            let Some(naked_cells) = naked_cells else {
-                continue;
+        let subsets = find_subsets(&pencilmarks, allowed_digits, complete).map_err(|subset| {
+            let digits: Vec<String> = subset.digits.digits().map(|digit| digit.to_string()).collect();
+            let reason = match subset.kind {
+                Locking::Naked => format!("{} cells only hold digits {}", subset.cells.len(), digits.join(", ")),
+                Locking::Hidden => format!("digits {} only fit {} cells", digits.join(", "), subset.cells.len()),
             };
+            Contradiction::new(self.name(), subset.cells, reason)
+        })?;
 
+        for subset in subsets {
             for (pos, pm) in pencilmarks.iter_mut() {
-                if naked_cells.contains(pos) {
-                    continue;
-                }
-
-                if pm[i] {
-                    pm.set_bit(i, false);
+                match subset.kind {
+                    Locking::Naked if !subset.cells.contains(pos) => pm.0 &= !subset.digits.0,
+                    Locking::Hidden if subset.cells.contains(pos) => pm.0 &= subset.digits.0,
+                    _ => {}
                 }
             }
         }
@@ -64,11 +90,10 @@ impl<T: SetRule> Rule for T {
             let count = pm.0.count_ones();
 
             let new_value = match count {
-                0 => return Err(()),
+                0 => return Err(Contradiction::new(self.name(), vec![*pos], "no candidates left")),
                 1 => pm.0.trailing_zeros() as Value + 1,
                 _ => {
-                    let Mask(mut pm_value) = *pm;
-                    pm_value |= 1 << 15;
+                    let pm_value = Value::from(*pm);
 
                     if pm_value != sudoku[*pos] {
                         pm_value
@@ -78,69 +103,107 @@ impl<T: SetRule> Rule for T {
                 }
             };
 
-            sudoku[*pos] = new_value;
+            sudoku.set(*pos, new_value);
         }
 
         Ok(())
     }
 }
 
-fn find_naked_sets(marks: &[CellMask]) -> [Option<Vec<Pos>>; 9] {
-    let mut result = [const { None }; 9];
+/// Whether a subset is found by its cells or by its digits.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Locking {
+    /// Cells whose candidates fall within as many digits, which are then removed from the other cells.
+    Naked,
+    /// Digits whose places fall within as many cells, which then hold none of the other digits.
+    Hidden,
+}
 
-    fn check_combination(marks: &[CellMask], indices: &[usize], result: &mut [Option<Vec<Pos>>; 9]) {
-        let mut digits = [false; 9];
-        let mut count = 0;
+/// A group of cells locked to a group of digits of the same size.
+#[derive(Debug, PartialEq)]
+struct Subset {
+    kind: Locking,
+    cells: Vec<Pos>,
+    digits: Mask,
+}
 
-        for &idx in indices {
-            for (i, digit) in digits.iter_mut().enumerate() {
-                if !*digit && marks[idx].1[i] {
-                    *digit = true;
-                    count += 1;
-                }
-            }
-        }
+/// Finds the naked subsets, and the hidden ones when `hidden` is set, among the pencilmarks of a unit
+/// whose unplaced digits are `digits`. Naked subsets are grown from the cells and the digits they cover,
+/// hidden ones from the digits and the cells they fit, up to half the cells, as subsets larger than that
+/// are the complement of a smaller one. Hidden singles are left to `HiddenSinglesRule`, which finds them
+/// from the digit bitboards, and cells without candidates to the caller.
+/// A subset with fewer digits than cells, or fewer cells than digits, is returned as the error.
+fn find_subsets(marks: &[CellMask], digits: Mask, hidden: bool) -> Result<Vec<Subset>, Subset> {
+    let max = marks.len() as u32 / 2;
+    let bits = |mask: u32| (0..32).filter(move |i| mask & (1 << i) != 0);
+    let mut found = vec![];
 
-        if count == indices.len() {
-            let positions: Vec<Pos> = indices.iter().map(|&idx| marks[idx].0).collect();
-            for i in 0..9 {
-                if digits[i] {
-                    result[i] = Some(positions.clone());
-                }
-            }
-        }
+    if hidden {
+        let order: Vec<usize> = bits(digits.0).collect();
+        let places: Vec<u32> = order
+            .iter()
+            .map(|&digit| bits(u32::MAX).take(marks.len()).filter(|&i| marks[i].1[digit]).fold(0, |m, i| m | 1 << i))
+            .collect();
+
+        let subset = |(chosen, span): (u32, u32)| Subset {
+            kind: Locking::Hidden,
+            cells: bits(span).map(|i| marks[i].0).collect(),
+            digits: Mask(bits(chosen).fold(0, |mask, i| mask | 1 << order[i])),
+        };
+
+        let mut locked = vec![];
+        grow(&places, max, 0, (0, 0), &mut locked).map_err(subset)?;
+        found.extend(locked.into_iter().map(subset));
     }
 
-    let len = marks.len();
+    let open: Vec<usize> = (0..marks.len()).filter(|&i| marks[i].1.count() > 0).collect();
+    let masks: Vec<u32> = open.iter().map(|&i| marks[i].1.0).collect();
 
-    // Naked quads
-    for i in 0..len {
-        for j in (i + 1)..len {
-            for k in (j + 1)..len {
-                for l in (k + 1)..len {
-                    check_combination(marks, &[i, j, k, l], &mut result);
-                }
-            }
+    let subset = |(chosen, span): (u32, u32)| Subset {
+        kind: Locking::Naked,
+        cells: bits(chosen).map(|i| marks[open[i]].0).collect(),
+        digits: Mask(span),
+    };
+
+    let mut locked = vec![];
+    grow(&masks, max, 0, (0, 0), &mut locked).map_err(subset)?;
+    found.splice(0..0, locked.into_iter().map(subset));
+
+    Ok(found)
+}
+
+/// Adds the items from `start` on, one at a time, to the chosen ones and the union of what they cover,
+/// collecting every choice of two to `max` items covering as many bits. A choice covering more than `max`
+/// bits is grown no further, and one covering fewer bits than it has items is returned as the error.
+fn grow(
+    items: &[u32],
+    max: u32,
+    start: usize,
+    (chosen, span): (u32, u32),
+    found: &mut Vec<(u32, u32)>,
+) -> Result<(), (u32, u32)> {
+    for (i, &item) in items.iter().enumerate().skip(start) {
+        let next = (chosen | 1 << i, span | item);
+        let (count, covered) = (next.0.count_ones(), next.1.count_ones());
+
+        if covered < count {
+            return Err(next);
         }
-    }
 
-    // Naked triplets
-    for i in 0..len {
-        for j in (i + 1)..len {
-            for k in (j + 1)..len {
-                check_combination(marks, &[i, j, k], &mut result);
-            }
+        if covered > max {
+            continue;
+        }
+
+        if covered == count && count > 1 {
+            found.push(next);
         }
-    }
 
-    // Naked pairs
-    for i in 0..len {
-        for j in (i + 1)..len {
-            check_combination(marks, &[i, j], &mut result);
+        if count <= max {
+            grow(items, max, i + 1, next, found)?;
         }
     }
 
-    result
+    Ok(())
 }
 
 #[cfg(test)]
@@ -148,24 +211,85 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_find_naked_sets() {
-        let pencilmarks =
-            vec![((0, 0), Mask(0b0111)), ((0, 1), Mask(0b0011)), ((0, 2), Mask(0b0101)), ((0, 3), Mask(0b1001))];
-
-        let result = find_naked_sets(&pencilmarks);
-
-        let a: [Option<Vec<Pos>>; 9] = [
-            Some(vec![(0, 0), (0, 1), (0, 2)]),
-            Some(vec![(0, 0), (0, 1), (0, 2)]),
-            Some(vec![(0, 0), (0, 1), (0, 2)]),
-            Some(vec![(0, 0), (0, 1), (0, 2), (0, 3)]),
-            None,
-            None,
-            None,
-            None,
-            None,
+    fn test_find_subsets() {
+        let pencilmarks = vec![
+            ((0, 0), Mask(0b0011)),
+            ((0, 1), Mask(0b0011)),
+            ((0, 2), Mask(0b1_1100)),
+            ((0, 3), Mask(0b1_1100)),
+            ((0, 4), Mask(0b1_1111)),
         ];
 
-        assert_eq!(result, a);
+        let result = find_subsets(&pencilmarks, Mask(0b1_1111), true).unwrap();
+
+        let pair = Subset { kind: Locking::Naked, cells: vec![(0, 0), (0, 1)], digits: Mask(0b0011) };
+        assert_eq!(result, [pair]);
+    }
+
+    #[test]
+    fn test_find_hidden_subsets() {
+        let pencilmarks =
+            vec![((0, 0), Mask(0b1111)), ((0, 1), Mask(0b1111)), ((0, 2), Mask(0b1100)), ((0, 3), Mask(0b1100))];
+
+        let result = find_subsets(&pencilmarks, Mask(0b1111), true).unwrap();
+
+        let naked = Subset { kind: Locking::Naked, cells: vec![(0, 2), (0, 3)], digits: Mask(0b1100) };
+        let hidden = Subset { kind: Locking::Hidden, cells: vec![(0, 0), (0, 1)], digits: Mask(0b0011) };
+        assert_eq!(result, [naked, hidden]);
+
+        let result = find_subsets(&pencilmarks, Mask(0b1111), false).unwrap();
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn test_find_subsets_in_loose_unit() {
+        let mut pencilmarks: Vec<CellMask> = (0..16).map(|col| ((0, col), Mask(0xFFFF))).collect();
+        pencilmarks[3].1 = Mask(0b0110);
+        pencilmarks[11].1 = Mask(0b0110);
+
+        let result = find_subsets(&pencilmarks, Mask(0xFFFF), true).unwrap();
+
+        let pair = Subset { kind: Locking::Naked, cells: vec![(0, 3), (0, 11)], digits: Mask(0b0110) };
+        assert_eq!(result, [pair]);
+    }
+
+    #[test]
+    fn test_hidden_singles_left_out() {
+        let pencilmarks =
+            vec![((0, 0), Mask(0b1111)), ((0, 1), Mask(0b1110)), ((0, 2), Mask(0b1110)), ((0, 3), Mask(0b1110))];
+
+        let result = find_subsets(&pencilmarks, Mask(0b1111), true).unwrap();
+        assert!(result.iter().all(|subset| subset.digits.count() > 1));
+    }
+
+    #[test]
+    fn test_subset_conflict() {
+        let pencilmarks =
+            vec![((0, 0), Mask(0b011)), ((0, 1), Mask(0b011)), ((0, 2), Mask(0b011)), ((0, 3), Mask(0b1111))];
+
+        let result = find_subsets(&pencilmarks, Mask(0b1111), false);
+
+        let subset = Subset { kind: Locking::Naked, cells: vec![(0, 0), (0, 1), (0, 2)], digits: Mask(0b011) };
+        assert_eq!(result, Err(subset));
+
+        let result = find_subsets(&pencilmarks, Mask(0b1111), true);
+
+        let subset = Subset { kind: Locking::Hidden, cells: vec![(0, 3)], digits: Mask(0b1100) };
+        assert_eq!(result, Err(subset));
+    }
+
+    #[test]
+    fn test_repeated_digit_conflict() {
+        let mut sudoku = Sudoku::default();
+
+        sudoku[(0, 2)] = 4;
+        sudoku[(0, 7)] = 4;
+
+        let rule = row::RowRule::default();
+        let result = rule.update_unit(0, &rule.units(&sudoku)[0], &mut sudoku);
+        let contradiction = result.unwrap_err();
+
+        assert_eq!(contradiction.cells, [(0, 2), (0, 7)]);
+        assert_eq!(contradiction.to_string(), "row rule: digit 4 appears twice at r1c3, r1c8");
     }
 }
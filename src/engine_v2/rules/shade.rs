@@ -0,0 +1,38 @@
+use crate::engine_v2::rules::{Contradiction, Rule};
+use crate::engine_v2::{Pos, Shade, Sudoku};
+
+/// A rule that keeps odd, even, high or low digits in the restricted cells.
+pub struct ShadeRule {
+    cells: Vec<(Pos, Shade)>,
+}
+
+impl ShadeRule {
+    pub fn new(cells: Vec<(Pos, Shade)>) -> Self {
+        ShadeRule { cells }
+    }
+}
+
+impl Rule for ShadeRule {
+    fn name(&self) -> &'static str {
+        "cell"
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        self.cells.iter().map(|&(pos, _)| vec![pos]).collect()
+    }
+
+    fn update_unit(&self, unit: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let shade = self.cells[unit].1;
+
+        if sudoku.restrict(cells[0], shade.mask(sudoku.size())) {
+            Ok(())
+        } else {
+            let reason = format!("no {:?} digit fits", shade).to_lowercase();
+            Err(Contradiction::new(self.name(), cells.to_vec(), reason))
+        }
+    }
+
+    fn explain(&self, unit: usize, _: &[Pos], _: &Sudoku) -> String {
+        format!("{:?} cell", self.cells[unit].1).to_lowercase()
+    }
+}
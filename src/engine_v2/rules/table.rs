@@ -0,0 +1,124 @@
+use crate::engine_v2::rules::{Contradiction, Rule};
+use crate::engine_v2::{Mask, Pos, Sudoku};
+
+/// A rule that lets its cells hold only one of a list of allowed tuples of digits, such as the cage fillings
+/// enumerated by engine v1.
+///
+/// Candidates are filtered as in the compact-table algorithm: every digit of every cell has a bitset of the tuples
+/// giving it that digit, the tuples still valid are those every cell supports with one of its candidates,
+/// and a candidate stays as long as one of its tuples is valid. Unlike the compact-table algorithm, the valid
+/// tuples aren't kept as state and restored on backtracking: each update is a stateless recompute from the grid,
+/// intersecting the supports of every cell, changed or not. Rules aren't told which cells changed since their
+/// last update, and the auxiliary variables hold at most 64 values, so neither could carry the table.
+pub struct TableRule {
+    cells: Vec<Pos>,
+    tuples: usize,
+    /// The tuples giving each cell each digit, by cell then digit - 1, as words of 64 tuples.
+    supports: Vec<Vec<Vec<u64>>>,
+}
+
+impl TableRule {
+    /// A table over distinct cells, each tuple listing a digit for every cell in order.
+    pub fn new(cells: Vec<Pos>, tuples: &[Vec<u8>]) -> Self {
+        let words = tuples.len().div_ceil(64);
+        let digits = tuples.iter().flatten().copied().max().unwrap_or(0) as usize;
+        let mut supports = vec![vec![vec![0u64; words]; digits]; cells.len()];
+
+        for (t, tuple) in tuples.iter().enumerate() {
+            assert_eq!(tuple.len(), cells.len(), "Tuple {} doesn't have a digit for every cell", t + 1);
+
+            for (cell, &digit) in tuple.iter().enumerate() {
+                assert!(digit > 0, "Tuple {} holds no digit for cell {}", t + 1, cell + 1);
+                supports[cell][digit as usize - 1][t / 64] |= 1 << (t % 64);
+            }
+        }
+
+        TableRule { cells, tuples: tuples.len(), supports }
+    }
+
+    /// The tuples every cell supports with one of its candidates, rebuilt from scratch.
+    fn valid(&self, cells: &[Pos], sudoku: &Sudoku) -> Vec<u64> {
+        let mut valid = vec![u64::MAX; self.tuples.div_ceil(64)];
+
+        if let Some(last) = valid.last_mut()
+            && !self.tuples.is_multiple_of(64)
+        {
+            *last = (1 << (self.tuples % 64)) - 1;
+        }
+
+        for (supports, &pos) in self.supports.iter().zip(cells) {
+            let mut supported = vec![0u64; valid.len()];
+
+            for digit in sudoku.candidates(pos).digits().filter(|&digit| digit as usize <= supports.len()) {
+                supported.iter_mut().zip(&supports[digit as usize - 1]).for_each(|(word, support)| *word |= support);
+            }
+
+            valid.iter_mut().zip(&supported).for_each(|(word, supported)| *word &= supported);
+        }
+
+        valid
+    }
+}
+
+impl Rule for TableRule {
+    fn name(&self) -> &'static str {
+        "table"
+    }
+
+    fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+        vec![self.cells.clone()]
+    }
+
+    /// Recomputes the valid tuples from the candidates of every cell, keeping nothing from earlier updates.
+    fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+        let valid = self.valid(cells, sudoku);
+
+        if valid.iter().all(|&word| word == 0) {
+            return Err(Contradiction::new(self.name(), cells.to_vec(), "no allowed tuple fits"));
+        }
+
+        for (supports, &pos) in self.supports.iter().zip(cells) {
+            let supported = |digit: u8| {
+                let support = supports.get(digit as usize - 1);
+                support.is_some_and(|support| support.iter().zip(&valid).any(|(a, b)| a & b != 0))
+            };
+
+            // Every valid tuple gives the cell one of its candidates, so one is left.
+            sudoku.restrict(pos, Mask::from_digits(sudoku.candidates(pos).digits().filter(|&digit| supported(digit))));
+        }
+
+        Ok(())
+    }
+
+    fn explain(&self, _: usize, _: &[Pos], _: &Sudoku) -> String {
+        format!("table of {} tuples", self.tuples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_candidates() {
+        let cells = vec![(0, 0), (0, 1), (1, 0)];
+        let mut tuples: Vec<Vec<u8>> = vec![vec![1, 2, 3], vec![1, 4, 5], vec![2, 4, 6]];
+        tuples.extend((0..70).map(|_| vec![9, 9, 9]));
+        let rule = TableRule::new(cells.clone(), &tuples);
+
+        let mut sudoku = Sudoku::default();
+        sudoku[(0, 1)] = 4;
+        sudoku[(1, 0)] = Mask::from_digits([5, 6, 7]).into();
+
+        assert!(rule.update_unit(0, &cells, &mut sudoku).is_ok());
+        assert_eq!(sudoku.candidates((0, 0)), Mask::from_digits([1, 2]));
+        assert_eq!(sudoku.candidates((1, 0)), Mask::from_digits([5, 6]));
+
+        sudoku.set((0, 0), 2);
+        assert!(rule.update_unit(0, &cells, &mut sudoku).is_ok());
+        assert_eq!(sudoku.candidates((1, 0)), Mask::from_digits([6]));
+
+        sudoku.set((1, 0), 5);
+        assert!(rule.update_unit(0, &cells, &mut sudoku).is_err());
+    }
+}
@@ -0,0 +1,976 @@
+use super::parallel::parallel_map;
+use super::registry::Registry;
+use super::rules::{Contradiction, Elimination, Rule};
+use super::stats::{Stats, progress};
+use super::{Checkpoint, Entry, Mask, Pos, Ruleset, Sudoku, Value, Var};
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How a search ended.
+#[derive(Debug)]
+pub enum State {
+    Solved,
+    /// A rule found that the grid can't be completed.
+    Unsolvable(Contradiction),
+    /// No rule or trial made progress. The grid may still have any number of solutions, as rules and candidate
+    /// tests don't find every deduction.
+    Stuck,
+    /// The number of rule updates reached the limit.
+    LimitReached,
+    /// The time budget ran out.
+    TimeLimitReached,
+    /// The number of candidate tests and guesses reached the node limit.
+    NodeLimitReached,
+    /// The search was stopped through its `CancelToken`.
+    Cancelled,
+}
+
+impl State {
+    /// Whether a budget or a cancellation stopped the search before it could decide the grid.
+    /// The grid is then left as far as the search got outside of any candidate test.
+    pub fn is_interrupted(&self) -> bool {
+        matches!(self, State::LimitReached | State::TimeLimitReached | State::NodeLimitReached | State::Cancelled)
+    }
+}
+
+/// Stops a running search from another thread. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Makes every search using the token stop after its current rule update.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The number of rule updates made, the grid as far as it was filled in, and how the search ended.
+pub struct SolveResult(pub usize, pub Sudoku, pub State);
+
+/// The default number of rule updates after which a search gives up.
+pub const DEFAULT_LIMIT: usize = 2_000_000;
+
+/// The most pending branches of an enumeration searched together, whatever the number of threads.
+const BATCH: usize = 16;
+
+/// Solves grids with a list of rules, which may include user-defined `Rule` implementations.
+/// Each rule runs in a priority tier, and the units of a tier are only updated once every lower tier is idle.
+pub struct Solver {
+    rules: Vec<Box<dyn Rule>>,
+    priorities: Vec<u8>,
+    limit: usize,
+    time_limit: Option<Duration>,
+    node_limit: usize,
+    cancel: Option<CancelToken>,
+    threads: Option<usize>,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver {
+            rules: vec![],
+            priorities: vec![],
+            limit: DEFAULT_LIMIT,
+            time_limit: None,
+            node_limit: usize::MAX,
+            cancel: None,
+            threads: None,
+        }
+    }
+}
+
+impl Solver {
+    /// A solver without any rules.
+    pub fn new() -> Self {
+        Solver::default()
+    }
+
+    /// A solver with the rows, columns and boxes of every sub-grid of the sudoku, followed by every
+    /// constraint of the ruleset, the hidden singles and fish, as listed by `Registry::default_specs`.
+    /// Constraints that don't fit the grid are left out, which `Ruleset::validate` reports beforehand.
+    pub fn from_ruleset(sudoku: &Sudoku, ruleset: &Ruleset) -> Self {
+        let registry = Registry::standard();
+        let mut solver = Solver::new();
+
+        for spec in Registry::default_specs(ruleset) {
+            if let Ok(rules) = registry.rules(&spec.name, sudoku, ruleset) {
+                rules.into_iter().for_each(|rule| solver.add_boxed(rule, spec.priority));
+            }
+        }
+
+        solver
+    }
+
+    /// Adds a rule in the tier of its own `Rule::priority`.
+    pub fn add_rule(&mut self, rule: impl Rule + 'static) {
+        self.add_boxed(Box::new(rule), None);
+    }
+
+    /// Adds a rule in the given tier, or in the tier of its own `Rule::priority` when there is none.
+    pub fn add_boxed(&mut self, rule: Box<dyn Rule>, priority: Option<u8>) {
+        self.priorities.push(priority.unwrap_or_else(|| rule.priority()));
+        self.rules.push(rule);
+    }
+
+    pub fn with_rule(mut self, rule: impl Rule + 'static) -> Self {
+        self.add_rule(rule);
+        self
+    }
+
+    /// Sets the number of rule updates after which a search gives up.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets the wall time after which a search gives up, counted from the start of each solve.
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    /// Sets the number of candidate tests and guessed digits after which a search gives up.
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    /// Stops searches once the token is cancelled, which may happen from another thread.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Spreads the work over threads. Candidates are then tested in rounds against the same grid,
+    /// and `solutions` searches up to 16 pending branches at once. Grids, states and iteration counts
+    /// are the same for any number of threads, including one, though they can differ from a solver
+    /// without threads, which tests candidates one after another on a grid refined as it goes.
+    /// This holds for runs that aren't interrupted: the threads draw on the same time and node budgets,
+    /// so which tests a limit cuts short depends on how they are scheduled.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    pub fn rules(&self) -> &[Box<dyn Rule>] {
+        &self.rules
+    }
+
+    /// Fills in every digit the rules and candidate tests can prove, without guessing.
+    pub fn solve(&self, sudoku: Sudoku) -> SolveResult {
+        self.solve_with_stats(sudoku).0
+    }
+
+    /// Solves like `solve`, also reporting the work done by each rule and by the candidate tests.
+    pub fn solve_with_stats(&self, mut sudoku: Sudoku) -> (SolveResult, Stats) {
+        self.declare(&mut sudoku);
+        let scheduler = Scheduler::new(self, &sudoku);
+        let mut context = self.context();
+        let state = search(&mut sudoku, &scheduler, scheduler.full_queue(), &mut context);
+
+        (SolveResult(context.counter, sudoku, state), context.stats)
+    }
+
+    /// Runs the rules without testing candidates, recording what each of them eliminated.
+    pub fn explain(&self, mut sudoku: Sudoku) -> (Vec<Elimination>, SolveResult) {
+        self.declare(&mut sudoku);
+        let scheduler = Scheduler::new(self, &sudoku);
+        let mut log = vec![];
+        let mut context = self.context();
+        let state = scheduler.propagate(&mut sudoku, &mut scheduler.full_queue(), &mut context, Some(&mut log));
+
+        let state = state.unwrap_or_else(|| {
+            let done = sudoku.positions().all(|pos| matches!(sudoku.entry(pos), Entry::Digit(_)));
+            if done { State::Solved } else { State::Stuck }
+        });
+
+        (log, SolveResult(context.counter, sudoku, state))
+    }
+
+    /// Adds the auxiliary variables of every rule to the grid.
+    fn declare(&self, sudoku: &mut Sudoku) {
+        for rule in &self.rules {
+            rule.vars(sudoku).into_iter().for_each(|(var, domain)| sudoku.declare(var, domain));
+        }
+    }
+
+    fn context(&self) -> Context {
+        let (stats, _) = Stats::new(self.rules.iter().map(|rule| rule.name()));
+
+        Context {
+            counter: 0,
+            limit: self.limit,
+            deadline: self.time_limit.map(|time_limit| Instant::now() + time_limit),
+            node_limit: self.node_limit,
+            spent: Arc::default(),
+            cancel: self.cancel.clone(),
+            threads: self.threads,
+            stats,
+        }
+    }
+
+    /// Checks a grid against every rule, as done for each solution found.
+    pub fn check(&self, sudoku: &Sudoku) -> Result<(), Contradiction> {
+        self.rules.iter().try_for_each(|rule| rule.check(sudoku))
+    }
+
+    /// Enumerates every solution of the grid, guessing a digit whenever the rules get stuck.
+    /// The iteration ends early once a limit is reached or the search is cancelled, which `Solutions::limit_reached` tells apart.
+    pub fn solutions(&self, mut sudoku: Sudoku) -> Solutions<'_> {
+        self.declare(&mut sudoku);
+        let scheduler = Scheduler::new(self, &sudoku);
+        let queue = scheduler.full_queue();
+        let context = self.context();
+
+        Solutions { scheduler, stack: vec![Node::Pending(sudoku, queue)], context, limit_reached: false }
+    }
+}
+
+/// An iterator over the solutions of a grid, depth first.
+pub struct Solutions<'a> {
+    scheduler: Scheduler<'a>,
+    stack: Vec<Node>,
+    context: Context,
+    limit_reached: bool,
+}
+
+impl Solutions<'_> {
+    /// The number of rule updates made so far.
+    pub fn iterations(&self) -> usize {
+        self.context.counter
+    }
+
+    /// The work done so far, with a branch for every digit guessed.
+    pub fn stats(&self) -> &Stats {
+        &self.context.stats
+    }
+
+    /// Whether the iteration stopped because a limit was reached or the search was cancelled,
+    /// rather than because every solution was found.
+    pub fn limit_reached(&self) -> bool {
+        self.limit_reached
+    }
+
+    /// Searches the pending branches on top of the stack, up to `BATCH` of them, spread over the threads.
+    /// A single branch keeps the threads for its own candidate tests.
+    fn search_batch(&mut self, threads: usize) {
+        let pending = self.stack.iter().rev().take(BATCH).take_while(|node| matches!(node, Node::Pending(..))).count();
+        let batch = self.stack.split_off(self.stack.len() - pending);
+
+        if let [Node::Pending(sudoku, queue)] = &batch[..] {
+            let (mut sudoku, queue) = (sudoku.clone(), queue.clone());
+            let state = search(&mut sudoku, &self.scheduler, queue, &mut self.context);
+            self.stack.push(Node::Searched(sudoku, state));
+            return;
+        }
+
+        let forks: Vec<_> = batch.into_iter().map(|node| (node, self.context.fork())).collect();
+        let scheduler = &self.scheduler;
+
+        let searched = parallel_map(forks, threads, |(node, mut fork)| {
+            let Node::Pending(mut sudoku, queue) = node else { unreachable!() };
+            let state = search(&mut sudoku, scheduler, queue, &mut fork);
+            (Node::Searched(sudoku, state), fork)
+        });
+
+        for (node, fork) in searched {
+            self.context.absorb(fork);
+            self.stack.push(node);
+        }
+    }
+}
+
+/// A branch of an enumeration, before and after its search.
+enum Node {
+    Pending(Sudoku, Queue),
+    Searched(Sudoku, State),
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Sudoku;
+
+    fn next(&mut self) -> Option<Sudoku> {
+        while let Some(node) = self.stack.pop() {
+            let (mut sudoku, state) = match (node, self.context.threads) {
+                (Node::Searched(sudoku, state), _) => (sudoku, state),
+                (Node::Pending(mut sudoku, queue), None) => {
+                    let state = search(&mut sudoku, &self.scheduler, queue, &mut self.context);
+                    (sudoku, state)
+                }
+                (node, Some(threads)) => {
+                    self.stack.push(node);
+                    self.search_batch(threads);
+                    continue;
+                }
+            };
+
+            match state {
+                State::Solved => return Some(sudoku),
+                State::Unsolvable(_) => {}
+                State::LimitReached | State::TimeLimitReached | State::NodeLimitReached | State::Cancelled => {
+                    self.stack.clear();
+                    self.limit_reached = true;
+                }
+                State::Stuck => {
+                    // Branch on the cell with the fewest candidates, pushed so that lower digits come out first.
+                    // Empty cells are open to every digit.
+                    let open = sudoku.positions().filter(|&pos| !matches!(sudoku[pos].into(), Entry::Digit(_)));
+                    let Some((pos, mask)) =
+                        open.map(|pos| (pos, sudoku.candidates(pos))).min_by_key(|(_, mask)| mask.count())
+                    else {
+                        continue;
+                    };
+
+                    sudoku.clear_trail();
+                    self.context.branch(mask.count() as usize);
+
+                    for digit in mask.digits().rev() {
+                        let mut branch = sudoku.clone();
+                        branch.set(pos, digit as Value);
+
+                        let mut queue = self.scheduler.empty_queue();
+                        self.scheduler.wake(pos, &mut queue);
+                        self.stack.push(Node::Pending(branch, queue));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// The units of every rule, and the units each cell belongs to, so that a change
+/// only wakes up the constraints that can react to it.
+struct Scheduler<'a> {
+    rules: &'a [Box<dyn Rule>],
+    /// The entry of each rule in the stats, shared by rules of the same name.
+    slots: Vec<usize>,
+    units: Vec<Unit>,
+    watchers: Vec<Vec<usize>>,
+    /// The units reading each auxiliary variable.
+    var_watchers: HashMap<Var, Vec<usize>>,
+    tiers: usize,
+    width: usize,
+}
+
+/// The counters of one solve, shared by every nested search.
+struct Context {
+    /// The rule updates of this context, while `spent` also holds those of its forks.
+    counter: usize,
+    limit: usize,
+    deadline: Option<Instant>,
+    node_limit: usize,
+    spent: Arc<Spent>,
+    cancel: Option<CancelToken>,
+    /// The threads candidate tests are spread over, with `None` testing them one after another.
+    threads: Option<usize>,
+    stats: Stats,
+}
+
+impl Context {
+    /// A context for work done on another thread, drawing on the same budgets.
+    /// Its own candidate tests run one after another.
+    fn fork(&self) -> Context {
+        Context {
+            counter: 0,
+            limit: self.limit,
+            deadline: self.deadline,
+            node_limit: self.node_limit,
+            spent: self.spent.clone(),
+            cancel: self.cancel.clone(),
+            threads: None,
+            stats: self.stats.fork(),
+        }
+    }
+
+    fn absorb(&mut self, fork: Context) {
+        self.counter += fork.counter;
+        self.stats.absorb(fork.stats);
+    }
+
+    fn update(&mut self) {
+        self.counter += 1;
+        self.spent.updates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn branch(&mut self, count: usize) {
+        self.stats.branches += count;
+        self.spent.nodes.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Takes a node for a candidate test, unless the forks have used them all up in the meantime.
+    fn enter_trial(&mut self) -> Option<State> {
+        if self.spent.nodes.fetch_add(1, Ordering::Relaxed) >= self.node_limit {
+            return Some(State::NodeLimitReached);
+        }
+
+        self.stats.enter_trial();
+        None
+    }
+
+    /// The state to stop with when a budget ran out or the search was cancelled.
+    fn interruption(&self) -> Option<State> {
+        if self.spent.updates.load(Ordering::Relaxed) >= self.limit {
+            Some(State::LimitReached)
+        } else if self.spent.nodes.load(Ordering::Relaxed) >= self.node_limit {
+            Some(State::NodeLimitReached)
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Some(State::TimeLimitReached)
+        } else if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            Some(State::Cancelled)
+        } else {
+            None
+        }
+    }
+}
+
+/// The rule updates, and the candidate tests and guesses, of a solve and all of its forks,
+/// so that a threaded search stops at the same budgets as a sequential one.
+#[derive(Default)]
+struct Spent {
+    updates: AtomicUsize,
+    nodes: AtomicUsize,
+}
+
+/// A unit of a rule, with the tier it is queued in.
+struct Unit {
+    rule: usize,
+    index: usize,
+    tier: usize,
+    cells: Vec<Pos>,
+}
+
+/// The units waiting to be updated, one first-in first-out queue per tier, each unit queued at most once.
+#[derive(Clone)]
+struct Queue {
+    pending: Vec<VecDeque<usize>>,
+    queued: Vec<bool>,
+}
+
+impl Queue {
+    fn push(&mut self, unit: usize, tier: usize) {
+        if !self.queued[unit] {
+            self.queued[unit] = true;
+            self.pending[tier].push_back(unit);
+        }
+    }
+
+    /// The oldest unit of the lowest tier with any.
+    fn pop(&mut self) -> Option<usize> {
+        let unit = self.pending.iter_mut().find_map(VecDeque::pop_front)?;
+        self.queued[unit] = false;
+        Some(unit)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.iter().all(VecDeque::is_empty)
+    }
+}
+
+impl<'a> Scheduler<'a> {
+    fn new(solver: &'a Solver, sudoku: &Sudoku) -> Self {
+        let mut units = vec![];
+        let mut watchers = vec![vec![]; sudoku.cells.len()];
+        let mut var_watchers: HashMap<Var, Vec<usize>> = HashMap::new();
+
+        for (rule, (boxed, &priority)) in solver.rules.iter().zip(&solver.priorities).enumerate() {
+            let mut links = boxed.links(sudoku).into_iter();
+
+            for (index, cells) in boxed.units(sudoku).into_iter().enumerate() {
+                for &(row, col) in &cells {
+                    watchers[row * sudoku.width + col].push(units.len());
+                }
+
+                for var in links.next().unwrap_or_default() {
+                    var_watchers.entry(var).or_default().push(units.len());
+                }
+
+                units.push(Unit { rule, index, tier: priority as usize, cells });
+            }
+        }
+
+        let tiers = solver.priorities.iter().max().map_or(1, |&max| max as usize + 1);
+        let (_, slots) = Stats::new(solver.rules.iter().map(|rule| rule.name()));
+        Scheduler { rules: &solver.rules, slots, units, watchers, var_watchers, tiers, width: sudoku.width }
+    }
+
+    fn empty_queue(&self) -> Queue {
+        Queue { pending: vec![VecDeque::new(); self.tiers], queued: vec![false; self.units.len()] }
+    }
+
+    fn full_queue(&self) -> Queue {
+        let mut queue = self.empty_queue();
+        self.units.iter().enumerate().for_each(|(unit, Unit { tier, .. })| queue.push(unit, *tier));
+        queue
+    }
+
+    /// Schedules every unit containing the cell.
+    fn wake(&self, (row, col): Pos, queue: &mut Queue) {
+        for &unit in &self.watchers[row * self.width + col] {
+            queue.push(unit, self.units[unit].tier);
+        }
+    }
+
+    /// Updates queued units until none are left, returning the state that stopped it early if any.
+    /// Every elimination is recorded in the log when there is one.
+    fn propagate(
+        &self,
+        sudoku: &mut Sudoku,
+        queue: &mut Queue,
+        context: &mut Context,
+        mut log: Option<&mut Vec<Elimination>>,
+    ) -> Option<State> {
+        while let Some(unit) = queue.pop() {
+            let &Unit { rule, index, ref cells, .. } = &self.units[unit];
+            context.update();
+
+            let stats = &mut context.stats.rules[self.slots[rule]];
+            let rule = &self.rules[rule];
+            let checkpoint = sudoku.checkpoint();
+            let reason = log.is_some().then(|| rule.explain(index, cells, sudoku));
+
+            stats.calls += 1;
+
+            let start = Instant::now();
+            let result = rule.update_unit(index, cells, sudoku);
+            stats.time += start.elapsed();
+
+            if let Err(contradiction) = result {
+                stats.contradictions += 1;
+                return Some(State::Unsolvable(contradiction));
+            }
+
+            let (placed, removed) = progress(sudoku, checkpoint);
+            stats.placed += placed;
+            stats.removed += removed;
+
+            if let (Some(log), Some(reason)) = (log.as_deref_mut(), reason) {
+                log.extend(eliminations(sudoku, checkpoint, rule.name(), &reason));
+            }
+
+            for pos in sudoku.changes_since(checkpoint) {
+                self.wake(pos, queue);
+            }
+
+            for var in sudoku.var_changes_since(checkpoint) {
+                for &unit in self.var_watchers.get(&var).into_iter().flatten() {
+                    queue.push(unit, self.units[unit].tier);
+                }
+            }
+
+            if let Some(state) = context.interruption() {
+                return Some(state);
+            }
+        }
+
+        None
+    }
+}
+
+/// The candidates removed from every cell changed since the checkpoint.
+fn eliminations(sudoku: &Sudoku, checkpoint: Checkpoint, rule: &'static str, reason: &str) -> Vec<Elimination> {
+    let size = sudoku.size();
+    let mut result: Vec<Elimination> = vec![];
+
+    for &(index, old) in &sudoku.trail[checkpoint.0..] {
+        let pos = (index / sudoku.width(), index % sudoku.width());
+
+        if result.iter().all(|elimination| elimination.pos != pos) {
+            let before = Sudoku::mask_of(old, size);
+            let removed = Mask(before.0 & !sudoku.candidates(pos).0);
+            result.push(Elimination { rule, reason: reason.to_string(), pos, removed });
+        }
+    }
+
+    result.retain(|elimination| elimination.removed.count() > 0);
+    result
+}
+
+/// Propagates and tests candidates until the sudoku is solved or no candidate can be ruled out.
+/// Every candidate is tried in place and rolled back to a checkpoint afterwards,
+/// so an interrupted search leaves the grid with only what was proven.
+fn search(sudoku: &mut Sudoku, scheduler: &Scheduler, mut queue: Queue, context: &mut Context) -> State {
+    loop {
+        // Outside a trial no checkpoint is outstanding, so the changes so far never have to be undone.
+        if !context.stats.in_trial() {
+            sudoku.clear_trail();
+        }
+
+        if let Some(state) = scheduler.propagate(sudoku, &mut queue, context, None) {
+            return state;
+        }
+
+        let done = sudoku.positions().all(|pos| matches!(sudoku[pos].into(), Entry::Digit(_)));
+
+        if done {
+            return match scheduler.rules.iter().try_for_each(|rule| rule.check(sudoku)) {
+                Ok(()) => State::Solved,
+                Err(contradiction) => State::Unsolvable(contradiction),
+            };
+        }
+
+        let interruption = match context.threads {
+            Some(threads) => test_in_rounds(sudoku, scheduler, &mut queue, context, threads),
+            None => test_in_place(sudoku, scheduler, &mut queue, context),
+        };
+
+        if let Some(state) = interruption {
+            return state;
+        }
+
+        if queue.is_empty() {
+            return State::Stuck;
+        }
+    }
+}
+
+/// Tests every candidate in place, rolling each trial back to a checkpoint and removing the candidates that fail.
+/// Returns the state that interrupted it, if any.
+fn test_in_place(
+    sudoku: &mut Sudoku,
+    scheduler: &Scheduler,
+    queue: &mut Queue,
+    context: &mut Context,
+) -> Option<State> {
+    for (i, j) in sudoku.positions().collect::<Vec<_>>() {
+        let mut cell_value = sudoku[(i, j)];
+        if let Entry::Pencil(pm) = cell_value.into() {
+            for d in 0..sudoku.size() {
+                if !pm[d] {
+                    continue;
+                }
+
+                if let Some(state) = context.interruption().or_else(|| context.enter_trial()) {
+                    return Some(state);
+                }
+
+                let checkpoint = sudoku.checkpoint();
+                sudoku.set((i, j), d as Value + 1);
+
+                let mut test_queue = scheduler.empty_queue();
+                scheduler.wake((i, j), &mut test_queue);
+
+                let state = search(sudoku, scheduler, test_queue, context);
+                context.stats.leave_trial();
+                sudoku.restore(checkpoint);
+
+                if state.is_interrupted() {
+                    return Some(state);
+                }
+
+                if let State::Unsolvable(_) = state {
+                    context.stats.refuted += 1;
+                    cell_value &= !(1 << d);
+                    sudoku.set((i, j), cell_value);
+                    scheduler.wake((i, j), queue);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Tests every candidate of the grid on its own copy, spread over the threads,
+/// then removes the candidates that fail in grid order, so the outcome doesn't depend on the number of threads.
+/// Returns the first state, in grid order, that interrupted a test, if any.
+fn test_in_rounds(
+    sudoku: &mut Sudoku,
+    scheduler: &Scheduler,
+    queue: &mut Queue,
+    context: &mut Context,
+    threads: usize,
+) -> Option<State> {
+    if let Some(state) = context.interruption() {
+        return Some(state);
+    }
+
+    sudoku.clear_trail();
+
+    let trials: Vec<(Pos, u8)> = sudoku
+        .positions()
+        .filter(|&pos| matches!(sudoku.entry(pos), Entry::Pencil(_)))
+        .flat_map(|pos| sudoku.candidates(pos).digits().map(move |digit| (pos, digit)))
+        .collect();
+
+    let grid = &*sudoku;
+    let forks: Vec<_> = trials.iter().map(|&trial| (trial, context.fork())).collect();
+
+    let results = parallel_map(forks, threads, |((pos, digit), mut fork)| {
+        if let Some(state) = fork.interruption().or_else(|| fork.enter_trial()) {
+            return (state, fork);
+        }
+
+        let mut trial = grid.clone();
+        trial.set(pos, digit as Value);
+
+        let mut test_queue = scheduler.empty_queue();
+        scheduler.wake(pos, &mut test_queue);
+
+        let state = search(&mut trial, scheduler, test_queue, &mut fork);
+        (state, fork)
+    });
+
+    let mut interruption = None;
+
+    // The work of every fork counts, even past an interruption, as it already drew on the budgets.
+    for ((pos, digit), (state, fork)) in trials.into_iter().zip(results) {
+        context.absorb(fork);
+
+        if interruption.is_some() {
+            continue;
+        }
+
+        if state.is_interrupted() {
+            interruption = Some(state);
+        } else if let State::Unsolvable(_) = state {
+            context.stats.refuted += 1;
+            sudoku.set(pos, Mask(sudoku.candidates(pos).0 & !(1 << (digit - 1))).into());
+            scheduler.wake(pos, queue);
+        }
+    }
+
+    interruption
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::rules::{BoxRule, HiddenSinglesRule};
+
+    fn solution() -> Sudoku {
+        Sudoku::from([
+            [9, 6, 5, 8, 4, 2, 1, 7, 3],
+            [4, 1, 2, 7, 3, 5, 9, 8, 6],
+            [8, 3, 7, 9, 1, 6, 5, 4, 2],
+            [3, 7, 9, 1, 2, 8, 6, 5, 4],
+            [2, 5, 4, 6, 9, 7, 3, 1, 8],
+            [1, 8, 6, 4, 5, 3, 2, 9, 7],
+            [7, 2, 3, 5, 8, 1, 4, 6, 9],
+            [5, 9, 8, 2, 6, 4, 7, 3, 1],
+            [6, 4, 1, 3, 7, 9, 8, 2, 5],
+        ])
+    }
+
+    #[test]
+    fn test_explain_eliminations() {
+        let mut sudoku = solution();
+        sudoku[(0, 0)] = 0;
+
+        let solver = Solver::from_ruleset(&sudoku, &Ruleset::default());
+        let (log, SolveResult(_, result, state)) = solver.explain(sudoku);
+
+        assert!(matches!(state, State::Solved));
+        assert_eq!(result[(0, 0)], 9);
+        assert_eq!(log[0].to_string(), "box rule, box 1: removed 1, 2, 3, 4, 5, 6, 7, 8 from r1c1");
+    }
+
+    #[test]
+    fn test_lower_tiers_run_first() {
+        let mut sudoku = solution();
+        sudoku[(0, 0)] = 0;
+
+        let solver = Solver::new().with_rule(HiddenSinglesRule::default()).with_rule(BoxRule::default());
+        let (log, _) = solver.explain(sudoku.clone());
+        assert_eq!(log[0].rule, "box");
+
+        let mut solver = Solver::new();
+        solver.add_boxed(Box::new(HiddenSinglesRule::default()), Some(0));
+        solver.add_rule(BoxRule::default());
+        let (log, _) = solver.explain(sudoku);
+        assert_eq!(log[0].to_string(), "hidden-singles rule, row 1: removed 1, 2, 3, 4, 5, 6, 7, 8 from r1c1");
+    }
+
+    #[test]
+    fn test_stats_per_rule() {
+        let mut sudoku = solution();
+        (0..9).for_each(|col| sudoku[(4, col)] = 0);
+        sudoku[(0, 0)] = 0;
+
+        let solver = Solver::from_ruleset(&sudoku, &Ruleset::default());
+        let (SolveResult(counter, _, state), stats) = solver.solve_with_stats(sudoku);
+
+        assert!(matches!(state, State::Solved));
+        assert_eq!(stats.iterations(), counter);
+        assert_eq!(
+            stats.rules.iter().map(|rule| rule.name).collect::<Vec<_>>(),
+            ["box", "column", "row", "hidden-singles", "fish"]
+        );
+        assert_eq!(stats.rules.iter().map(|rule| rule.placed).sum::<usize>(), 10);
+        assert_eq!(stats.trials, 0);
+    }
+
+    fn puzzle() -> Sudoku {
+        Sudoku::from([
+            [0, 6, 0, 8, 0, 0, 0, 0, 0],
+            [4, 0, 0, 0, 0, 5, 0, 8, 0],
+            [0, 3, 7, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 4, 0, 9, 7, 3, 0, 0],
+            [0, 0, 0, 0, 5, 3, 0, 0, 0],
+            [7, 0, 0, 0, 0, 1, 4, 6, 0],
+            [5, 9, 0, 0, 0, 4, 7, 3, 0],
+            [0, 0, 1, 0, 7, 0, 0, 0, 5],
+        ])
+    }
+
+    #[test]
+    fn test_node_limit_keeps_proven_candidates() {
+        let solver = Solver::from_ruleset(&puzzle(), &Ruleset::default()).with_node_limit(10);
+        let (SolveResult(_, sudoku, state), stats) = solver.solve_with_stats(puzzle());
+
+        assert!(matches!(state, State::NodeLimitReached));
+        assert_eq!(stats.trials, 10);
+
+        let solution = solution();
+        for pos in sudoku.positions() {
+            assert!(sudoku.candidates(pos).contains(solution[pos] as u8));
+        }
+    }
+
+    /// An interrupted run only promises the limit and sound candidates for every thread count,
+    /// not the same grid, as the threads share the budget.
+    #[test]
+    fn test_threads_share_the_node_limit() {
+        for threads in [1, 4, 8] {
+            let solver = Solver::from_ruleset(&puzzle(), &Ruleset::default()).with_node_limit(10).with_threads(threads);
+            let (SolveResult(_, sudoku, state), stats) = solver.solve_with_stats(puzzle());
+
+            assert!(matches!(state, State::NodeLimitReached));
+            assert_eq!(stats.trials, 10);
+
+            let solution = solution();
+            for pos in sudoku.positions() {
+                assert!(sudoku.candidates(pos).contains(solution[pos] as u8));
+            }
+        }
+    }
+
+    #[test]
+    fn test_trail_cleared_outside_trials() {
+        let solver = Solver::from_ruleset(&puzzle(), &Ruleset::default());
+        let SolveResult(_, sudoku, state) = solver.solve(puzzle());
+        assert!(matches!(state, State::Solved));
+
+        // Only the changes since the last round of candidate tests are left, where the whole solve makes
+        // several times as many changes as there are cells.
+        assert!(sudoku.trail.len() < sudoku.positions().count());
+    }
+
+    #[test]
+    fn test_cancel_and_time_limit() {
+        let token = CancelToken::new();
+        let remote = token.clone();
+        std::thread::spawn(move || remote.cancel()).join().unwrap();
+
+        let solver = Solver::from_ruleset(&puzzle(), &Ruleset::default()).with_cancel_token(token);
+        let SolveResult(counter, sudoku, state) = solver.solve(puzzle());
+        assert!(matches!(state, State::Cancelled));
+        assert_eq!(counter, 1);
+        assert_eq!(sudoku[(0, 1)], 6);
+
+        let solver = Solver::from_ruleset(&puzzle(), &Ruleset::default()).with_time_limit(Duration::ZERO);
+        let SolveResult(_, _, state) = solver.solve(puzzle());
+        assert!(matches!(state, State::TimeLimitReached) && state.is_interrupted());
+    }
+
+    #[test]
+    fn test_threads_are_deterministic() {
+        let solve = |threads| {
+            let solver = Solver::from_ruleset(&puzzle(), &Ruleset::default()).with_threads(threads);
+            let (SolveResult(counter, sudoku, state), stats) = solver.solve_with_stats(puzzle());
+            assert!(matches!(state, State::Solved));
+            (counter, sudoku, stats.trials, stats.refuted, stats.max_depth)
+        };
+
+        let single = solve(1);
+        assert_eq!(single.1, solution());
+        assert_eq!(solve(3), single);
+        assert_eq!(solve(8), single);
+    }
+
+    #[test]
+    fn test_parallel_solutions_match() {
+        // Two rectangles whose digits can be swapped, and more blanks for the rules to fill.
+        let mut sudoku = solution();
+        for pos in [(0, 2), (0, 5), (1, 2), (1, 5), (0, 3), (0, 7), (1, 3), (1, 7)] {
+            sudoku[pos] = 0;
+        }
+        for pos in solution().positions().filter(|(row, col)| (row + col) % 4 == 0) {
+            sudoku[pos] = 0;
+        }
+
+        let solver = Solver::from_ruleset(&sudoku, &Ruleset::default());
+        let expected: Vec<Sudoku> = solver.solutions(sudoku.clone()).collect();
+        assert_eq!(expected.len(), 4);
+
+        let enumerate = |threads| {
+            let solver = Solver::from_ruleset(&sudoku, &Ruleset::default()).with_threads(threads);
+            let mut solutions = solver.solutions(sudoku.clone());
+            let found: Vec<Sudoku> = solutions.by_ref().collect();
+            (found, solutions.iterations())
+        };
+
+        let (found, iterations) = enumerate(1);
+        assert_eq!(found, expected);
+        assert_eq!(enumerate(4), (found, iterations));
+    }
+
+    /// Accepts any candidates, but no 9 in the top-left cell of a finished grid.
+    struct NoCornerNine;
+
+    impl Rule for NoCornerNine {
+        fn name(&self) -> &'static str {
+            "no corner nine"
+        }
+
+        fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+            vec![vec![(0, 0)]]
+        }
+
+        fn update_unit(&self, _: usize, _: &[Pos], _: &mut Sudoku) -> Result<(), Contradiction> {
+            Ok(())
+        }
+
+        fn check(&self, sudoku: &Sudoku) -> Result<(), Contradiction> {
+            match sudoku[(0, 0)] {
+                9 => Err(Contradiction::new(self.name(), vec![(0, 0)], "digit 9 in the corner")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_solutions_branch_on_empty_cells() {
+        let grid = Solver::new().solutions(Sudoku::default()).next().unwrap();
+        assert!(grid.positions().all(|pos| matches!(grid[pos].into(), Entry::Digit(_))));
+    }
+
+    #[test]
+    fn test_check_rejects_solution() {
+        let mut sudoku = solution();
+        sudoku[(0, 0)] = 0;
+
+        let solver = Solver::from_ruleset(&sudoku, &Ruleset::default());
+        assert_eq!(solver.rules()[0].cells(&sudoku).len(), 81);
+        assert!(solver.check(&solution()).is_ok());
+
+        let solver = solver.with_rule(NoCornerNine);
+        let SolveResult(_, _, state) = solver.solve(sudoku);
+
+        assert!(matches!(state, State::Unsolvable(contradiction) if contradiction.rule == "no corner nine"));
+        assert!(solver.check(&solution()).is_err());
+    }
+}
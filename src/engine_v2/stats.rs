@@ -0,0 +1,141 @@
+use super::{Checkpoint, Entry, Sudoku};
+use std::fmt::Display;
+use std::time::Duration;
+
+/// The work done by every rule of one name during a solve.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RuleStats {
+    pub name: &'static str,
+    /// The number of unit updates.
+    pub calls: usize,
+    /// The digits the rule placed, including cells left with a single candidate.
+    pub placed: usize,
+    /// The candidates the rule removed.
+    pub removed: usize,
+    pub contradictions: usize,
+    pub time: Duration,
+}
+
+/// What a solve spent its time on, per rule and in the candidate tests that run once the rules stall.
+/// Work undone when a trial is rolled back still counts.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Stats {
+    /// One entry per rule name, in the order the solver first lists them.
+    pub rules: Vec<RuleStats>,
+    /// The candidates placed to test whether they lead to a contradiction.
+    pub trials: usize,
+    /// The candidates removed because their trial failed.
+    pub refuted: usize,
+    /// The deepest nesting of trials inside trials.
+    pub max_depth: usize,
+    /// The digits guessed while enumerating solutions.
+    pub branches: usize,
+    depth: usize,
+}
+
+impl Stats {
+    /// Empty stats with an entry for every distinct name, and the entry each name maps to.
+    pub(super) fn new(names: impl IntoIterator<Item = &'static str>) -> (Self, Vec<usize>) {
+        let mut stats = Stats::default();
+
+        let slots = names
+            .into_iter()
+            .map(|name| match stats.rules.iter().position(|rule| rule.name == name) {
+                Some(slot) => slot,
+                None => {
+                    stats.rules.push(RuleStats { name, ..Default::default() });
+                    stats.rules.len() - 1
+                }
+            })
+            .collect();
+
+        (stats, slots)
+    }
+
+    /// The number of rule updates, summed over every rule.
+    pub fn iterations(&self) -> usize {
+        self.rules.iter().map(|rule| rule.calls).sum()
+    }
+
+    /// Empty stats with the same rule entries, for work done on another thread.
+    pub(super) fn fork(&self) -> Stats {
+        let rules = self.rules.iter().map(|rule| RuleStats { name: rule.name, ..Default::default() }).collect();
+        Stats { rules, ..Default::default() }
+    }
+
+    /// Adds the work recorded by forked stats, whose trials started at the current depth.
+    pub(super) fn absorb(&mut self, other: Stats) {
+        for (rule, other) in self.rules.iter_mut().zip(other.rules) {
+            rule.calls += other.calls;
+            rule.placed += other.placed;
+            rule.removed += other.removed;
+            rule.contradictions += other.contradictions;
+            rule.time += other.time;
+        }
+
+        self.trials += other.trials;
+        self.refuted += other.refuted;
+        self.branches += other.branches;
+        self.max_depth = self.max_depth.max(self.depth + other.max_depth);
+    }
+
+    pub(super) fn enter_trial(&mut self) {
+        self.trials += 1;
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    pub(super) fn leave_trial(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Whether a trial is under way, whose checkpoint is still to be restored.
+    pub(super) fn in_trial(&self) -> bool {
+        self.depth > 0
+    }
+}
+
+/// The digits placed and candidates removed since the checkpoint, comparing each cell with its first recorded value.
+pub(super) fn progress(sudoku: &Sudoku, checkpoint: Checkpoint) -> (usize, usize) {
+    let size = sudoku.size();
+    let changes = &sudoku.trail[checkpoint.0..];
+    let (mut placed, mut removed) = (0, 0);
+
+    for (i, &(index, old)) in changes.iter().enumerate() {
+        if changes[..i].iter().any(|&(other, _)| other == index) {
+            continue;
+        }
+
+        let pos = (index / sudoku.width(), index % sudoku.width());
+        let before = Sudoku::mask_of(old, size).count() as usize;
+        removed += before.saturating_sub(sudoku.candidates(pos).count() as usize);
+
+        if matches!(sudoku.entry(pos), Entry::Digit(_)) && !matches!(Entry::from(old), Entry::Digit(_)) {
+            placed += 1;
+        }
+    }
+
+    (placed, removed)
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<16}{:>10}{:>10}{:>10}{:>16}{:>12}",
+            "rule", "calls", "placed", "removed", "contradictions", "time"
+        )?;
+
+        for rule in &self.rules {
+            let RuleStats { name, calls, placed, removed, contradictions, time } = rule;
+            let time = format!("{:.2?}", time);
+            writeln!(f, "{:<16}{:>10}{:>10}{:>10}{:>16}{:>12}", name, calls, placed, removed, contradictions, time)?;
+        }
+
+        write!(
+            f,
+            "trials: {}, refuted: {}, max depth: {}, branches: {}",
+            self.trials, self.refuted, self.max_depth, self.branches
+        )
+    }
+}
@@ -0,0 +1,239 @@
+use std::ops::ControlFlow;
+
+/// Follows a search through the matrix, and can cut it short.
+pub trait Visitor {
+    /// A row joins the partial cover. Returning false backs out of it without searching further.
+    fn enter(&mut self, _row: usize) -> bool {
+        true
+    }
+
+    /// The last row entered leaves the partial cover, whether or not `enter` accepted it.
+    fn leave(&mut self, _row: usize) {}
+
+    /// A complete cover, as its rows in the order they were chosen. Breaking stops the search.
+    fn found(&mut self, rows: &[usize]) -> ControlFlow<()>;
+}
+
+/// A sparse 0/1 matrix searched for exact covers with Knuth's Algorithm X, as Dancing Links.
+/// Primary columns must be covered exactly once, secondary columns at most once.
+///
+/// Node 0 is the root and nodes `1..=columns` are the column headers, followed by one node per 1 of the matrix.
+/// Secondary headers are left out of the root's list, so they are never chosen to branch on.
+pub struct Matrix {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    /// The header of each node.
+    column: Vec<usize>,
+    /// The row of each node, unused for the headers.
+    row: Vec<usize>,
+    /// The number of rows left in each column, by header.
+    size: Vec<usize>,
+    rows: usize,
+    nodes: u64,
+}
+
+impl Matrix {
+    pub fn new(primary: usize, secondary: usize) -> Self {
+        let headers = primary + secondary + 1;
+        let mut matrix = Matrix {
+            left: (0..headers).collect(),
+            right: (0..headers).collect(),
+            up: (0..headers).collect(),
+            down: (0..headers).collect(),
+            column: (0..headers).collect(),
+            row: vec![usize::MAX; headers],
+            size: vec![0; headers],
+            rows: 0,
+            nodes: 0,
+        };
+
+        for header in 0..=primary {
+            matrix.left[header] = if header == 0 { primary } else { header - 1 };
+            matrix.right[header] = if header == primary { 0 } else { header + 1 };
+        }
+
+        matrix
+    }
+
+    /// Adds a row with a 1 in each of the given columns, counted from 0 with the primary ones first,
+    /// and returns its index.
+    pub fn add_row(&mut self, columns: &[usize]) -> usize {
+        let row = self.rows;
+        let first = self.column.len();
+
+        for (i, &column) in columns.iter().enumerate() {
+            let header = column + 1;
+            let node = first + i;
+
+            self.column.push(header);
+            self.row.push(row);
+            self.up.push(self.up[header]);
+            self.down.push(header);
+            self.left.push(if i == 0 { first + columns.len() - 1 } else { node - 1 });
+            self.right.push(if i + 1 == columns.len() { first } else { node + 1 });
+
+            let above = self.up[header];
+            self.down[above] = node;
+            self.up[header] = node;
+            self.size[header] += 1;
+        }
+
+        self.rows += 1;
+        row
+    }
+
+    /// The number of rows tried by every search so far.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Searches every exact cover, branching on the primary column with the fewest rows left.
+    /// The matrix is back in its starting state once the search returns.
+    pub fn search(&mut self, visitor: &mut impl Visitor) -> ControlFlow<()> {
+        self.descend(&mut Vec::new(), visitor)
+    }
+
+    fn descend(&mut self, chosen: &mut Vec<usize>, visitor: &mut impl Visitor) -> ControlFlow<()> {
+        if self.right[0] == 0 {
+            return visitor.found(chosen);
+        }
+
+        let mut best = self.right[0];
+        let mut header = self.right[best];
+        while header != 0 {
+            if self.size[header] < self.size[best] {
+                best = header;
+            }
+            header = self.right[header];
+        }
+
+        self.cover(best);
+
+        let mut node = self.down[best];
+        let mut flow = ControlFlow::Continue(());
+
+        while node != best && flow.is_continue() {
+            self.nodes += 1;
+            chosen.push(self.row[node]);
+            self.across(node, Matrix::cover);
+
+            if visitor.enter(self.row[node]) {
+                flow = self.descend(chosen, visitor);
+            }
+
+            visitor.leave(self.row[node]);
+            self.across_back(node, Matrix::uncover);
+            chosen.pop();
+            node = self.down[node];
+        }
+
+        self.uncover(best);
+        flow
+    }
+
+    /// Applies `f` to the columns of the other nodes of the row, from left to right.
+    fn across(&mut self, node: usize, f: fn(&mut Matrix, usize)) {
+        let mut other = self.right[node];
+        while other != node {
+            f(self, self.column[other]);
+            other = self.right[other];
+        }
+    }
+
+    /// Applies `f` to the columns of the other nodes of the row, from right to left.
+    fn across_back(&mut self, node: usize, f: fn(&mut Matrix, usize)) {
+        let mut other = self.left[node];
+        while other != node {
+            f(self, self.column[other]);
+            other = self.left[other];
+        }
+    }
+
+    /// Takes the column out of the header list, along with every row crossing it.
+    fn cover(&mut self, header: usize) {
+        let (left, right) = (self.left[header], self.right[header]);
+        self.right[left] = right;
+        self.left[right] = left;
+
+        let mut node = self.down[header];
+        while node != header {
+            let mut other = self.right[node];
+            while other != node {
+                let (up, down) = (self.up[other], self.down[other]);
+                self.down[up] = down;
+                self.up[down] = up;
+                self.size[self.column[other]] -= 1;
+                other = self.right[other];
+            }
+            node = self.down[node];
+        }
+    }
+
+    /// Undoes `cover`, relinking everything in the reverse order.
+    fn uncover(&mut self, header: usize) {
+        let mut node = self.up[header];
+        while node != header {
+            let mut other = self.left[node];
+            while other != node {
+                self.size[self.column[other]] += 1;
+                self.down[self.up[other]] = other;
+                self.up[self.down[other]] = other;
+                other = self.left[other];
+            }
+            node = self.up[node];
+        }
+
+        let (left, right) = (self.left[header], self.right[header]);
+        self.right[left] = header;
+        self.left[right] = header;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects every cover, sorted.
+    struct Covers(Vec<Vec<usize>>);
+
+    impl Visitor for Covers {
+        fn found(&mut self, rows: &[usize]) -> ControlFlow<()> {
+            let mut rows = rows.to_vec();
+            rows.sort_unstable();
+            self.0.push(rows);
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn test_exact_cover() {
+        // Knuth's example, whose only cover is rows 0, 3 and 4.
+        let mut matrix = Matrix::new(7, 0);
+        for row in [&[2, 4, 5][..], &[0, 3, 6], &[1, 2, 5], &[0, 3], &[1, 6], &[3, 4, 6]] {
+            matrix.add_row(row);
+        }
+
+        let mut covers = Covers(vec![]);
+        assert!(matrix.search(&mut covers).is_continue());
+        assert_eq!(covers.0, [[0, 3, 4]]);
+
+        let mut again = Covers(vec![]);
+        assert!(matrix.search(&mut again).is_continue());
+        assert_eq!(again.0, covers.0);
+    }
+
+    #[test]
+    fn test_secondary_columns() {
+        // Rows 0 and 1 cover both primary columns but share the secondary column 2.
+        let mut matrix = Matrix::new(2, 1);
+        matrix.add_row(&[0, 2]);
+        matrix.add_row(&[1, 2]);
+        matrix.add_row(&[1]);
+
+        let mut covers = Covers(vec![]);
+        assert!(matrix.search(&mut covers).is_continue());
+        assert_eq!(covers.0, [[0, 2]]);
+    }
+}
@@ -0,0 +1,252 @@
+mod dlx;
+
+pub use dlx::{Matrix, Visitor};
+
+use crate::engine_v2::{Checkpoint, Pos, Registry, Rule, Ruleset, Sudoku, Value, distinct_sets};
+use std::ops::ControlFlow;
+
+/// The rules too irregular for the matrix, checked on the partial grid whenever one of their cells is filled.
+const CHECKED_RULES: [&str; 4] = ["clues", "cage", "palindrome", "sequence"];
+
+/// A grid encoded as an exact cover problem, with a row for every candidate digit of every cell
+/// and a primary column for every cell. The sets of distinct digits of the grid add a column per digit,
+/// and the rules that don't fit the matrix prune the search instead.
+pub struct ExactCover {
+    matrix: Matrix,
+    /// The cell and digit of each row.
+    choices: Vec<(Pos, u8)>,
+    sudoku: Sudoku,
+    checks: Vec<Box<dyn Rule>>,
+    /// The checks covering each cell, by cell index.
+    watchers: Vec<Vec<usize>>,
+}
+
+impl ExactCover {
+    /// Encodes the grid with its standard constraints and those of the ruleset.
+    pub fn new(sudoku: &Sudoku, ruleset: &Ruleset) -> Result<Self, String> {
+        let sets = distinct_sets(sudoku, ruleset)?;
+        let size = sudoku.size();
+        let cells: Vec<Pos> = sudoku.positions().collect();
+        let (primary, secondary): (Vec<_>, Vec<_>) = sets.into_iter().partition(|set| set.len() == size);
+
+        let mut matrix = Matrix::new(cells.len() + primary.len() * size, secondary.len() * size);
+
+        // The first column of every set holding each cell, by cell index.
+        let mut columns = vec![vec![]; sudoku.height() * sudoku.width()];
+        for (i, set) in primary.iter().chain(&secondary).enumerate() {
+            for &pos in set {
+                columns[sudoku.cell_index(pos)].push(cells.len() + i * size);
+            }
+        }
+
+        let mut shaded = sudoku.clone();
+        for &(pos, shade) in &ruleset.shades {
+            shaded.restrict(pos, shade.mask(size));
+        }
+
+        let mut choices = vec![];
+        for (i, &pos) in cells.iter().enumerate() {
+            for digit in shaded.candidates(pos).digits() {
+                let index = sudoku.cell_index(pos);
+                let row: Vec<usize> =
+                    std::iter::once(i).chain(columns[index].iter().map(|first| first + digit as usize - 1)).collect();
+
+                matrix.add_row(&row);
+                choices.push((pos, digit));
+            }
+        }
+
+        let mut cover = ExactCover {
+            matrix,
+            choices,
+            sudoku: sudoku.clone(),
+            checks: vec![],
+            watchers: vec![vec![]; sudoku.height() * sudoku.width()],
+        };
+
+        let registry = Registry::standard();
+        for name in CHECKED_RULES {
+            if name == "clues" || ruleset.sum_sequence {
+                registry.rules(name, sudoku, ruleset)?.into_iter().for_each(|rule| cover.add_check(rule));
+            }
+        }
+
+        Ok(cover)
+    }
+
+    /// Adds a rule that prunes the search, checked whenever one of its cells is filled.
+    pub fn with_check(mut self, rule: impl Rule + 'static) -> Self {
+        self.add_check(Box::new(rule));
+        self
+    }
+
+    fn add_check(&mut self, rule: Box<dyn Rule>) {
+        for pos in rule.cells(&self.sudoku) {
+            self.watchers[self.sudoku.cell_index(pos)].push(self.checks.len());
+        }
+
+        self.checks.push(rule);
+    }
+
+    /// Finds up to `limit` solutions, in the order the search reaches them.
+    pub fn solutions(&mut self, limit: usize) -> Vec<Sudoku> {
+        let mut filler = Filler {
+            sudoku: self.sudoku.clone(),
+            choices: &self.choices,
+            checks: &self.checks,
+            watchers: &self.watchers,
+            checkpoints: vec![],
+            found: vec![],
+            limit,
+        };
+
+        if limit > 0 {
+            let _ = self.matrix.search(&mut filler);
+        }
+
+        filler.found
+    }
+
+    /// The number of rows tried by every search so far.
+    pub fn nodes(&self) -> u64 {
+        self.matrix.nodes()
+    }
+}
+
+/// Fills the grid along the search, pruning the rows the checks reject.
+struct Filler<'a> {
+    sudoku: Sudoku,
+    choices: &'a [(Pos, u8)],
+    checks: &'a [Box<dyn Rule>],
+    watchers: &'a [Vec<usize>],
+    checkpoints: Vec<Checkpoint>,
+    found: Vec<Sudoku>,
+    limit: usize,
+}
+
+impl Visitor for Filler<'_> {
+    fn enter(&mut self, row: usize) -> bool {
+        let (pos, digit) = self.choices[row];
+
+        self.checkpoints.push(self.sudoku.checkpoint());
+        self.sudoku.set(pos, digit as Value);
+
+        let watching = &self.watchers[self.sudoku.cell_index(pos)];
+        watching.iter().all(|&check| self.checks[check].check(&self.sudoku).is_ok())
+    }
+
+    fn leave(&mut self, _: usize) {
+        let checkpoint = self.checkpoints.pop().unwrap();
+        self.sudoku.restore(checkpoint);
+    }
+
+    fn found(&mut self, _: &[usize]) -> ControlFlow<()> {
+        self.found.push(self.sudoku.clone());
+
+        match self.found.len() < self.limit {
+            true => ControlFlow::Continue(()),
+            false => ControlFlow::Break(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::{Contradiction, Shade, Solver};
+    use crate::puzzle::parse_puzzle;
+
+    fn puzzle() -> Sudoku {
+        Sudoku::from([
+            [0, 6, 0, 8, 0, 0, 0, 0, 0],
+            [4, 0, 0, 0, 0, 5, 0, 8, 0],
+            [0, 3, 7, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 4, 0, 9, 7, 3, 0, 0],
+            [0, 0, 0, 0, 5, 3, 0, 0, 0],
+            [7, 0, 0, 0, 0, 1, 4, 6, 0],
+            [5, 9, 0, 0, 0, 4, 7, 3, 0],
+            [0, 0, 1, 0, 7, 0, 0, 0, 5],
+        ])
+    }
+
+    /// Both engines find the same solutions, in any order.
+    fn cross_check(sudoku: &Sudoku, ruleset: &Ruleset) -> Vec<Sudoku> {
+        let mut expected: Vec<Sudoku> = Solver::from_ruleset(sudoku, ruleset).solutions(sudoku.clone()).collect();
+        let mut found = ExactCover::new(sudoku, ruleset).unwrap().solutions(usize::MAX);
+
+        let key = |sudoku: &Sudoku| sudoku.positions().map(|pos| sudoku[pos]).collect::<Vec<_>>();
+        expected.sort_by_key(key);
+        found.sort_by_key(key);
+
+        assert_eq!(found, expected);
+        found
+    }
+
+    #[test]
+    fn test_unique_solution() {
+        let solutions = cross_check(&puzzle(), &Ruleset::default());
+        assert_eq!(solutions.len(), 1);
+    }
+
+    #[test]
+    fn test_several_solutions() {
+        let puzzle = parse_puzzle(
+            "96.84.173\n41.73.986\n837916542\n379128654\n254697318\n186453297\n723581469\n598264731\n641379825\n",
+        )
+        .unwrap();
+
+        assert_eq!(cross_check(&puzzle.sudoku, &puzzle.ruleset).len(), 2);
+
+        let ruleset = Ruleset { shades: vec![((0, 2), Shade::Odd)], ..puzzle.ruleset };
+        let solutions = cross_check(&puzzle.sudoku, &ruleset);
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0][(0, 2)], 5);
+    }
+
+    #[test]
+    fn test_neighbor_sets() {
+        let mut sudoku = puzzle();
+        (3..9).for_each(|row| (0..9).for_each(|col| sudoku[(row, col)] = 0));
+
+        let ruleset = Ruleset { anti_king: true, diagonal: true, ..Default::default() };
+        let solutions = ExactCover::new(&sudoku, &ruleset).unwrap().solutions(2);
+        let solver = Solver::from_ruleset(&sudoku, &ruleset);
+
+        assert!(!solutions.is_empty());
+        assert!(solutions.iter().all(|solution| solver.check(solution).is_ok()));
+    }
+
+    #[test]
+    fn test_checked_rules() {
+        let mut cover = ExactCover::new(&puzzle(), &Ruleset::default()).unwrap();
+        let nodes = cover.nodes();
+        assert_eq!(cover.solutions(0), []);
+        assert_eq!(cover.nodes(), nodes);
+
+        let solution = cover.solutions(2).remove(0);
+        let value = solution[(0, 0)];
+        let mut cover = cover.with_check(NotAt(value));
+        assert_eq!(cover.solutions(2), []);
+    }
+
+    /// Rejects one digit in the top-left cell.
+    struct NotAt(Value);
+
+    impl Rule for NotAt {
+        fn name(&self) -> &'static str {
+            "not at"
+        }
+
+        fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+            vec![vec![(0, 0)]]
+        }
+
+        fn update_unit(&self, _: usize, _: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+            match sudoku[(0, 0)] == self.0 {
+                true => Err(Contradiction::new(self.name(), vec![(0, 0)], "digit not allowed")),
+                false => Ok(()),
+            }
+        }
+    }
+}
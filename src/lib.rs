@@ -0,0 +1,63 @@
+//! Sudoku solvers for the sum-sequence puzzle and other variants.
+//!
+//! Puzzles are read with [`puzzle::parse_puzzle`], or built cell by cell on an [`engine_v2::Sudoku`].
+//! An [`engine_v2::Solver`] applies the standard rules and those of a [`engine_v2::Ruleset`],
+//! along with any user-defined [`engine_v2::Rule`]:
+//!
+//! ```
+//! use sum_sequence_sudoku_solver::engine_v2::{Contradiction, Mask, Pos, Rule, Solver, State, Sudoku};
+//! use sum_sequence_sudoku_solver::puzzle::parse_puzzle;
+//!
+//! /// r1c3 holds an odd digit.
+//! struct OddR1C3;
+//!
+//! impl Rule for OddR1C3 {
+//!     fn name(&self) -> &'static str {
+//!         "odd r1c3"
+//!     }
+//!
+//!     fn units(&self, _: &Sudoku) -> Vec<Vec<Pos>> {
+//!         vec![vec![(0, 2)]]
+//!     }
+//!
+//!     fn update_unit(&self, _: usize, cells: &[Pos], sudoku: &mut Sudoku) -> Result<(), Contradiction> {
+//!         match sudoku.restrict(cells[0], Mask::from_digits([1, 3, 5, 7, 9])) {
+//!             true => Ok(()),
+//!             false => Err(Contradiction::new(self.name(), cells.to_vec(), "no odd digit fits")),
+//!         }
+//!     }
+//! }
+//!
+//! let puzzle = parse_puzzle(
+//!     "96.84.173\n41.73.986\n837916542\n379128654\n254697318\n186453297\n723581469\n598264731\n641379825\n",
+//! )?;
+//!
+//! let solver = Solver::from_ruleset(&puzzle.sudoku, &puzzle.ruleset);
+//! let result = solver.solve(puzzle.sudoku.clone());
+//!
+//! assert!(matches!(result.2, State::Stuck));
+//! assert_eq!(result.1.candidates((0, 2)), Mask::from_digits([2, 5]));
+//! assert_eq!(solver.solutions(puzzle.sudoku.clone()).count(), 2);
+//!
+//! let solver = solver.with_rule(OddR1C3);
+//! let solutions: Vec<Sudoku> = solver.solutions(puzzle.sudoku).collect();
+//!
+//! assert_eq!(solutions.len(), 1);
+//! assert_eq!(solutions[0][(0, 2)], 5);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// Limited cage pair combinations solver.
+pub mod engine_v1;
+
+/// Experimental full sudoku solver.
+pub mod engine_v2;
+
+/// Exact-cover solver, a complete baseline for engine_v2.
+pub mod engine_v3;
+
+/// CNF encoding of puzzles in DIMACS format, with a CDCL solver behind the `cdcl` feature.
+pub mod sat;
+
+/// Puzzle file parsing.
+pub mod puzzle;
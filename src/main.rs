@@ -1,20 +1,22 @@
-/// Limited cage pair combinations solver.
-mod engine_v1;
-
-/// Experimental full sudoku solver.
-mod engine_v2;
-
 use clap::Parser;
-use engine_v2::{SolveResult, State, Sudoku, solve_sudoku};
 use std::error::Error;
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
+use sum_sequence_sudoku_solver::engine_v1::feasibility::{Feasible, explore};
+use sum_sequence_sudoku_solver::engine_v1::{self, Params, constraints::Layout};
+use sum_sequence_sudoku_solver::engine_v2::{Registry, RuleSpec, Ruleset, SolveResult, Solver, State};
+use sum_sequence_sudoku_solver::engine_v3::ExactCover;
+use sum_sequence_sudoku_solver::puzzle::{Puzzle, parse_puzzle_file};
+#[cfg(feature = "cdcl")]
+use sum_sequence_sudoku_solver::sat::Cdcl;
+use sum_sequence_sudoku_solver::sat::Encoding;
 
 #[derive(Parser, Debug)]
 #[command(name = "sum-sequence-sudoku-solver")]
 #[command(about = "A Sudoku solver for a custom puzzle rule set", long_about = None)]
 struct Args {
-    /// Engine version to use (1 or 2)
+    /// Engine version to use (1, 2, 3 for the exact-cover baseline, or 4 for the SAT solver of the cdcl feature)
     #[arg(short, long, default_value_t = 2)]
     engine: u8,
 
@@ -22,87 +24,309 @@ struct Args {
     #[arg(short, long, default_value_t = false)]
     sum_sequence: bool,
 
+    /// Forbid equal digits a knight's move apart
+    #[arg(long, default_value_t = false)]
+    anti_knight: bool,
+
+    /// Forbid equal digits a king's move apart
+    #[arg(long, default_value_t = false)]
+    anti_king: bool,
+
+    /// Require distinct digits on both main diagonals
+    #[arg(long, default_value_t = false)]
+    diagonal: bool,
+
     /// Path to sudoku input file [default: ./sudoku.txt]
     #[arg(short, long)]
     file: Option<PathBuf>,
 
+    /// Rules to solve with instead of the ones the puzzle turns on, as `name` or `name:priority`
+    /// (for example `row,column,box,hidden-singles:1`). Lower priorities run first
+    #[arg(long, value_delimiter = ',')]
+    rules: Vec<RuleSpec>,
+
+    /// Print the work done by each rule and by the candidate tests
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
     /// Iteration limit for engine v2
     #[arg(short, long, default_value_t = 2_000_000)]
     limit: usize,
+
+    /// Give up after this many seconds, printing the grid as far as it got
+    #[arg(long)]
+    time_limit: Option<f64>,
+
+    /// Give up after this many candidate tests
+    #[arg(long)]
+    node_limit: Option<usize>,
+
+    /// Test candidates on this many threads. The result is the same for any count,
+    /// unless a time or node limit interrupts the run
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// Write the puzzle as a CNF formula in DIMACS format to this file instead of solving it
+    #[arg(long)]
+    cnf: Option<PathBuf>,
+
+    /// Place the solutions of engine v1 on the pair cages of the puzzle and solve each with engine v2,
+    /// with the sum-sequence rules turned on
+    #[arg(long, default_value_t = false)]
+    pipeline: bool,
+
+    /// Stop the pipeline after this many solutions
+    #[arg(long)]
+    max_solutions: Option<usize>,
+
+    /// List the sequence starts and palindrome digit sets of a rule set, checked with engine v1,
+    /// instead of solving a puzzle
+    #[arg(long, default_value_t = false)]
+    explore: bool,
+
+    /// Number of pair cages of the explored rule set
+    #[arg(long, default_value_t = 12)]
+    pairs: usize,
+
+    /// Number of triplet cages of the explored rule set
+    #[arg(long, default_value_t = 3)]
+    triplets: usize,
+
+    /// How many times every digit appears in the cage cells outside palindromes, in the explored rule set
+    #[arg(long, default_value_t = 3)]
+    occurrences: usize,
+
+    /// Number of cage cells inside palindromes of the explored rule set, each palindrome digit filling two
+    #[arg(long, default_value_t = 6)]
+    palindrome_cells: usize,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
+    if let Some(path) = &args.cnf {
+        let puzzle = load_puzzle(&args)?;
+        let encoding = Encoding::new(&puzzle.sudoku, &puzzle.ruleset)?;
+        let cnf = encoding.cnf();
+
+        fs::write(path, cnf.to_string())?;
+        println!("Wrote {} variables and {} clauses to {}", cnf.vars(), cnf.clauses().len(), path.display());
+        return Ok(());
+    }
+
+    if args.pipeline {
+        return run_pipeline(&args);
+    }
+
+    if args.explore {
+        return run_explorer(&args);
+    }
+
     match args.engine {
-        1 => engine_v1::generate()?,
-        2 => run_engine_v2(args.file, args.sum_sequence, args.limit)?,
-        _ => return Err(format!("Invalid engine: {}. Use '1' or '2'", args.engine).into()),
+        1 => engine_v1::generate(&Params::default(), &Layout::default())?,
+        2 => run_engine_v2(&args, load_puzzle(&args)?)?,
+        3 => run_engine_v3(load_puzzle(&args)?)?,
+        #[cfg(feature = "cdcl")]
+        4 => run_sat(load_puzzle(&args)?)?,
+        #[cfg(not(feature = "cdcl"))]
+        4 => return Err("Engine 4 needs the cdcl feature: cargo run --features cdcl -- --engine 4".into()),
+        _ => return Err(format!("Invalid engine: {}. Use '1', '2', '3' or '4'", args.engine).into()),
     }
 
     Ok(())
 }
 
-fn run_engine_v2(file_path: Option<PathBuf>, sum_sequence: bool, limit: usize) -> Result<(), Box<dyn Error>> {
-    let path = file_path.unwrap_or_else(|| PathBuf::from("sudoku.txt"));
+/// Reads the puzzle file, with the constraints turned on by the arguments added to its own.
+fn load_puzzle(args: &Args) -> Result<Puzzle, Box<dyn Error>> {
+    let ruleset = Ruleset {
+        sum_sequence: args.sum_sequence || args.pipeline,
+        anti_knight: args.anti_knight,
+        anti_king: args.anti_king,
+        diagonal: args.diagonal,
+        ..Default::default()
+    };
+
+    let path = args.file.clone().unwrap_or_else(|| PathBuf::from("sudoku.txt"));
 
-    let sudoku = if path.exists() {
-        parse_sudoku_file(&path)?
+    let puzzle = if path.exists() {
+        parse_puzzle_file(&path)?
     } else {
         return Err(format!("File not found: {}", path.display()).into());
     };
 
+    let ruleset = ruleset.merge(&puzzle.ruleset);
+    ruleset.validate(&puzzle.sudoku)?;
+
+    Ok(Puzzle { ruleset, ..puzzle })
+}
+
+/// Builds the engine v2 solver of the puzzle, with the rules and limits of the arguments.
+fn build_solver(args: &Args, puzzle: &Puzzle) -> Result<Solver, Box<dyn Error>> {
+    let mut solver = if args.rules.is_empty() {
+        Solver::from_ruleset(&puzzle.sudoku, &puzzle.ruleset)
+    } else {
+        Registry::standard().build(&args.rules, &puzzle.sudoku, &puzzle.ruleset)?
+    };
+
+    solver = solver.with_limit(args.limit);
+
+    if let Some(seconds) = args.time_limit {
+        solver = solver.with_time_limit(Duration::try_from_secs_f64(seconds)?);
+    }
+
+    if let Some(nodes) = args.node_limit {
+        solver = solver.with_node_limit(nodes);
+    }
+
+    if let Some(threads) = args.threads {
+        solver = solver.with_threads(threads);
+    }
+
+    Ok(solver)
+}
+
+fn run_engine_v2(args: &Args, puzzle: Puzzle) -> Result<(), Box<dyn Error>> {
+    let solver = build_solver(args, &puzzle)?;
+
     println!("Input Sudoku:");
-    println!("{}", sudoku);
+    println!("{}", puzzle);
 
     let start = std::time::Instant::now();
-    let result = solve_sudoku(sudoku, sum_sequence, limit);
+    let (result, stats) = solver.solve_with_stats(puzzle.sudoku.clone());
     let duration = start.elapsed();
 
     println!("Time elapsed: {:?}", duration);
 
+    if args.stats {
+        println!("{}", stats);
+    }
+
     let SolveResult(counter, sudoku, state) = result;
 
     match state {
         State::Solved => println!("Solved in {} iterations:", counter),
         State::Stuck => eprintln!("No progress made after {} iterations", counter),
         State::LimitReached => eprintln!("Reached iteration limit: {}", counter),
-        State::Unsolvable => return Err("Sudoku is unsolvable:".into()),
+        State::TimeLimitReached => eprintln!("Reached time limit after {} iterations", counter),
+        State::NodeLimitReached => eprintln!("Reached node limit after {} iterations", counter),
+        State::Cancelled => eprintln!("Cancelled after {} iterations", counter),
+        State::Unsolvable(contradiction) => return Err(format!("Sudoku is unsolvable: {}", contradiction).into()),
     }
 
-    println!("{}", sudoku);
+    for (var, domain) in sudoku.vars() {
+        println!("{}: {}", var, domain);
+    }
+
+    println!("{}", Puzzle { sudoku, ..puzzle });
 
     Ok(())
 }
 
-fn parse_sudoku_file(path: &PathBuf) -> Result<Sudoku, Box<dyn Error>> {
-    let content = fs::read_to_string(path)?;
-    let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+/// Solves the puzzle once for every solution of engine v1 placed on it, printing the grids completed.
+fn run_pipeline(args: &Args) -> Result<(), Box<dyn Error>> {
+    let puzzle = load_puzzle(args)?;
+    let solver = build_solver(args, &puzzle)?;
+
+    println!("Input Sudoku:");
+    println!("{}", puzzle);
+
+    let start = std::time::Instant::now();
+    let (params, layout) = (Params::default(), Layout::default());
+    let solutions = engine_v1::solutions(&params, &layout);
+    let max = args.max_solutions.unwrap_or(usize::MAX);
+    let pipeline = engine_v1::pipeline(&params, &layout, &puzzle.sudoku, &solver, &solutions, max)?;
+    let duration = start.elapsed();
+
+    println!("Time elapsed: {:?}", duration);
+    println!(
+        "{} solved, {} unsolvable, {} open, written to out/5_pipeline.txt",
+        pipeline.solved.len(),
+        pipeline.unsolvable,
+        pipeline.open
+    );
 
-    if lines.len() != 9 {
-        return Err(format!("Invalid sudoku format: expected 9 rows, got {}", lines.len()).into());
+    for (triplet, solution, sudoku) in pipeline.solved {
+        println!("Triplet {:?}, pairs {:?}:", triplet, solution);
+        println!("{}", Puzzle { sudoku, ruleset: puzzle.ruleset.clone() });
     }
 
-    let mut grid = [[0u16; 9]; 9];
-    for (i, line) in lines.iter().enumerate() {
-        let numbers: Vec<&str> =
-            line.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()).collect();
+    Ok(())
+}
 
-        if numbers.len() != 9 {
-            return Err(
-                format!("Invalid sudoku format: row {} has {} numbers, expected 9", i + 1, numbers.len()).into()
-            );
-        }
+/// Lists the feasible sequence starts and palindrome digit sets, with the cage digit combinations found for each.
+fn run_explorer(args: &Args) -> Result<(), Box<dyn Error>> {
+    let feasible = explore(args.pairs, args.triplets, args.occurrences, args.palindrome_cells)?;
 
-        for (j, num_str) in numbers.iter().enumerate() {
-            match num_str.trim().parse::<u16>() {
-                Ok(num) if num <= 9 => grid[i][j] = num,
-                _ => {
-                    return Err(format!("Invalid number '{}' at row {}, col {}", num_str, i + 1, j + 1).into());
-                }
+    if feasible.is_empty() {
+        return Err("No sequence start fits the rule set".into());
+    }
+
+    let cages = (args.pairs + args.triplets) as u16;
+
+    for Feasible { start, palindrome_total, digit_sets } in feasible {
+        println!("S = {}, X = {}, cage sums {} to {}:", start, palindrome_total, start, start + cages - 1);
+
+        for (set, combinations) in digit_sets {
+            match combinations {
+                0 => println!("  {:?}: no cage digits found", set),
+                _ => println!("  {:?}: {} cage digit combination(s)", set, combinations),
             }
         }
     }
 
-    Ok(Sudoku(grid))
+    Ok(())
+}
+
+/// Enumerates up to two solutions with the exact-cover engine, telling whether the puzzle is unique.
+fn run_engine_v3(puzzle: Puzzle) -> Result<(), Box<dyn Error>> {
+    println!("Input Sudoku:");
+    println!("{}", puzzle);
+
+    let start = std::time::Instant::now();
+    let mut cover = ExactCover::new(&puzzle.sudoku, &puzzle.ruleset)?;
+    let mut solutions = cover.solutions(2);
+    let duration = start.elapsed();
+
+    println!("Time elapsed: {:?}", duration);
+
+    match solutions.len() {
+        0 => return Err(format!("Sudoku is unsolvable, after {} nodes", cover.nodes()).into()),
+        1 => println!("Solved in {} nodes, the solution is unique:", cover.nodes()),
+        _ => println!("Solved in {} nodes, with more than one solution:", cover.nodes()),
+    }
+
+    println!("{}", Puzzle { sudoku: solutions.remove(0), ..puzzle });
+
+    Ok(())
+}
+
+/// Solves the CNF encoding of the puzzle, then rules the solution out to tell whether it is unique.
+#[cfg(feature = "cdcl")]
+fn run_sat(puzzle: Puzzle) -> Result<(), Box<dyn Error>> {
+    println!("Input Sudoku:");
+    println!("{}", puzzle);
+
+    let start = std::time::Instant::now();
+    let encoding = Encoding::new(&puzzle.sudoku, &puzzle.ruleset)?;
+    let mut solver = Cdcl::new(encoding.cnf());
+
+    let Some(model) = solver.solve() else {
+        return Err(format!("Sudoku is unsolvable, after {} conflicts", solver.conflicts()).into());
+    };
+
+    let sudoku = encoding.decode(&model);
+    solver.add_clause(&encoding.block(&sudoku));
+    let unique = solver.solve().is_none();
+    let duration = start.elapsed();
+
+    println!("Time elapsed: {:?}", duration);
+
+    match unique {
+        true => println!("Solved in {} conflicts, the solution is unique:", solver.conflicts()),
+        false => println!("Solved in {} conflicts, with more than one solution:", solver.conflicts()),
+    }
+
+    println!("{}", Puzzle { sudoku, ..puzzle });
+
+    Ok(())
 }
@@ -0,0 +1,442 @@
+use crate::engine_v2::{Clue, ClueKind, Direction, Edge, Pos, Regions, Ruleset, Shade, Shape, Sudoku};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+/// A parsed puzzle file: the starting grid plus any constraints declared alongside it.
+/// The grid can be 4x4, 6x6, 9x9, 16x16 or any other size up to 16, with one row per line.
+///
+/// ```txt
+/// 0 6 0 8 0 0 0 0 0
+/// ...
+///
+/// [rules]
+/// anti-king
+/// diagonal
+///
+/// [regions]
+/// 1 1 1 2 2 2 3 3 3
+/// ...
+///
+/// [clues]
+/// sandwich T3 15
+/// little-killer L1 NE 20
+///
+/// [cells]
+/// o . . e . . h . l
+/// ...
+///
+/// [cages]
+/// r3c3 r4c3 r4c2
+/// ```
+///
+/// Puzzles made of several overlapping grids declare them in a `[layout]` section,
+/// and write the whole canvas as the grid.
+#[derive(Debug, Default)]
+pub struct Puzzle {
+    pub sudoku: Sudoku,
+    pub ruleset: Ruleset,
+}
+
+pub fn parse_puzzle_file(path: &Path) -> Result<Puzzle, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    parse_puzzle(&content)
+}
+
+/// Reads a puzzle. Its ruleset isn't checked against the grid, as flags may still turn on more constraints:
+/// call `Ruleset::validate` once it is complete.
+pub fn parse_puzzle(content: &str) -> Result<Puzzle, Box<dyn Error>> {
+    let mut grid_lines = Vec::new();
+    let mut rule_lines = Vec::new();
+    let mut region_lines = Vec::new();
+    let mut clue_lines = Vec::new();
+    let mut cell_lines = Vec::new();
+    let mut layout_lines = Vec::new();
+    let mut cage_lines = Vec::new();
+    let mut section: Option<&str> = None;
+
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = Some(name.trim());
+            continue;
+        }
+
+        match section {
+            None => grid_lines.push(line),
+            Some("rules") => rule_lines.push(line),
+            Some("regions") => region_lines.push(line),
+            Some("clues") => clue_lines.push(line),
+            Some("cells") => cell_lines.push(line),
+            Some("layout") => layout_lines.push(line),
+            Some("cages") => cage_lines.push(line),
+            Some(name) => return Err(format!("Unknown section: [{}]", name).into()),
+        }
+    }
+
+    let sudoku = if layout_lines.is_empty() {
+        parse_grid(&grid_lines)?
+    } else {
+        parse_canvas(&grid_lines, &parse_layout(&layout_lines)?)?
+    };
+
+    let mut ruleset = parse_rules(&rule_lines)?;
+
+    if !region_lines.is_empty() {
+        ruleset.regions = Some(parse_regions(&region_lines, sudoku.size())?);
+    }
+
+    if !cell_lines.is_empty() {
+        ruleset.shades = parse_shades(&cell_lines, &sudoku)?;
+    }
+
+    ruleset.clues = clue_lines.iter().map(|line| parse_clue(line)).collect::<Result<_, _>>()?;
+    ruleset.cages = cage_lines.iter().map(|line| parse_cage(line)).collect::<Result<_, _>>()?;
+
+    Ok(Puzzle { sudoku, ruleset })
+}
+
+impl Display for Puzzle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.sudoku.write_grid(f, |pos| {
+            self.ruleset.shades.iter().find(|(shaded, _)| *shaded == pos).map(|(_, shade)| shade.symbol())
+        })
+    }
+}
+
+fn split_row(line: &str) -> Vec<&str> {
+    line.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()).collect()
+}
+
+/// Splits a row into `size` labels, either separated by spaces or commas or written as single characters.
+fn split_labels<'a>(line: &'a str, i: usize, section: &str, size: usize) -> Result<Vec<&'a str>, Box<dyn Error>> {
+    let mut tokens = split_row(line);
+
+    if tokens.len() == 1 && size > 1 {
+        tokens = line.char_indices().map(|(j, c)| &line[j..j + c.len_utf8()]).collect();
+    }
+
+    if tokens.len() != size {
+        return Err(format!(
+            "Invalid {} format: row {} has {} labels, expected {}",
+            section,
+            i + 1,
+            tokens.len(),
+            size
+        )
+        .into());
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a square grid of any supported size, taking its size from the number of rows.
+fn parse_grid(lines: &[&str]) -> Result<Sudoku, Box<dyn Error>> {
+    let shape = Shape::new(lines.len()).map_err(|e| format!("Invalid sudoku format: {}", e))?;
+    fill_cells(Sudoku::new(shape), lines)
+}
+
+/// Reads the whole canvas of a multi-grid puzzle, where the cells outside every grid are written as `-`.
+/// The size of the grids is what remains below and right of the last origin.
+fn parse_canvas(lines: &[&str], origins: &[Pos]) -> Result<Sudoku, Box<dyn Error>> {
+    let last_row = origins.iter().map(|(row, _)| row).max().unwrap();
+    let size = lines.len().checked_sub(*last_row).filter(|&size| size > 0).ok_or("Invalid layout: grids don't fit")?;
+    let shape = Shape::new(size).map_err(|e| format!("Invalid layout: {}", e))?;
+
+    fill_cells(Sudoku::with_grids(shape, origins.to_vec())?, lines)
+}
+
+/// Fills the cells of an empty grid, one row per line. Digits above 9 can be written as numbers
+/// or as the letters A-G, and `.` marks an empty cell.
+fn fill_cells(mut sudoku: Sudoku, lines: &[&str]) -> Result<Sudoku, Box<dyn Error>> {
+    if lines.len() != sudoku.height() {
+        return Err(format!("Invalid sudoku format: expected {} rows, got {}", sudoku.height(), lines.len()).into());
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        for (j, num_str) in split_labels(line, i, "sudoku", sudoku.width())?.into_iter().enumerate() {
+            let num = match num_str.as_bytes() {
+                [b'-'] if !sudoku.is_active((i, j)) => continue,
+                [b'.'] => Some(0),
+                [c @ (b'A'..=b'Z' | b'a'..=b'z')] => Some(u32::from(c.to_ascii_uppercase() - b'A') + 10),
+                _ => num_str.parse::<u32>().ok(),
+            };
+
+            match num {
+                Some(num) if num as usize <= sudoku.size() && sudoku.is_active((i, j)) => sudoku[(i, j)] = num,
+                _ => {
+                    return Err(format!("Invalid number '{}' at row {}, col {}", num_str, i + 1, j + 1).into());
+                }
+            }
+        }
+    }
+
+    Ok(sudoku)
+}
+
+/// Reads the top-left cell of every grid as `<row> <col>`, or one of the presets
+/// `samurai`, `twin` and `butterfly` for overlapping 9x9 grids.
+fn parse_layout(lines: &[&str]) -> Result<Vec<Pos>, Box<dyn Error>> {
+    match lines {
+        ["samurai"] => return Ok(vec![(0, 0), (0, 12), (6, 6), (12, 0), (12, 12)]),
+        ["twin"] => return Ok(vec![(0, 0), (6, 6)]),
+        ["butterfly"] => return Ok(vec![(0, 0), (0, 3), (3, 0), (3, 3)]),
+        _ => {}
+    }
+
+    lines
+        .iter()
+        .map(|line| match split_row(line).iter().map(|n| n.parse::<usize>()).collect::<Vec<_>>()[..] {
+            [Ok(row), Ok(col)] if row >= 1 && col >= 1 => Ok((row - 1, col - 1)),
+            _ => Err(format!("Invalid grid origin: {}", line).into()),
+        })
+        .collect()
+}
+
+fn parse_rules(lines: &[&str]) -> Result<Ruleset, Box<dyn Error>> {
+    let mut ruleset = Ruleset::default();
+
+    for &line in lines {
+        match line {
+            "sum-sequence" => ruleset.sum_sequence = true,
+            "anti-knight" => ruleset.anti_knight = true,
+            "anti-king" => ruleset.anti_king = true,
+            "diagonal" => ruleset.diagonal = true,
+            _ => return Err(format!("Unknown rule: {}", line).into()),
+        }
+    }
+
+    Ok(ruleset)
+}
+
+/// Reads a row of region labels per grid row. Labels can be any token and are numbered in order of first appearance.
+fn parse_regions(lines: &[&str], size: usize) -> Result<Regions, Box<dyn Error>> {
+    if lines.len() != size {
+        return Err(format!("Invalid regions format: expected {} rows, got {}", size, lines.len()).into());
+    }
+
+    let mut labels: Vec<&str> = Vec::new();
+    let mut regions = vec![vec![0u8; size]; size];
+
+    for (i, line) in lines.iter().enumerate() {
+        for (j, token) in split_labels(line, i, "regions", size)?.into_iter().enumerate() {
+            let region = labels.iter().position(|&label| label == token).unwrap_or_else(|| {
+                labels.push(token);
+                labels.len() - 1
+            });
+
+            if region >= size {
+                return Err(format!(
+                    "Invalid regions format: more than {} labels at row {}, col {}",
+                    size,
+                    i + 1,
+                    j + 1
+                )
+                .into());
+            }
+
+            regions[i][j] = region as u8;
+        }
+    }
+
+    Ok(Regions::new(regions)?)
+}
+
+/// Reads a row of cell restrictions per grid row: `o` for odd, `e` for even, `h` for high (6-9),
+/// `l` for low (1-4) and `.` or `-` for unrestricted cells.
+fn parse_shades(lines: &[&str], sudoku: &Sudoku) -> Result<Vec<(Pos, Shade)>, Box<dyn Error>> {
+    if lines.len() != sudoku.height() {
+        return Err(format!("Invalid cells format: expected {} rows, got {}", sudoku.height(), lines.len()).into());
+    }
+
+    let mut shades = vec![];
+
+    for (i, line) in lines.iter().enumerate() {
+        for (j, token) in split_labels(line, i, "cells", sudoku.width())?.into_iter().enumerate() {
+            let shade = match token {
+                "." | "-" => continue,
+                "o" => Shade::Odd,
+                "e" => Shade::Even,
+                "h" => Shade::High,
+                "l" => Shade::Low,
+                _ => return Err(format!("Invalid cell restriction '{}' at row {}, col {}", token, i + 1, j + 1).into()),
+            };
+
+            shades.push(((i, j), shade));
+        }
+    }
+
+    Ok(shades)
+}
+
+/// Reads a clue as `<kind> <edge><index> [direction] <value>`, e.g. `x-sum B7 21`.
+/// Edges are `T`, `B`, `L` or `R`, and little killer directions are `NE`, `NW`, `SE` or `SW`.
+fn parse_clue(line: &str) -> Result<Clue, Box<dyn Error>> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let invalid = || format!("Invalid clue: {}", line);
+
+    let (kind, place, value) = match tokens[..] {
+        ["sandwich", place, value] => (ClueKind::Sandwich, place, value),
+        ["x-sum", place, value] => (ClueKind::XSum, place, value),
+        ["skyscraper", place, value] => (ClueKind::Skyscraper, place, value),
+        ["little-killer", place, direction, value] => {
+            let direction = match direction {
+                "NE" => Direction::NorthEast,
+                "NW" => Direction::NorthWest,
+                "SE" => Direction::SouthEast,
+                "SW" => Direction::SouthWest,
+                _ => return Err(invalid().into()),
+            };
+            (ClueKind::LittleKiller(direction), place, value)
+        }
+        _ => return Err(invalid().into()),
+    };
+
+    let edge = match place.get(..1) {
+        Some("T") => Edge::Top,
+        Some("B") => Edge::Bottom,
+        Some("L") => Edge::Left,
+        Some("R") => Edge::Right,
+        _ => return Err(invalid().into()),
+    };
+
+    let index = place[1..].parse::<usize>().ok().filter(|&index| index >= 1).ok_or_else(invalid)?;
+    let value: u8 = value.parse().map_err(|_| invalid())?;
+
+    Ok(Clue::new(kind, edge, index - 1, value)?)
+}
+
+/// Reads a cage of the sum-sequence layout as its cells, e.g. `r3c3 r4c3 r4c2`.
+fn parse_cage(line: &str) -> Result<Vec<Pos>, Box<dyn Error>> {
+    let cell = |token: &str| {
+        let (row, col) = token.strip_prefix('r')?.split_once('c')?;
+        Some((row.parse::<usize>().ok()?.checked_sub(1)?, col.parse::<usize>().ok()?.checked_sub(1)?))
+    };
+
+    split_row(line)
+        .into_iter()
+        .map(|token| cell(token).ok_or_else(|| format!("Invalid cage cell: {}", token).into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules_section() {
+        let content = "0 0 0 0 0 0 0 0 0\n".repeat(9) + "\n[rules]\nanti-king\ndiagonal\n";
+
+        let puzzle = parse_puzzle(&content).unwrap();
+
+        assert!(puzzle.ruleset.anti_king);
+        assert!(puzzle.ruleset.diagonal);
+        assert!(!puzzle.ruleset.anti_knight);
+        assert!(!puzzle.ruleset.sum_sequence);
+        assert!(puzzle.ruleset.regions.is_none());
+    }
+
+    #[test]
+    fn test_parse_grid_sizes() {
+        let puzzle = parse_puzzle("1...\n..2.\n.3..\n...4\n").unwrap();
+
+        assert_eq!(puzzle.sudoku.shape(), Shape { size: 4, box_height: 2, box_width: 2 });
+        assert_eq!(puzzle.to_string(), " _______\n|1  |   |\n|___|2__|\n|  3|   |\n|___|__4|\n");
+
+        let content = "G 0 0 0 0 0 0 0 0 0 0 0 0 0 0 16\n".repeat(16);
+        let puzzle = parse_puzzle(&content).unwrap();
+
+        assert_eq!(puzzle.sudoku.shape(), Shape { size: 16, box_height: 4, box_width: 4 });
+        assert_eq!(puzzle.sudoku[(0, 0)], 16);
+        assert_eq!(puzzle.sudoku[(0, 15)], 16);
+
+        assert!(parse_puzzle(&"0 0 0 0 0 0 0 0 0\n".repeat(8)).is_err());
+        assert!(parse_puzzle("1...\n..2.\n.5..\n...4\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_layout_section() {
+        let canvas = [".........------", "...9.....------"]
+            .into_iter()
+            .chain([".........------"; 4])
+            .chain(["..............."; 3])
+            .chain(["------........."; 6])
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let puzzle = parse_puzzle(&(canvas.clone() + "\n[layout]\ntwin\n")).unwrap();
+
+        assert_eq!(puzzle.sudoku.grids(), [(0, 0), (6, 6)]);
+        assert_eq!((puzzle.sudoku.height(), puzzle.sudoku.width()), (15, 15));
+        assert_eq!(puzzle.sudoku[(1, 3)], 9);
+        assert!(!puzzle.sudoku.is_active((0, 9)));
+
+        let puzzle = parse_puzzle(&(canvas.clone() + "\n[layout]\n1 1\n7 7\n")).unwrap();
+        assert_eq!(puzzle.sudoku.grids(), [(0, 0), (6, 6)]);
+
+        assert!(parse_puzzle(&(canvas.replacen('-', "1", 1) + "\n[layout]\ntwin\n")).is_err());
+    }
+
+    #[test]
+    fn test_parse_regions_section() {
+        let regions = "\
+            AAABBBCCC\n\
+            AAABBBCCC\n\
+            AAABBBCCC\n\
+            DDDEEEFFF\n\
+            DDDEEEFFF\n\
+            DDDEEEFFF\n\
+            GGGHHHIII\n\
+            GGGHHHIII\n\
+            GGGHHHIII\n";
+
+        let content = "0 0 0 0 0 0 0 0 0\n".repeat(9) + "[regions]\n" + regions;
+        let puzzle = parse_puzzle(&content).unwrap();
+
+        assert_eq!(puzzle.ruleset.regions, Some(Regions::default()));
+
+        let content = "0 0 0 0 0 0 0 0 0\n".repeat(9) + "[regions]\n" + &regions.replacen('B', "A", 1);
+        assert!(parse_puzzle(&content).is_err());
+    }
+
+    #[test]
+    fn test_parse_clues_section() {
+        let content = "0 0 0 0 0 0 0 0 0\n".repeat(9) + "[clues]\nsandwich T3 15\nlittle-killer L1 NE 20\n";
+        let puzzle = parse_puzzle(&content).unwrap();
+
+        let clues = [
+            Clue { kind: ClueKind::Sandwich, edge: Edge::Top, index: 2, value: 15 },
+            Clue { kind: ClueKind::LittleKiller(Direction::NorthEast), edge: Edge::Left, index: 0, value: 20 },
+        ];
+        assert_eq!(puzzle.ruleset.clues, clues);
+
+        assert!(parse_clue("skyscraper T0 3").is_err());
+        assert!(parse_clue("little-killer T4 NW 12").is_err());
+    }
+
+    #[test]
+    fn test_parse_cells_section() {
+        let content =
+            "0 0 0 0 0 0 0 0 0\n".repeat(9) + "[cells]\no.......e\n" + &".........\n".repeat(7) + "h . . . . . . . l\n";
+        let puzzle = parse_puzzle(&content).unwrap();
+
+        let shades = [((0, 0), Shade::Odd), ((0, 8), Shade::Even), ((8, 0), Shade::High), ((8, 8), Shade::Low)];
+        assert_eq!(puzzle.ruleset.shades, shades);
+        assert!(puzzle.to_string().starts_with(" _________________\n|o    |     |    e|"));
+    }
+
+    #[test]
+    fn test_parse_cages_section() {
+        let grid = "0 0 0 0 0 0 0 0 0\n".repeat(9);
+        let puzzle = parse_puzzle(&(grid.clone() + "[rules]\nsum-sequence\n[cages]\nr3c3 r4c3 r4c2\n")).unwrap();
+        assert_eq!(puzzle.ruleset.cages, [vec![(2, 2), (3, 2), (3, 1)]]);
+
+        // Cages need the sum-sequence rules, which `-s` may turn on after parsing.
+        let puzzle = parse_puzzle(&(grid.clone() + "[cages]\nr1c1 r1c2 r2c1\n")).unwrap();
+        assert!(puzzle.ruleset.validate(&puzzle.sudoku).is_err());
+
+        let flags = Ruleset { sum_sequence: true, ..Default::default() };
+        assert_eq!(flags.merge(&puzzle.ruleset).validate(&puzzle.sudoku), Ok(()));
+        assert!(parse_cage("r3c3 r0c1").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+use super::{Cnf, Lit};
+use std::collections::BinaryHeap;
+
+/// The index of a literal in the watch lists: `2 * (var - 1)`, plus one when negated.
+fn code(lit: Lit) -> usize {
+    2 * (lit.unsigned_abs() as usize - 1) + (lit < 0) as usize
+}
+
+fn var(lit: Lit) -> usize {
+    lit.unsigned_abs() as usize
+}
+
+/// The conflicts between restarts are this many times a term of the Luby sequence.
+const RESTART_BASE: u64 = 100;
+
+/// The conflicts before the first reduction of the learnt clauses, and how many more before each next one.
+const REDUCE_BASE: u64 = 2000;
+const REDUCE_STEP: u64 = 300;
+
+/// A small conflict-driven clause learning SAT solver: two watched literals, first-UIP learning,
+/// VSIDS branching with saved phases, and Luby restarts. Half of the learnt clauses are dropped now and then,
+/// keeping those whose literals span the fewest decision levels.
+pub struct Cdcl {
+    /// Every clause by index, left empty once deleted.
+    clauses: Vec<Vec<Lit>>,
+    /// The learnt clauses still kept, with the number of decision levels they spanned when learnt.
+    learnt: Vec<(usize, usize)>,
+    /// The conflict count of the next reduction of the learnt clauses.
+    reduction: u64,
+    /// The clauses watching each literal, by `code`, to be visited when it becomes false.
+    watches: Vec<Vec<usize>>,
+    /// The value of each variable, by index from 1.
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    /// The length of the trail where each decision level starts.
+    limits: Vec<usize>,
+    /// The first literal of the trail whose consequences aren't propagated yet.
+    head: usize,
+    activity: Vec<f64>,
+    increment: f64,
+    /// Unassigned variables by activity, with stale entries skipped when popped.
+    order: BinaryHeap<(u64, usize)>,
+    phases: Vec<bool>,
+    seen: Vec<bool>,
+    conflicts: u64,
+    /// Whether a clause was found false without any decision, making the formula unsatisfiable for good.
+    failed: bool,
+}
+
+impl Cdcl {
+    pub fn new(cnf: &Cnf) -> Self {
+        let vars = cnf.vars() as usize;
+        let mut solver = Cdcl {
+            clauses: vec![],
+            learnt: vec![],
+            reduction: REDUCE_BASE,
+            watches: vec![vec![]; 2 * vars],
+            values: vec![None; vars + 1],
+            levels: vec![0; vars + 1],
+            reasons: vec![None; vars + 1],
+            trail: vec![],
+            limits: vec![],
+            head: 0,
+            activity: vec![0.0; vars + 1],
+            increment: 1.0,
+            order: (1..=vars).map(|var| (0, var)).collect(),
+            phases: vec![false; vars + 1],
+            seen: vec![false; vars + 1],
+            conflicts: 0,
+            failed: false,
+        };
+
+        for clause in cnf.clauses() {
+            solver.add_clause(clause);
+        }
+
+        solver
+    }
+
+    /// The number of conflicts met by every search so far.
+    pub fn conflicts(&self) -> u64 {
+        self.conflicts
+    }
+
+    /// Adds a clause between searches, such as one ruling out the last model.
+    pub fn add_clause(&mut self, clause: &[Lit]) {
+        self.backtrack(0);
+
+        let mut lits: Vec<Lit> = vec![];
+        for &lit in clause {
+            match self.value(lit) {
+                Some(true) => return,
+                Some(false) => {}
+                None if lits.contains(&-lit) => return,
+                None if !lits.contains(&lit) => lits.push(lit),
+                None => {}
+            }
+        }
+
+        match lits.len() {
+            0 => self.failed = true,
+            1 => {
+                self.assign(lits[0], None);
+                self.failed |= self.propagate().is_some();
+            }
+            _ => {
+                self.attach(lits);
+            }
+        }
+    }
+
+    /// Searches for a model, returned as the value of every variable from index 1 on.
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        if self.failed {
+            return None;
+        }
+
+        let mut restarts = 0;
+        let mut budget = RESTART_BASE * luby(restarts);
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                self.conflicts += 1;
+
+                if self.limits.is_empty() {
+                    self.failed = true;
+                    return None;
+                }
+
+                let (learnt, level) = self.analyze(conflict);
+                self.backtrack(level);
+
+                match learnt.len() {
+                    1 => self.assign(learnt[0], None),
+                    _ => {
+                        let asserting = learnt[0];
+                        let mut levels: Vec<usize> = learnt.iter().map(|&lit| self.levels[var(lit)]).collect();
+                        levels.sort_unstable();
+                        levels.dedup();
+
+                        let clause = self.attach(learnt);
+                        self.learnt.push((clause, levels.len()));
+                        self.assign(asserting, Some(clause));
+                    }
+                }
+
+                if self.conflicts >= self.reduction {
+                    self.reduce();
+                    self.reduction = self.conflicts + REDUCE_BASE + REDUCE_STEP * (self.reduction / REDUCE_BASE);
+                }
+
+                self.decay();
+                budget = budget.saturating_sub(1);
+                continue;
+            }
+
+            if budget == 0 {
+                restarts += 1;
+                budget = RESTART_BASE * luby(restarts);
+                self.backtrack(0);
+                continue;
+            }
+
+            let Some(var) = self.pick() else {
+                let model = self.values.iter().map(|value| value.unwrap_or(false)).collect();
+                return Some(model);
+            };
+
+            self.limits.push(self.trail.len());
+            let lit = if self.phases[var] { var as Lit } else { -(var as Lit) };
+            self.assign(lit, None);
+        }
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.values[var(lit)].map(|value| value == (lit > 0))
+    }
+
+    /// Stores a clause of at least two literals, watching its first two.
+    fn attach(&mut self, lits: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[code(lits[0])].push(index);
+        self.watches[code(lits[1])].push(index);
+        self.clauses.push(lits);
+        index
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = var(lit);
+        self.values[var] = Some(lit > 0);
+        self.levels[var] = self.limits.len();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Assigns every literal forced by a clause, returning the clause found false if any.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.head < self.trail.len() {
+            let falsified = -self.trail[self.head];
+            self.head += 1;
+
+            let mut watching = std::mem::take(&mut self.watches[code(falsified)]);
+            let mut i = 0;
+
+            while i < watching.len() {
+                let index = watching[i];
+                let clause = &mut self.clauses[index];
+
+                if clause.is_empty() {
+                    watching.swap_remove(i);
+                    continue;
+                }
+
+                if clause[0] == falsified {
+                    clause.swap(0, 1);
+                }
+
+                let other = clause[0];
+                if self.values[var(other)] == Some(other > 0) {
+                    i += 1;
+                    continue;
+                }
+
+                let replacement = (2..clause.len()).find(|&k| self.values[var(clause[k])] != Some(clause[k] < 0));
+
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    self.watches[code(clause[1])].push(index);
+                    watching.swap_remove(i);
+                    continue;
+                }
+
+                if self.values[var(other)].is_some() {
+                    self.watches[code(falsified)] = watching;
+                    return Some(index);
+                }
+
+                self.assign(other, Some(index));
+                i += 1;
+            }
+
+            self.watches[code(falsified)] = watching;
+        }
+
+        None
+    }
+
+    /// Learns the first-UIP clause of a conflict, with its asserting literal first, and the level to go back to.
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let level = self.limits.len();
+        let mut learnt = vec![0];
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut index = self.trail.len();
+        let mut resolved: Option<Lit> = None;
+
+        loop {
+            for k in 0..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let var = var(lit);
+
+                if Some(lit) == resolved {
+                    continue;
+                }
+
+                if self.seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+
+                self.seen[var] = true;
+                self.bump(var);
+
+                if self.levels[var] == level {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+
+            // The latest literal of the current level taking part in the conflict.
+            let lit = loop {
+                index -= 1;
+                if self.seen[var(self.trail[index])] {
+                    break self.trail[index];
+                }
+            };
+
+            self.seen[var(lit)] = false;
+            pending -= 1;
+
+            if pending == 0 {
+                learnt[0] = -lit;
+                break;
+            }
+
+            resolved = Some(lit);
+            clause = self.reasons[var(lit)].expect("implied literal without a reason");
+        }
+
+        learnt[1..].iter().for_each(|&lit| self.seen[var(lit)] = false);
+
+        // Backjump to the highest level among the other literals, which becomes the second watch.
+        let back = (1..learnt.len()).max_by_key(|&i| self.levels[var(learnt[i])]);
+        let level = back.map_or(0, |i| {
+            learnt.swap(1, i);
+            self.levels[var(learnt[1])]
+        });
+
+        (learnt, level)
+    }
+
+    /// Deletes the half of the learnt clauses spanning the most levels, except those spanning two at most
+    /// and those implying a literal of the trail. Their watches are dropped as propagation meets them.
+    fn reduce(&mut self) {
+        self.learnt.sort_by_key(|&(_, levels)| levels);
+        let keep = self.learnt.len() / 2;
+
+        for (clause, levels) in self.learnt.split_off(keep) {
+            let first = self.clauses[clause][0];
+            let locked = self.value(first) == Some(true) && self.reasons[var(first)] == Some(clause);
+
+            if levels <= 2 || locked {
+                self.learnt.push((clause, levels));
+            } else {
+                self.clauses[clause] = vec![];
+            }
+        }
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.limits.len() <= level {
+            return;
+        }
+
+        for lit in self.trail.drain(self.limits[level]..) {
+            let var = var(lit);
+            self.values[var] = None;
+            self.reasons[var] = None;
+            self.phases[var] = lit > 0;
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+
+        self.limits.truncate(level);
+        self.head = self.trail.len();
+    }
+
+    /// The unassigned variable of highest activity.
+    fn pick(&mut self) -> Option<usize> {
+        while let Some((activity, var)) = self.order.pop() {
+            if self.values[var].is_none() && activity == self.activity[var].to_bits() {
+                return Some(var);
+            }
+        }
+
+        // Entries may all be stale after a rescale; fall back to a scan.
+        (1..self.values.len()).find(|&var| self.values[var].is_none())
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.increment;
+
+        if self.activity[var] > 1e100 {
+            self.activity.iter_mut().for_each(|activity| *activity *= 1e-100);
+            self.increment *= 1e-100;
+
+            let unassigned = (1..self.values.len()).filter(|&var| self.values[var].is_none());
+            self.order = unassigned.map(|var| (self.activity[var].to_bits(), var)).collect();
+        } else if self.values[var].is_none() {
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+    }
+
+    fn decay(&mut self) {
+        self.increment /= 0.95;
+    }
+}
+
+/// The Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, ..., from index 0.
+fn luby(index: u64) -> u64 {
+    let mut index = index + 1;
+
+    loop {
+        let power = 64 - index.leading_zeros();
+        if index == (1 << power) - 1 {
+            return 1 << (power - 1);
+        }
+        index -= (1 << (power - 1)) - 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luby() {
+        assert_eq!((0..15).map(luby).collect::<Vec<_>>(), [1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn test_pigeonhole() {
+        // Four pigeons in three holes.
+        let mut cnf = Cnf::new();
+        let holes: Vec<Vec<Lit>> = (0..4).map(|_| (0..3).map(|_| cnf.var()).collect()).collect();
+
+        holes.iter().for_each(|pigeon| cnf.clause(pigeon.iter().copied()));
+        for hole in 0..3 {
+            cnf.at_most_one(&holes.iter().map(|pigeon| pigeon[hole]).collect::<Vec<_>>());
+        }
+
+        assert_eq!(Cdcl::new(&cnf).solve(), None);
+
+        let mut cnf = Cnf::new();
+        let vars: Vec<Lit> = (0..5).map(|_| cnf.var()).collect();
+        cnf.exactly(2, &vars);
+        cnf.clause([vars[0]]);
+
+        let mut solver = Cdcl::new(&cnf);
+        let mut models = 0;
+
+        while let Some(model) = solver.solve() {
+            assert!(model[1]);
+            assert_eq!(vars.iter().filter(|&&var| model[var as usize]).count(), 2);
+            solver
+                .add_clause(&vars.iter().map(|&var| if model[var as usize] { -var } else { var }).collect::<Vec<_>>());
+            models += 1;
+        }
+
+        assert_eq!(models, 4);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt::Display;
+
+/// A literal: variable `v` as `v`, its negation as `-v`. Variables are numbered from 1, as in DIMACS.
+pub type Lit = i32;
+
+/// A formula in conjunctive normal form, built clause by clause.
+#[derive(Debug, Default, Clone)]
+pub struct Cnf {
+    vars: u32,
+    clauses: Vec<Vec<Lit>>,
+    /// Comment lines written at the top of the DIMACS output.
+    comments: Vec<String>,
+}
+
+impl Cnf {
+    pub fn new() -> Self {
+        Cnf::default()
+    }
+
+    /// A fresh variable, as its positive literal.
+    pub fn var(&mut self) -> Lit {
+        self.vars += 1;
+        self.vars as Lit
+    }
+
+    pub fn vars(&self) -> u32 {
+        self.vars
+    }
+
+    pub fn clauses(&self) -> &[Vec<Lit>] {
+        &self.clauses
+    }
+
+    pub fn comment(&mut self, comment: impl Into<String>) {
+        self.comments.push(comment.into());
+    }
+
+    pub fn clause(&mut self, lits: impl IntoIterator<Item = Lit>) {
+        self.clauses.push(lits.into_iter().collect());
+    }
+
+    /// No two of the literals hold, pairwise.
+    pub fn at_most_one(&mut self, lits: &[Lit]) {
+        for (i, &a) in lits.iter().enumerate() {
+            for &b in &lits[i + 1..] {
+                self.clause([-a, -b]);
+            }
+        }
+    }
+
+    pub fn exactly_one(&mut self, lits: &[Lit]) {
+        self.clause(lits.iter().copied());
+        self.at_most_one(lits);
+    }
+
+    /// Exactly `k` of the literals hold, with a sequential counter: one variable per literal and count up to `k + 1`,
+    /// true when at least that many of the literals so far hold.
+    pub fn exactly(&mut self, k: usize, lits: &[Lit]) {
+        if k > lits.len() {
+            self.clause([]);
+            return;
+        }
+
+        // The counts reached before the current literal, `None` standing for false. A count of 0 always holds.
+        let mut counts: Vec<Option<Lit>> = vec![None; k + 2];
+
+        for (i, &lit) in lits.iter().enumerate() {
+            let mut next = vec![None; k + 2];
+
+            for j in 1..=(k + 1).min(i + 1) {
+                let var = self.var();
+                let (same, fewer) = (counts[j], counts[j - 1]);
+
+                // At least j so far when there were j before, or j - 1 before and this literal holds.
+                if let Some(same) = same {
+                    self.clause([-same, var]);
+                }
+
+                match fewer {
+                    _ if j == 1 => self.clause([-lit, var]),
+                    Some(fewer) => self.clause([-fewer, -lit, var]),
+                    None => {}
+                }
+
+                // And only then.
+                if j > 1 {
+                    self.clause([-var].into_iter().chain(same).chain(fewer));
+                }
+                self.clause([-var].into_iter().chain(same).chain([lit]));
+
+                next[j] = Some(var);
+            }
+
+            counts = next;
+        }
+
+        match counts[k] {
+            Some(at_least) if k > 0 => self.clause([at_least]),
+            _ => {}
+        }
+
+        if let Some(too_many) = counts[k + 1] {
+            self.clause([-too_many]);
+        }
+    }
+}
+
+/// Writes the formula in DIMACS format.
+impl Display for Cnf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for comment in &self.comments {
+            writeln!(f, "c {}", comment)?;
+        }
+
+        writeln!(f, "p cnf {} {}", self.vars, self.clauses.len())?;
+
+        for clause in &self.clauses {
+            for lit in clause {
+                write!(f, "{} ", lit)?;
+            }
+            writeln!(f, "0")?;
+        }
+
+        Ok(())
+    }
+}
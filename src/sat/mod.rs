@@ -0,0 +1,312 @@
+#[cfg(feature = "cdcl")]
+mod cdcl;
+mod cnf;
+
+#[cfg(feature = "cdcl")]
+pub use cdcl::Cdcl;
+pub use cnf::{Cnf, Lit};
+
+use crate::engine_v2::rules::{FREQUENCY, PAIR_CAGES, PalindromeRule};
+use crate::engine_v2::{Pos, Rule, Ruleset, Sudoku, Value, distinct_sets};
+use std::collections::BTreeMap;
+
+/// A grid encoded as a CNF formula, with a variable for every digit of every cell.
+///
+/// Each cell holds exactly one of its candidates, and every set of distinct digits holds each digit at most once,
+/// or exactly once when it has a cell per digit. With the sum-sequence rules, palindromes mirror their digits,
+/// cages hold distinct digits, and their sums, counted by auxiliary variables, are all different.
+/// Once the cages outside the palindromes hold three cells per digit, the sums also form a sequence
+/// of consecutive numbers, and each digit appears exactly three times in those cells.
+pub struct Encoding {
+    cnf: Cnf,
+    sudoku: Sudoku,
+    /// The variable of digit 1 in each cell, by cell index, followed by those of the other digits.
+    first: Vec<Option<Lit>>,
+}
+
+impl Encoding {
+    pub fn new(sudoku: &Sudoku, ruleset: &Ruleset) -> Result<Self, String> {
+        if !ruleset.clues.is_empty() {
+            return Err("Outside clues have no CNF encoding".into());
+        }
+
+        let size = sudoku.size();
+        let mut cnf = Cnf::new();
+        let mut first = vec![None; sudoku.height() * sudoku.width()];
+
+        cnf.comment(format!("{}x{} grid, digit d of a cell is its first variable + d - 1", size, size));
+
+        let mut shaded = sudoku.clone();
+        for &(pos, shade) in &ruleset.shades {
+            shaded.restrict(pos, shade.mask(size));
+        }
+
+        for pos in sudoku.positions() {
+            let vars: Vec<Lit> = (0..size).map(|_| cnf.var()).collect();
+            let candidates = shaded.candidates(pos);
+
+            cnf.comment(format!("r{}c{}: {}", pos.0 + 1, pos.1 + 1, vars[0]));
+            cnf.exactly_one(&vars);
+
+            for digit in (1..=size as u8).filter(|&digit| !candidates.contains(digit)) {
+                cnf.clause([-vars[digit as usize - 1]]);
+            }
+
+            first[sudoku.cell_index(pos)] = Some(vars[0]);
+        }
+
+        let mut encoding = Encoding { cnf, sudoku: sudoku.clone(), first };
+
+        for set in distinct_sets(sudoku, ruleset)? {
+            encoding.distinct(&set, set.len() == size);
+        }
+
+        if ruleset.sum_sequence {
+            encoding.sum_sequence(&ruleset.cages);
+        }
+
+        Ok(encoding)
+    }
+
+    pub fn cnf(&self) -> &Cnf {
+        &self.cnf
+    }
+
+    /// The variable telling that the cell holds the digit.
+    pub fn literal(&self, pos: Pos, digit: u8) -> Lit {
+        self.first[self.sudoku.cell_index(pos)].expect("cell outside the grid") + digit as Lit - 1
+    }
+
+    /// The grid of a satisfying assignment, given as the value of every variable from index 1 on.
+    pub fn decode(&self, model: &[bool]) -> Sudoku {
+        let mut sudoku = self.sudoku.clone();
+
+        for pos in self.sudoku.positions() {
+            if let Some(digit) = (1..=sudoku.size() as u8).find(|&digit| model[self.literal(pos, digit) as usize]) {
+                sudoku[pos] = digit as Value;
+            }
+        }
+
+        sudoku
+    }
+
+    /// The clause ruling out a solution, for looking for another one.
+    pub fn block(&self, solution: &Sudoku) -> Vec<Lit> {
+        self.sudoku.positions().map(|pos| -self.literal(pos, solution[pos] as u8)).collect()
+    }
+
+    fn digits(&self, pos: Pos) -> Vec<Lit> {
+        (1..=self.sudoku.size() as u8).map(|digit| self.literal(pos, digit)).collect()
+    }
+
+    /// Every digit at most once in the cells, or exactly once when `full`.
+    fn distinct(&mut self, cells: &[Pos], full: bool) {
+        for digit in 1..=self.sudoku.size() as u8 {
+            let vars: Vec<Lit> = cells.iter().map(|&pos| self.literal(pos, digit)).collect();
+
+            match full {
+                true => self.cnf.exactly_one(&vars),
+                false => self.cnf.at_most_one(&vars),
+            }
+        }
+    }
+
+    fn sum_sequence(&mut self, extra: &[Vec<Pos>]) {
+        let palindromes = PalindromeRule::default().units(&self.sudoku);
+
+        for palindrome in &palindromes {
+            for (&a, &b) in palindrome.iter().zip(palindrome.iter().rev()).take(palindrome.len() / 2) {
+                for (a, b) in self.digits(a).into_iter().zip(self.digits(b)) {
+                    self.cnf.clause([-a, b]);
+                    self.cnf.clause([a, -b]);
+                }
+            }
+        }
+
+        let cages: Vec<Vec<Pos>> = PAIR_CAGES.iter().map(|cage| cage.to_vec()).chain(extra.iter().cloned()).collect();
+        let mut sums = vec![];
+
+        for cage in &cages {
+            self.distinct(cage, false);
+            sums.push(self.sum(cage));
+        }
+
+        // Different sums: no value is the sum of two cages.
+        let mut by_value: BTreeMap<usize, Vec<Lit>> = BTreeMap::new();
+        for sum in &sums {
+            sum.iter().for_each(|(&value, &var)| by_value.entry(value).or_default().push(var));
+        }
+
+        by_value.values().for_each(|vars| self.cnf.at_most_one(vars));
+
+        let mut counted: Vec<Pos> = cages.concat();
+        counted.sort_unstable();
+        counted.dedup();
+        counted.retain(|pos| !palindromes.iter().flatten().any(|cell| cell == pos));
+
+        if counted.len() != FREQUENCY * self.sudoku.size() {
+            return;
+        }
+
+        for digit in 1..=self.sudoku.size() as u8 {
+            let vars: Vec<Lit> = counted.iter().map(|&pos| self.literal(pos, digit)).collect();
+            self.cnf.exactly(FREQUENCY, &vars);
+        }
+
+        // Consecutive sums: a first sum from which every value is taken by a cage, which the different sums
+        // of as many cages as values make a one to one match.
+        let (lowest, highest) = (*by_value.keys().next().unwrap(), *by_value.keys().last().unwrap());
+        let starts: Vec<(usize, Lit)> =
+            (lowest..=(highest + 1).saturating_sub(cages.len())).map(|start| (start, self.cnf.var())).collect();
+
+        self.cnf.exactly_one(&starts.iter().map(|&(_, var)| var).collect::<Vec<_>>());
+
+        for (start, var) in starts {
+            for value in start..start + cages.len() {
+                let taken = by_value.get(&value).into_iter().flatten().copied();
+                self.cnf.clause(std::iter::once(-var).chain(taken));
+            }
+        }
+    }
+
+    /// A variable for every possible sum of the cage, exactly one of them holding: the running sum of its first
+    /// cells, from one cell to the next.
+    fn sum(&mut self, cage: &[Pos]) -> BTreeMap<usize, Lit> {
+        let mut sums: BTreeMap<usize, Lit> = BTreeMap::new();
+        sums.extend(self.digits(cage[0]).into_iter().enumerate().map(|(i, var)| (i + 1, var)));
+
+        for &pos in &cage[1..] {
+            let mut next: BTreeMap<usize, Lit> = BTreeMap::new();
+
+            for (&sum, &before) in &sums {
+                for (i, digit) in self.digits(pos).into_iter().enumerate() {
+                    let var = *next.entry(sum + i + 1).or_insert_with(|| self.cnf.var());
+                    self.cnf.clause([-before, -digit, var]);
+                }
+            }
+
+            self.cnf.at_most_one(&next.values().copied().collect::<Vec<_>>());
+            sums = next;
+        }
+
+        sums
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_v2::{Clue, ClueKind, Edge};
+
+    fn solution() -> Sudoku {
+        Sudoku::from([
+            [9, 6, 5, 8, 4, 2, 1, 7, 3],
+            [4, 1, 2, 7, 3, 5, 9, 8, 6],
+            [8, 3, 7, 9, 1, 6, 5, 4, 2],
+            [3, 7, 9, 1, 2, 8, 6, 5, 4],
+            [2, 5, 4, 6, 9, 7, 3, 1, 8],
+            [1, 8, 6, 4, 5, 3, 2, 9, 7],
+            [7, 2, 3, 5, 8, 1, 4, 6, 9],
+            [5, 9, 8, 2, 6, 4, 7, 3, 1],
+            [6, 4, 1, 3, 7, 9, 8, 2, 5],
+        ])
+    }
+
+    /// The assignment of the cell variables of a finished grid.
+    fn model(encoding: &Encoding, sudoku: &Sudoku) -> Vec<bool> {
+        let mut model = vec![false; encoding.cnf().vars() as usize + 1];
+        sudoku.positions().for_each(|pos| model[encoding.literal(pos, sudoku[pos] as u8) as usize] = true);
+        model
+    }
+
+    fn satisfied(cnf: &Cnf, model: &[bool]) -> bool {
+        cnf.clauses().iter().all(|clause| clause.iter().any(|&lit| model[lit.unsigned_abs() as usize] == (lit > 0)))
+    }
+
+    #[test]
+    fn test_encode_standard_rules() {
+        let mut puzzle = solution();
+        puzzle[(0, 0)] = 0;
+        puzzle[(4, 4)] = 0;
+
+        let encoding = Encoding::new(&puzzle, &Ruleset::default()).unwrap();
+        assert_eq!(encoding.cnf().vars(), 729);
+
+        let model = model(&encoding, &solution());
+        assert!(satisfied(encoding.cnf(), &model));
+        assert_eq!(encoding.decode(&model), solution());
+
+        let mut swapped = solution();
+        swapped[(0, 0)] = 6;
+        swapped[(0, 1)] = 9;
+        assert!(!satisfied(encoding.cnf(), &self::model(&encoding, &swapped)));
+
+        let dimacs = encoding.cnf().to_string();
+        assert!(dimacs.starts_with("c 9x9 grid"));
+        assert!(dimacs.contains(&format!("p cnf 729 {}\n", encoding.cnf().clauses().len())));
+    }
+
+    #[test]
+    fn test_exactly() {
+        // Every assignment of four literals, with the counter variables set as the encoding defines them.
+        for bits in 0..16u32 {
+            let mut cnf = Cnf::new();
+            let lits: Vec<Lit> = (0..4).map(|_| cnf.var()).collect();
+            cnf.exactly(2, &lits);
+
+            let mut model = vec![false; cnf.vars() as usize + 1];
+            (0..4).for_each(|i| model[i + 1] = bits & (1 << i) != 0);
+
+            let consistent = (0..1 << (cnf.vars() - 4)).any(|aux: u32| {
+                (0..cnf.vars() - 4).for_each(|i| model[5 + i as usize] = aux & (1 << i) != 0);
+                satisfied(&cnf, &model)
+            });
+
+            assert_eq!(consistent, bits.count_ones() == 2, "{:04b}", bits);
+        }
+    }
+
+    #[test]
+    fn test_clues_are_rejected() {
+        let clue = Clue::new(ClueKind::XSum, Edge::Top, 0, 10).unwrap();
+        let ruleset = Ruleset { clues: vec![clue], ..Default::default() };
+        assert!(Encoding::new(&solution(), &ruleset).is_err());
+    }
+
+    #[cfg(feature = "cdcl")]
+    #[test]
+    fn test_solve_and_block() {
+        // The grid of the crate example, with two solutions.
+        let mut puzzle = solution();
+        for pos in [(0, 2), (0, 5), (1, 2), (1, 5)] {
+            puzzle[pos] = 0;
+        }
+
+        let encoding = Encoding::new(&puzzle, &Ruleset::default()).unwrap();
+        let mut solver = Cdcl::new(encoding.cnf());
+        let mut solutions = vec![];
+
+        while let Some(model) = solver.solve() {
+            let sudoku = encoding.decode(&model);
+            solver.add_clause(&encoding.block(&sudoku));
+            solutions.push(sudoku);
+        }
+
+        let expected: Vec<Sudoku> =
+            crate::engine_v2::Solver::from_ruleset(&puzzle, &Ruleset::default()).solutions(puzzle.clone()).collect();
+        assert_eq!(solutions.len(), expected.len());
+        assert!(solutions.iter().all(|solution| expected.contains(solution)));
+    }
+
+    #[cfg(feature = "cdcl")]
+    #[test]
+    fn test_sum_sequence() {
+        let ruleset = Ruleset { sum_sequence: true, ..Default::default() };
+        let encoding = Encoding::new(&Sudoku::default(), &ruleset).unwrap();
+        let solution = encoding.decode(&Cdcl::new(encoding.cnf()).solve().unwrap());
+
+        let solver = crate::engine_v2::Solver::from_ruleset(&solution, &ruleset);
+        assert!(solver.check(&solution).is_ok());
+        assert!(solution.positions().all(|pos| solution[pos] != 0));
+    }
+}